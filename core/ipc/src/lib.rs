@@ -16,13 +16,17 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::path::PathBuf;
+use std::{
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
 
 use flume::{unbounded, Receiver, Sender};
 use hearth_schema::protocol::CapOperation;
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
-    net::UnixStream,
+    net::{TcpStream, ToSocketAddrs, UnixStream},
+    task::JoinHandle,
 };
 
 /// Returns the path of the Hearth IPC socket.
@@ -59,12 +63,98 @@ pub fn get_socket_path() -> Option<PathBuf> {
     None
 }
 
+/// The maximum length in bytes of an authentication token.
+pub const MAX_TOKEN_LEN: u32 = 4096;
+
+/// Returns the shared token used to authenticate IPC connections.
+///
+/// If the HEARTH_TOKEN environment variable is set, then its value is used.
+/// Otherwise, if HEARTH_TOKEN_FILE is set, the contents of that file are used
+/// with trailing whitespace removed. If neither is set, this returns
+/// `Ok(None)` and connections are not authenticated.
+pub fn get_auth_token() -> std::io::Result<Option<Vec<u8>>> {
+    let token = if let Ok(token) = std::env::var("HEARTH_TOKEN") {
+        token.into_bytes()
+    } else if let Ok(path) = std::env::var("HEARTH_TOKEN_FILE") {
+        let contents = std::fs::read_to_string(path)?;
+        contents.trim_end().as_bytes().to_vec()
+    } else {
+        return Ok(None);
+    };
+
+    if token.is_empty() || token.len() > MAX_TOKEN_LEN as usize {
+        let msg = format!("Auth token must be 1 to {} bytes long", MAX_TOKEN_LEN);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+
+    Ok(Some(token))
+}
+
+/// Performs the client side of the authentication handshake.
+///
+/// The client sends its token prefixed by its length, then the server replies
+/// with a single byte that is 1 if the token was accepted. Returns an error of
+/// kind [ErrorKind::PermissionDenied] if the token was rejected.
+pub async fn client_handshake(
+    rx: &mut (impl AsyncRead + Unpin),
+    tx: &mut (impl AsyncWrite + Unpin),
+    token: &[u8],
+) -> std::io::Result<()> {
+    tx.write_u32_le(token.len() as u32).await?;
+    tx.write_all(token).await?;
+
+    match rx.read_u8().await? {
+        1 => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "Daemon rejected the auth token",
+        )),
+    }
+}
+
+/// Performs the server side of the authentication handshake. Returns whether
+/// the client presented the expected token.
+///
+/// See [client_handshake] for the format of the handshake.
+pub async fn server_handshake(
+    rx: &mut (impl AsyncRead + Unpin),
+    tx: &mut (impl AsyncWrite + Unpin),
+    token: &[u8],
+) -> std::io::Result<bool> {
+    let len = rx.read_u32_le().await?;
+    if len > MAX_TOKEN_LEN {
+        tx.write_u8(0).await?;
+        return Ok(false);
+    }
+
+    let mut received = vec![0; len as usize];
+    rx.read_exact(&mut received).await?;
+
+    // compare every byte so that timing doesn't reveal the matching prefix
+    let accepted = received.len() == token.len()
+        && received
+            .iter()
+            .zip(token.iter())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0;
+
+    tx.write_u8(accepted as u8).await?;
+    Ok(accepted)
+}
+
 pub struct Connection {
     /// An outgoing channel for capability operations.
     pub op_tx: Sender<CapOperation>,
 
     /// A channel for incoming capability operations.
     pub op_rx: Receiver<CapOperation>,
+
+    /// The task writing outgoing operations to the transport.
+    ///
+    /// Finishes once every sender of [Self::op_tx] has been dropped and all
+    /// queued operations have been written, after which the transport is
+    /// shut down.
+    pub writer: JoinHandle<()>,
 }
 
 impl Connection {
@@ -76,13 +166,15 @@ impl Connection {
         let (outgoing_tx, outgoing_rx) = unbounded();
         let (incoming_tx, incoming_rx) = unbounded();
 
-        tokio::spawn(async move {
+        let writer = tokio::spawn(async move {
             while let Ok(op) = outgoing_rx.recv_async().await {
                 let payload = bincode::serialize(&op).unwrap();
                 let len = payload.len() as u32;
                 tx.write_u32_le(len).await.unwrap();
                 tx.write_all(&payload).await.unwrap();
             }
+
+            let _ = tx.shutdown().await;
         });
 
         #[allow(clippy::read_zero_byte_vec)]
@@ -102,14 +194,15 @@ impl Connection {
         Self {
             op_tx: outgoing_tx,
             op_rx: incoming_rx,
+            writer,
         }
     }
 }
 
 /// Connects to the Hearth daemon and returns a [Connection].
+///
+/// Authenticates with the token from [get_auth_token], if there is one.
 pub async fn connect() -> std::io::Result<Connection> {
-    use std::io::{Error, ErrorKind};
-
     let sock_path = match get_socket_path() {
         Some(p) => p,
         None => {
@@ -121,6 +214,26 @@ pub async fn connect() -> std::io::Result<Connection> {
     };
 
     let stream = UnixStream::connect(&sock_path).await?;
-    let (rx, tx) = stream.into_split();
+    let (mut rx, mut tx) = stream.into_split();
+
+    if let Some(token) = get_auth_token()? {
+        client_handshake(&mut rx, &mut tx, &token).await?;
+    }
+
+    Ok(Connection::new(rx, tx))
+}
+
+/// Connects to a Hearth daemon listening on TCP and returns a [Connection].
+///
+/// Authenticates with the token from [get_auth_token], which the daemon
+/// requires for TCP connections.
+pub async fn connect_tcp(addr: impl ToSocketAddrs) -> std::io::Result<Connection> {
+    let stream = TcpStream::connect(addr).await?;
+    let (mut rx, mut tx) = stream.into_split();
+
+    if let Some(token) = get_auth_token()? {
+        client_handshake(&mut rx, &mut tx, &token).await?;
+    }
+
     Ok(Connection::new(rx, tx))
 }
@@ -19,8 +19,8 @@
 use proc_macro2::{Literal, Span, TokenStream};
 use quote::quote;
 use syn::{
-    parse_macro_input, AttributeArgs, DeriveInput, FnArg, Ident, ImplItem, ImplItemMethod, Meta,
-    MetaNameValue, NestedMeta, Pat, PatIdent, Type,
+    parse_macro_input, AttributeArgs, DeriveInput, FnArg, Ident, ImplItem, ImplItemMethod, Lit,
+    Meta, MetaNameValue, NestedMeta, Pat, PatIdent, Type,
 };
 
 /// Helper macro to implement [GetProcessMetadata] using doc comments and Cargo environment variables.
@@ -29,16 +29,65 @@ use syn::{
 ///
 /// The `name` field is initialized with the type's name.
 ///
+/// Either can be overridden with a `#[process(...)]` attribute, for example to
+/// give a service a stable public name that's decoupled from its type:
+///
+/// ```ignore
+/// #[derive(GetProcessMetadata)]
+/// #[process(name = "hearth.Renderer", description = "The renderer service.")]
+/// pub struct RendererService { /* ... */ }
+/// ```
+///
 /// The following [ProcessMetadata] fields are initialized with corresponding `CARGO_PKG_*` environment variables:
 /// - `authors`: `CARGO_PKG_AUTHORS`
 /// - `repository`: `CARGO_PKG_REPOSITORY`
 /// - `homepage`: `CARGO_PKG_HOMEPAGE`
 /// - `license`: `CARGO_PKG_LICENSE`
-#[proc_macro_derive(GetProcessMetadata)]
+#[proc_macro_derive(GetProcessMetadata, attributes(process))]
 pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let DeriveInput { ident, attrs, .. } = parse_macro_input!(input as DeriveInput);
 
-    let name = ident.to_string();
+    let mut name = ident.to_string();
+    let mut description = None;
+
+    for attr in attrs.iter() {
+        if !attr.path.is_ident("process") {
+            continue;
+        }
+
+        let meta = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list,
+            Ok(other) => {
+                return syn::Error::new_spanned(other, "expected `#[process(...)]`")
+                    .to_compile_error()
+                    .into();
+            }
+            Err(err) => return err.to_compile_error().into(),
+        };
+
+        for nested in meta.nested.iter() {
+            let NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                path,
+                lit: Lit::Str(value),
+                ..
+            })) = nested
+            else {
+                return syn::Error::new_spanned(nested, "expected `key = \"value\"`")
+                    .to_compile_error()
+                    .into();
+            };
+
+            if path.is_ident("name") {
+                name = value.value();
+            } else if path.is_ident("description") {
+                description = Some(value.value());
+            } else {
+                return syn::Error::new_spanned(path, "expected `name` or `description`")
+                    .to_compile_error()
+                    .into();
+            }
+        }
+    }
 
     let mut docs = String::new();
 
@@ -63,7 +112,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         docs.push('\n');
     }
 
-    let docs = docs.trim();
+    let docs = description.unwrap_or_else(|| docs.trim().to_string());
 
     quote! {
         impl ::hearth_runtime::utils::GetProcessMetadata for #ident {
@@ -88,6 +137,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                 meta.repository = some_or_empty(env!("CARGO_PKG_REPOSITORY"));
                 meta.homepage = some_or_empty(env!("CARGO_PKG_HOMEPAGE"));
                 meta.license = some_or_empty(env!("CARGO_PKG_LICENSE"));
+                meta.version = some_or_empty(env!("CARGO_PKG_VERSION"));
 
                 meta
             }
@@ -96,6 +146,108 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     .into()
 }
 
+/// Derives `hearth_schema::versioned::HearthProtocol` for a protocol enum.
+///
+/// The protocol's version is set with a required `#[protocol(version = N)]`
+/// attribute. Variants are assigned stable discriminants in declaration
+/// order, so new variants must only be added at the end of the enum.
+///
+/// ```ignore
+/// #[derive(Deserialize, Serialize, HearthProtocol)]
+/// #[protocol(version = 2)]
+/// pub enum CanvasUpdate { /* ... */ }
+/// ```
+#[proc_macro_derive(HearthProtocol, attributes(protocol))]
+pub fn derive_hearth_protocol(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match hearth_protocol_impl(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn hearth_protocol_impl(input: DeriveInput) -> syn::Result<TokenStream> {
+    let DeriveInput {
+        ident,
+        attrs,
+        generics,
+        data,
+        ..
+    } = input;
+
+    let syn::Data::Enum(data) = data else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "HearthProtocol can only be derived for enums",
+        ));
+    };
+
+    let mut version = None;
+    for attr in attrs.iter() {
+        if !attr.path.is_ident("protocol") {
+            continue;
+        }
+
+        let Meta::List(list) = attr.parse_meta()? else {
+            return Err(syn::Error::new_spanned(attr, "expected `#[protocol(...)]`"));
+        };
+
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    path,
+                    lit: Lit::Int(value),
+                    ..
+                })) if path.is_ident("version") => {
+                    version = Some(value.base10_parse::<u32>()?);
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        nested,
+                        "expected `version = <integer>`",
+                    ));
+                }
+            }
+        }
+    }
+
+    let Some(version) = version else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "missing `#[protocol(version = N)]` attribute",
+        ));
+    };
+
+    let names: Vec<_> = data
+        .variants
+        .iter()
+        .map(|variant| variant.ident.to_string())
+        .collect();
+
+    let arms = data.variants.iter().enumerate().map(|(index, variant)| {
+        let variant = &variant.ident;
+        let index = index as u32;
+        quote! { Self::#variant { .. } => #index, }
+    });
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::hearth_schema::versioned::HearthProtocol for #ident #ty_generics #where_clause {
+            const PROTOCOL_VERSION: u32 = #version;
+
+            const VARIANTS: &'static [&'static str] = &[#(#names),*];
+
+            fn discriminant(&self) -> u32 {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
+
 #[proc_macro_attribute]
 pub fn impl_wasm_linker(
     attr: proc_macro::TokenStream,
@@ -164,6 +316,22 @@ fn handle_fn_item(
     let impl_type = get_impl_type_ident(impl_type);
     let link_fn_ident = get_link_fn_ident(&fn_method);
 
+    // wasmtime only provides async function wrappers up to a fixed arity
+    let num_args = get_guest_arg_count(&fn_method);
+    if is_async(&fn_method) && num_args > MAX_ASYNC_ARGS {
+        let message = format!(
+            "ABI method `{}` takes {} arguments, but async ABI methods support at most {}; \
+             consider passing a pointer to a struct in guest memory instead",
+            get_fn_name(&fn_method),
+            num_args,
+            MAX_ASYNC_ARGS
+        );
+
+        let error = syn::Error::new_spanned(&fn_method.sig, message).to_compile_error();
+        link_wrapped_fns.push(error);
+        return;
+    }
+
     let linker_function = generate_linker_function(&link_fn_ident, &fn_method, &impl_type);
     let wasm_linker_fn = generate_add_to_linker_call(&link_fn_ident);
     link_wrapped_fns.push(linker_function);
@@ -190,22 +358,64 @@ fn generate_internal_function(fn_method: &ImplItemMethod, impl_type: &Ident) ->
     let internal_args = get_internal_args(fn_method);
     let internal_parameters = get_internal_parameters(fn_method);
     let return_type = fn_method.sig.output.clone();
+    let context = generate_error_context(fn_method, &impl_type);
     if is_async(fn_method) {
         quote! {
             async fn #fn_name <T: GetAbi<#impl_type> + Send>(#internal_args) #return_type {
                 let this = caller.data_mut().get_abi()?;
-                this.#fn_name(#internal_parameters).await
+                let result = this.#fn_name(#internal_parameters).await;
+                #context
             }
         }
     } else {
         quote! {
             fn #fn_name <T: GetAbi<#impl_type> + Send>(#internal_args) #return_type {
                 let this = caller.data_mut().get_abi()?;
-                this.#fn_name(#internal_parameters)
+                let result = this.#fn_name(#internal_parameters);
+                #context
             }
         }
     }
 }
+/// Generates code that adds context to a method's `result` naming the ABI
+/// function and the values of its handle arguments.
+///
+/// Handle arguments are the `u32` arguments that aren't pointers or lengths
+/// into guest memory, which are meaningless to someone reading the error.
+fn generate_error_context(fn_method: &ImplItemMethod, impl_type: &Ident) -> TokenStream {
+    let fn_name = get_fn_name(fn_method).to_string();
+
+    let handles: Vec<Ident> = get_fn_args(fn_method)
+        .into_iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(typed) => match (typed.pat.as_ref(), typed.ty.as_ref()) {
+                (Pat::Ident(ident), Type::Path(ty)) if ty.path.is_ident("u32") => {
+                    Some(ident.ident.clone())
+                }
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .filter(|ident| {
+            let name = ident.to_string();
+            !name.ends_with("ptr") && !name.ends_with("len")
+        })
+        .collect();
+
+    let format = handles
+        .iter()
+        .map(|ident| format!("{ident} = {{}}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let format = format!("{{}}::{fn_name}({format})");
+
+    quote! {
+        ::hearth_runtime::anyhow::Context::with_context(result, move || {
+            format!(#format, #impl_type::MODULE, #(#handles),*)
+        })
+    }
+}
 fn generate_add_to_linker_call(link_fn_ident: &Ident) -> TokenStream {
     let link_fn_ident = link_fn_ident.clone();
     quote! {
@@ -253,11 +463,19 @@ fn generate_closure_args(fn_method: &ImplItemMethod) -> TokenStream {
         #caller_arg, #(#fn_args),*
     }
 }
-fn generate_func_wrap_ident(fn_method: &ImplItemMethod) -> Ident {
+/// The maximum number of guest arguments supported by wasmtime's
+/// `func_wrapN_async` functions.
+const MAX_ASYNC_ARGS: usize = 16;
+
+fn get_guest_arg_count(fn_method: &ImplItemMethod) -> usize {
     let mut num_args = get_fn_args(fn_method).len();
     if has_guest_memory(&get_fn_args(fn_method)) {
         num_args -= 1;
     }
+    num_args
+}
+fn generate_func_wrap_ident(fn_method: &ImplItemMethod) -> Ident {
+    let num_args = get_guest_arg_count(fn_method);
     let str = if is_async(fn_method) {
         format!("func_wrap{num_args}_async")
     } else {
@@ -364,3 +582,94 @@ fn get_fn_method(fn_item: ImplItem) -> ImplItemMethod {
         _ => panic!("there is a non-method item within this impl block"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    /// Expands the `HearthProtocol` derive and returns its error message.
+    fn protocol_error(input: DeriveInput) -> String {
+        hearth_protocol_impl(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn protocol_expansion() {
+        let tokens = hearth_protocol_impl(parse_quote! {
+            #[protocol(version = 4)]
+            enum Example {
+                A,
+                B(u32),
+                C { x: i32 },
+            }
+        })
+        .unwrap();
+
+        let expected = quote! {
+            impl ::hearth_schema::versioned::HearthProtocol for Example {
+                const PROTOCOL_VERSION: u32 = 4u32;
+
+                const VARIANTS: &'static [&'static str] = &["A", "B", "C"];
+
+                fn discriminant(&self) -> u32 {
+                    match self {
+                        Self::A { .. } => 0u32,
+                        Self::B { .. } => 1u32,
+                        Self::C { .. } => 2u32,
+                    }
+                }
+            }
+        };
+
+        assert_eq!(tokens.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn protocol_requires_enum() {
+        let err = protocol_error(parse_quote! {
+            #[protocol(version = 1)]
+            struct Example;
+        });
+
+        assert_eq!(err, "HearthProtocol can only be derived for enums");
+    }
+
+    #[test]
+    fn protocol_requires_version() {
+        let err = protocol_error(parse_quote! {
+            enum Example { A }
+        });
+
+        assert_eq!(err, "missing `#[protocol(version = N)]` attribute");
+    }
+
+    #[test]
+    fn protocol_rejects_bad_attributes() {
+        let inputs: [DeriveInput; 3] = [
+            parse_quote! {
+                #[protocol(version = "1")]
+                enum Example { A }
+            },
+            parse_quote! {
+                #[protocol(revision = 1)]
+                enum Example { A }
+            },
+            parse_quote! {
+                #[protocol = 1]
+                enum Example { A }
+            },
+        ];
+
+        for input in inputs {
+            assert!(hearth_protocol_impl(input).is_err());
+        }
+
+        let err = protocol_error(parse_quote! {
+            #[protocol(version = 4294967296)]
+            enum Example { A }
+        });
+
+        assert!(err.contains("number too large"), "{err}");
+    }
+}
@@ -18,16 +18,52 @@
 
 use std::any::{type_name, Any, TypeId};
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::sync::Arc;
 
 use crate::lump::LumpStoreImpl;
-use anyhow::{anyhow, Context, Result};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use hearth_schema::LumpId;
 use serde::Deserialize;
 use tokio::sync::{Mutex, RwLock};
 use tracing::{debug, error};
 
+/// An error while loading an asset that isn't specific to its loader.
+///
+/// Errors returned by [AssetStore::load_asset] can be downcast to this to
+/// tell why loading failed.
+#[derive(Debug)]
+pub enum AssetError {
+    /// No asset loader of the requested type has been added.
+    NoLoader(&'static str),
+
+    /// The asset's lump is not in the lump store.
+    LumpNotFound(LumpId),
+
+    /// The asset's lump could not be decoded into its loader's data type.
+    Decode(serde_json::Error),
+}
+
+impl Display for AssetError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            AssetError::NoLoader(name) => write!(fmt, "could not find asset loader {:?}", name),
+            AssetError::LumpNotFound(lump) => write!(fmt, "failed to get lump {}", lump),
+            AssetError::Decode(err) => write!(fmt, "failed to decode asset: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetError::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 #[async_trait]
 pub trait AssetLoader: Send + Sync + 'static {
     type Asset: Send + Sync + 'static;
@@ -51,6 +87,7 @@ impl<T: JsonAssetLoader> AssetLoader for T {
 
     async fn load_asset(&self, store: &AssetStore, data: &[u8]) -> Result<T::Asset> {
         let data: T::Data = serde_json::from_slice(data)
+            .map_err(AssetError::Decode)
             .with_context(|| format!("Deserializing asset from {}", type_name::<T::Data>()))?;
 
         self.load_asset(store, data).await
@@ -131,13 +168,13 @@ impl AssetStore {
         let pool = self
             .pools
             .get(&type_id)
-            .ok_or_else(|| anyhow!("Could not find asset loader '{:?}", type_name))?;
+            .ok_or(AssetError::NoLoader(type_name))?;
         let pool: &AssetPool<T> = pool.downcast_ref().unwrap();
         let data = self
             .lump_store
             .get_lump(lump)
-            .await
-            .ok_or_else(|| anyhow!("Failed to get lump {}", lump))?;
+            .await?
+            .ok_or(AssetError::LumpNotFound(*lump))?;
         pool.load_asset(self, lump, &data).await
     }
 }
@@ -16,55 +16,471 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
+use anyhow::{Context, Result};
+use async_trait::async_trait;
 use bytes::{Buf, Bytes};
 use hearth_schema::*;
+use parking_lot::Mutex;
 use tokio::sync::RwLock;
-use tracing::debug;
+use tracing::{debug, warn};
 
 pub use bytes;
 
+/// The zstd compression level used for lumps.
+const COMPRESSION_LEVEL: i32 = 3;
+
 #[derive(Debug)]
 struct Lump {
+    /// The stored data, which is zstd-compressed if `compressed` is true.
     data: Bytes,
+
+    /// Whether `data` is compressed.
+    compressed: bool,
+
+    /// The length of the uncompressed data.
+    len: usize,
+
+    /// The store's clock when this lump was last accessed, for LRU eviction.
+    last_used: AtomicU64,
 }
 
+/// The number of [LumpPin]s to each lump, shared between a store and its pins.
+type Pins = Arc<Mutex<HashMap<LumpId, usize>>>;
+
 #[derive(Debug, Default)]
+struct Lumps {
+    lumps: HashMap<LumpId, Lump>,
+
+    /// The total size in bytes of all stored lumps, after compression.
+    size: usize,
+
+    /// The maximum total size of all stored lumps, if any.
+    capacity: Option<usize>,
+
+    /// The size in bytes from which lumps are compressed, if any.
+    compression: Option<usize>,
+}
+
+impl Lumps {
+    fn remove(&mut self, id: &LumpId) -> bool {
+        let Some(lump) = self.lumps.remove(id) else {
+            return false;
+        };
+
+        self.size -= lump.data.len();
+        true
+    }
+
+    /// Evicts least recently used lumps, except for `keep` and pinned lumps,
+    /// until the store is within its capacity.
+    fn evict(&mut self, keep: Option<&LumpId>, pins: &Pins) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+
+        let pins = pins.lock();
+        while self.size > capacity {
+            let lru = self
+                .lumps
+                .iter()
+                .filter(|(id, _)| Some(*id) != keep && !pins.contains_key(id))
+                .min_by_key(|(_, lump)| lump.last_used.load(Ordering::Relaxed))
+                .map(|(id, _)| *id);
+
+            let Some(id) = lru else {
+                break;
+            };
+
+            debug!("Evicting lump {}", id);
+            self.remove(&id);
+        }
+    }
+}
+
+/// A source that lumps missing from a [LumpStoreImpl] can be fetched from,
+/// such as a network peer.
+#[async_trait]
+pub trait LumpSource: Send + Sync + 'static {
+    /// Fetches the data of a lump, or returns `None` if this source doesn't
+    /// have it.
+    async fn fetch_lump(&self, id: &LumpId) -> Option<Bytes>;
+}
+
+/// Keeps a lump from being evicted or removed from a [LumpStoreImpl] while
+/// it's alive. Created with [LumpStoreImpl::pin].
+pub struct LumpPin {
+    id: LumpId,
+    pins: Pins,
+}
+
+impl Debug for LumpPin {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.debug_tuple("LumpPin").field(&self.id).finish()
+    }
+}
+
+impl Drop for LumpPin {
+    fn drop(&mut self) {
+        let mut pins = self.pins.lock();
+        if let Some(count) = pins.get_mut(&self.id) {
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// Stores lumps in memory by their IDs.
+///
+/// By default, lumps are stored indefinitely. When a capacity is set with
+/// [LumpStoreImpl::set_capacity], the least recently used lumps are evicted
+/// to keep the store within it. Lumps that are pinned with
+/// [LumpStoreImpl::pin] are never evicted. Evicting a lump does not affect
+/// lump data that has already been retrieved, and [LumpStoreImpl::get_lump]
+/// re-fetches evicted lumps from the sources added with
+/// [LumpStoreImpl::add_source].
+///
+/// When a compression threshold is set with [LumpStoreImpl::set_compression],
+/// lumps at least that large are stored compressed with zstd and decompressed
+/// when they're retrieved. Lump IDs are always hashed from the uncompressed
+/// data, so compression never changes a lump's ID or its retrieved data.
+#[derive(Default)]
 pub struct LumpStoreImpl {
-    store: RwLock<HashMap<LumpId, Lump>>,
+    store: RwLock<Lumps>,
+
+    /// A counter incremented on every lump access to order them by recency.
+    clock: AtomicU64,
+
+    /// The number of pins to each pinned lump.
+    pins: Pins,
+
+    /// Sources to fetch missing lumps from, in the order that they're tried.
+    sources: parking_lot::RwLock<Vec<Arc<dyn LumpSource>>>,
+}
+
+impl Debug for LumpStoreImpl {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.debug_struct("LumpStoreImpl")
+            .field("store", &self.store)
+            .field("pins", &self.pins)
+            .finish_non_exhaustive()
+    }
 }
 
 impl LumpStoreImpl {
     pub fn new() -> Self {
-        Self {
-            store: Default::default(),
-        }
+        Self::default()
     }
 
     pub async fn add_lump(&self, data: Bytes) -> LumpId {
-        let id = LumpId(
-            blake3::Hasher::new()
-                .update(data.chunk())
-                .finalize()
-                .as_bytes()
-                .to_owned(),
-        );
+        let id = hash_lump(&data);
+        let last_used = self.tick();
+
+        // skip compressing lumps that are already stored
+        let compression = {
+            let store = self.store.read().await;
+            if let Some(lump) = store.lumps.get(&id) {
+                lump.last_used.store(last_used, Ordering::Relaxed);
+                return id;
+            }
+
+            store.compression
+        };
+
+        let len = data.len();
+        let (data, compressed) = match compression {
+            Some(threshold) if len >= threshold => compress(data).await,
+            _ => (data, false),
+        };
 
         let mut store = self.store.write().await;
-        store.entry(id).or_insert_with(|| {
-            debug!("Storing lump {}", id);
-            Lump { data }
-        });
+
+        // another task may have stored the same lump while compressing
+        if store.lumps.contains_key(&id) {
+            return id;
+        }
+
+        debug!("Storing lump {}", id);
+        store.size += data.len();
+        store.lumps.insert(
+            id,
+            Lump {
+                data,
+                compressed,
+                len,
+                last_used: AtomicU64::new(last_used),
+            },
+        );
+
+        store.evict(Some(&id), &self.pins);
 
         id
     }
 
-    pub async fn get_lump(&self, id: &LumpId) -> Option<Bytes> {
-        self.store
-            .read()
+    /// Retrieves a lump's data, fetching it from this store's sources if it's
+    /// missing. Returns `None` if no source has the lump.
+    ///
+    /// Fails if the stored lump couldn't be decompressed.
+    pub async fn get_lump(&self, id: &LumpId) -> Result<Option<Bytes>> {
+        let stored = {
+            let store = self.store.read().await;
+            store.lumps.get(id).map(|lump| {
+                lump.last_used.store(self.tick(), Ordering::Relaxed);
+                (lump.data.clone(), lump.compressed, lump.len)
+            })
+        };
+
+        let Some((data, compressed, len)) = stored else {
+            return Ok(self.fetch_lump(id).await);
+        };
+
+        if !compressed {
+            return Ok(Some(data));
+        }
+
+        let data = tokio::task::spawn_blocking(move || zstd::bulk::decompress(&data, len))
             .await
-            .get(id)
-            .map(|lump| lump.data.clone())
+            .unwrap()
+            .with_context(|| format!("failed to decompress stored lump {}", id))?;
+
+        Ok(Some(data.into()))
+    }
+
+    /// Fetches a missing lump from this store's sources and re-adds it.
+    async fn fetch_lump(&self, id: &LumpId) -> Option<Bytes> {
+        let sources = self.sources.read().clone();
+        for source in sources {
+            let Some(data) = source.fetch_lump(id).await else {
+                continue;
+            };
+
+            if hash_lump(&data) != *id {
+                warn!("Lump source returned mismatched data for lump {}", id);
+                continue;
+            }
+
+            debug!("Fetched lump {}", id);
+            self.add_lump(data.clone()).await;
+            return Some(data);
+        }
+
+        None
+    }
+
+    /// Adds a source to fetch lumps from when they're missing from this store.
+    ///
+    /// Sources are tried in the order that they were added.
+    pub fn add_source(&self, source: impl LumpSource) {
+        self.sources.write().push(Arc::new(source));
+    }
+
+    /// Pins a lump so that it isn't evicted or removed from this store until
+    /// the returned [LumpPin] is dropped.
+    ///
+    /// The lump doesn't need to be in the store to be pinned.
+    pub fn pin(&self, id: LumpId) -> LumpPin {
+        *self.pins.lock().entry(id).or_default() += 1;
+
+        LumpPin {
+            id,
+            pins: self.pins.clone(),
+        }
+    }
+
+    pub async fn has_lump(&self, id: &LumpId) -> bool {
+        self.store.read().await.lumps.contains_key(id)
+    }
+
+    /// Removes a lump from the store unless it's pinned. Returns true if the
+    /// lump was removed.
+    pub async fn remove_lump(&self, id: &LumpId) -> bool {
+        let mut store = self.store.write().await;
+
+        if self.pins.lock().contains_key(id) {
+            return false;
+        }
+
+        let removed = store.remove(id);
+
+        if removed {
+            debug!("Removed lump {}", id);
+        }
+
+        removed
+    }
+
+    /// Sets the maximum total size in bytes of the stored lumps, or removes
+    /// the limit if `None`. Evicts lumps immediately to fit.
+    ///
+    /// A single lump larger than the capacity is still stored until the next
+    /// lump is added.
+    pub async fn set_capacity(&self, capacity: Option<usize>) {
+        let mut store = self.store.write().await;
+        store.capacity = capacity;
+        store.evict(None, &self.pins);
+    }
+
+    /// Sets the size in bytes from which newly added lumps are compressed, or
+    /// disables compression if `None`. Already stored lumps are unaffected.
+    pub async fn set_compression(&self, threshold: Option<usize>) {
+        self.store.write().await.compression = threshold;
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// Computes the ID of lump data.
+fn hash_lump(data: &Bytes) -> LumpId {
+    LumpId(
+        blake3::Hasher::new()
+            .update(data.chunk())
+            .finalize()
+            .as_bytes()
+            .to_owned(),
+    )
+}
+
+/// Compresses lump data on a blocking thread. Returns the data to store and
+/// whether it's compressed, keeping the original data if compression doesn't
+/// make it smaller.
+async fn compress(data: Bytes) -> (Bytes, bool) {
+    tokio::task::spawn_blocking(move || {
+        match zstd::bulk::compress(data.chunk(), COMPRESSION_LEVEL) {
+            Ok(compressed) if compressed.len() < data.len() => (compressed.into(), true),
+            _ => (data, false),
+        }
+    })
+    .await
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A [LumpSource] that serves lumps from a map.
+    struct MapSource(HashMap<LumpId, Bytes>);
+
+    #[async_trait]
+    impl LumpSource for MapSource {
+        async fn fetch_lump(&self, id: &LumpId) -> Option<Bytes> {
+            self.0.get(id).cloned()
+        }
+    }
+
+    fn lump(byte: u8) -> Bytes {
+        vec![byte; 4].into()
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        let store = LumpStoreImpl::new();
+        store.set_capacity(Some(12)).await;
+
+        let a = store.add_lump(lump(0)).await;
+        let b = store.add_lump(lump(1)).await;
+        let c = store.add_lump(lump(2)).await;
+        store.get_lump(&a).await.unwrap();
+        let d = store.add_lump(lump(3)).await;
+
+        assert!(store.has_lump(&a).await);
+        assert!(!store.has_lump(&b).await);
+        assert!(store.has_lump(&c).await);
+        assert!(store.has_lump(&d).await);
+    }
+
+    #[tokio::test]
+    async fn pinned_lumps_are_not_evicted() {
+        let store = LumpStoreImpl::new();
+        let a = store.add_lump(lump(0)).await;
+        let b = store.add_lump(lump(1)).await;
+        let pin = store.pin(a);
+
+        store.set_capacity(Some(4)).await;
+        assert!(store.has_lump(&a).await);
+        assert!(!store.has_lump(&b).await);
+
+        drop(pin);
+        store.add_lump(lump(2)).await;
+        assert!(!store.has_lump(&a).await);
+    }
+
+    #[tokio::test]
+    async fn remove_while_pinned() {
+        let store = LumpStoreImpl::new();
+        let id = store.add_lump(lump(0)).await;
+        let first = store.pin(id);
+        let second = store.pin(id);
+
+        assert!(!store.remove_lump(&id).await);
+        drop(first);
+        assert!(!store.remove_lump(&id).await);
+        drop(second);
+        assert!(store.remove_lump(&id).await);
+        assert!(!store.has_lump(&id).await);
+    }
+
+    #[tokio::test]
+    async fn compression_round_trip() {
+        let data: Bytes = b"hearth ".repeat(1024).into();
+
+        let plain = LumpStoreImpl::new();
+        let plain_id = plain.add_lump(data.clone()).await;
+
+        let store = LumpStoreImpl::new();
+        store.set_compression(Some(0)).await;
+        let id = store.add_lump(data.clone()).await;
+
+        assert_eq!(id, plain_id);
+        assert!(store.store.read().await.size < data.len());
+        assert_eq!(store.get_lump(&id).await.unwrap(), Some(data));
+    }
+
+    #[tokio::test]
+    async fn skips_incompressible_lumps() {
+        let store = LumpStoreImpl::new();
+        store.set_compression(Some(0)).await;
+        let data = lump(0).slice(..1);
+        let id = store.add_lump(data.clone()).await;
+
+        assert!(!store.store.read().await.lumps[&id].compressed);
+        assert_eq!(store.get_lump(&id).await.unwrap(), Some(data));
+    }
+
+    #[tokio::test]
+    async fn refetches_removed_lumps() {
+        let data = lump(0);
+        let id = hash_lump(&data);
+
+        let store = LumpStoreImpl::new();
+        store.add_source(MapSource(HashMap::from([(id, data.clone())])));
+        assert_eq!(store.get_lump(&id).await.unwrap(), Some(data.clone()));
+        assert!(store.has_lump(&id).await);
+
+        assert!(store.remove_lump(&id).await);
+        assert_eq!(store.get_lump(&id).await.unwrap(), Some(data));
+        assert!(store.has_lump(&id).await);
+    }
+
+    #[tokio::test]
+    async fn ignores_mismatched_sources() {
+        let id = hash_lump(&lump(0));
+
+        let store = LumpStoreImpl::new();
+        store.add_source(MapSource(HashMap::from([(id, lump(1))])));
+        assert_eq!(store.get_lump(&id).await.unwrap(), None);
+        assert!(!store.has_lump(&id).await);
     }
 }
@@ -97,6 +97,21 @@ pub struct ProcessMetadata {
 
     /// An SPDX license identifier of this process's software license.
     pub license: Option<String>,
+
+    /// The semantic version of this process's software.
+    pub version: Option<String>,
+
+    /// The version of the host ABI that this process was built against, if
+    /// it runs on a guest ABI.
+    pub abi_version: Option<u32>,
+
+    /// A scheduling hint for this process. Higher priorities let a process
+    /// run for longer before it yields to others.
+    ///
+    /// Set by whatever spawns the process, never by the process itself. How
+    /// the priority is interpreted is up to the process's host, if at all.
+    /// Defaults to the lowest priority, zero.
+    pub priority: Option<u32>,
 }
 
 /// A factory for making local instances of [Process].
@@ -28,6 +28,7 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use flue::PostOffice;
+use hearth_schema::DEFAULT_MAX_MESSAGE_CAPS;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tracing::{debug, error, warn};
 
@@ -249,6 +250,11 @@ impl RuntimeBuilder {
             ..crate::utils::cargo_process_metadata!()
         };
 
+        self.lump_store.set_capacity(config.lump_capacity).await;
+        self.lump_store
+            .set_compression(config.lump_compression)
+            .await;
+
         let ctx = self.process_factory.spawn_with_table(meta, registry_table);
         let registry = Arc::new(ctx);
 
@@ -287,7 +293,32 @@ impl RuntimeBuilder {
 }
 
 /// Configuration info for a runtime.
-pub struct RuntimeConfig {}
+pub struct RuntimeConfig {
+    /// The maximum number of capabilities that a process may attach to a
+    /// single message. Defaults to [DEFAULT_MAX_MESSAGE_CAPS].
+    ///
+    /// Each transferred capability takes a slot in the receiver's table, so
+    /// this bounds how much a single message can grow it.
+    pub max_message_caps: usize,
+
+    /// The maximum total size in bytes of the lump store, if any. See
+    /// [LumpStoreImpl::set_capacity].
+    pub lump_capacity: Option<usize>,
+
+    /// The size in bytes from which lumps are stored compressed, if any. See
+    /// [LumpStoreImpl::set_compression].
+    pub lump_compression: Option<usize>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            max_message_caps: DEFAULT_MAX_MESSAGE_CAPS,
+            lump_capacity: None,
+            lump_compression: None,
+        }
+    }
+}
 
 /// An instance of a single Hearth runtime.
 ///
@@ -40,6 +40,7 @@ use crate::{
 /// - `repository`: `CARGO_PKG_REPOSITORY`
 /// - `homepage`: `CARGO_PKG_HOMEPAGE`
 /// - `license`: `CARGO_PKG_LICENSE`
+/// - `version`: `CARGO_PKG_VERSION`
 #[macro_export]
 macro_rules! cargo_process_metadata {
     () => {{
@@ -60,6 +61,7 @@ macro_rules! cargo_process_metadata {
         meta.repository = some_or_empty(env!("CARGO_PKG_REPOSITORY"));
         meta.homepage = some_or_empty(env!("CARGO_PKG_HOMEPAGE"));
         meta.license = some_or_empty(env!("CARGO_PKG_LICENSE"));
+        meta.version = some_or_empty(env!("CARGO_PKG_VERSION"));
         meta
     }};
 }
@@ -252,6 +254,14 @@ pub trait SinkProcess: Send {
     /// The deserializeable data type to be received.
     type Message: for<'a> Deserialize<'a> + Send + Debug;
 
+    /// Decodes the data of a received message.
+    ///
+    /// Defaults to parsing the data as JSON. Messages that fail to decode are
+    /// logged and dropped.
+    fn decode(data: &[u8]) -> anyhow::Result<Self::Message> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
     /// A callback to call when messages are received by this process.
     async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>);
 
@@ -280,7 +290,7 @@ where
             use OwnedTableSignal::*;
             match recv {
                 Some(Message { data, caps }) => {
-                    let data: T::Message = match serde_json::from_slice(&data) {
+                    let data = match T::decode(&data) {
                         Ok(request) => request,
                         Err(err) => {
                             // TODO make this a process log
@@ -17,9 +17,12 @@
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
 use glam::{Quat, Vec2, Vec3};
+use hearth_macros::HearthProtocol;
 use serde::{Deserialize, Serialize};
 use serde_with::{base64::Base64, serde_as};
 
+use crate::{CapProtocol, CapSpec};
+
 /// A rectangular buffer of pixel data.
 #[serde_as]
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -54,6 +57,22 @@ pub struct Blit {
     pub pixels: Pixels,
 }
 
+/// A rectangular region of a canvas's pixel buffer.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Region {
+    /// The X coordinate of this region's origin in pixels.
+    pub x: u32,
+
+    /// The Y coordinate of this region's origin in pixels.
+    pub y: u32,
+
+    /// The width of this region in pixels.
+    pub width: u32,
+
+    /// The height of this region in pixels.
+    pub height: u32,
+}
+
 /// The positioning of a canvas in 3D space.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Position {
@@ -71,7 +90,11 @@ pub struct Position {
 }
 
 /// A message to update a canvas instance.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+///
+/// Canvas updates are encoded with
+/// [HearthProtocol::encode_versioned](crate::versioned::HearthProtocol::encode_versioned).
+#[derive(Clone, Debug, Deserialize, Serialize, HearthProtocol)]
+#[protocol(version = 1)]
 pub enum CanvasUpdate {
     /// Relocate the canvas to a given [Position].
     Relocate(Position),
@@ -79,6 +102,8 @@ pub enum CanvasUpdate {
     /// Resize the canvas using [Pixels].
     ///
     /// The canvas's new pixel buffer size is derived from the pixel buffer's size.
+    /// Resizes to sizes that would be rejected by [FactoryError::InvalidSize]
+    /// are ignored.
     ///
     /// If the given pixel buffer's size is the same as the current canvas's
     /// size, the canvas will be efficiently updated without reallocating any
@@ -87,6 +112,29 @@ pub enum CanvasUpdate {
 
     /// Blit a buffer to a part of this canvas.
     Blit(Blit),
+
+    /// Blit multiple buffers to this canvas, in order.
+    ///
+    /// Cheaper than sending a separate [CanvasUpdate::Blit] for each buffer.
+    BlitBatch(Vec<Blit>),
+
+    /// Read back the pixels in a region of this canvas.
+    ///
+    /// The [Pixels] of the region are sent to the message's only capability.
+    /// The region is clipped to the bounds of the canvas, so the returned
+    /// buffer may be smaller than requested, and is empty if the region lies
+    /// entirely out of bounds or the pixels could not be read.
+    Readback(Region),
+}
+
+/// [CanvasUpdate::Readback] carries the reply capability for the pixels.
+impl CapProtocol for CanvasUpdate {
+    fn expected_caps(&self) -> CapSpec {
+        match self {
+            CanvasUpdate::Readback(_) => CapSpec::exact(&["reply"]),
+            _ => CapSpec::NONE,
+        }
+    }
 }
 
 /// Configures the method of texture sampling to use for a canvas.
@@ -99,6 +147,20 @@ pub enum CanvasSamplingMode {
     Nearest,
 }
 
+/// Configures how a canvas is blended with what is behind it.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CanvasBlendMode {
+    /// The canvas is fully opaque and its alpha channel is ignored.
+    #[default]
+    Opaque,
+
+    /// The canvas is blended over what is behind it using its alpha channel.
+    ///
+    /// Alpha-blended canvases are drawn after all opaque canvases and don't
+    /// occlude anything drawn after them.
+    Alpha,
+}
+
 /// A request to the canvas factory.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum FactoryRequest {
@@ -115,6 +177,15 @@ pub enum FactoryRequest {
 
         /// The sampling method to use.
         sampling: CanvasSamplingMode,
+
+        /// The blending mode to use. Defaults to opaque.
+        #[serde(default)]
+        blend: CanvasBlendMode,
+
+        /// Whether to draw this canvas on top of all 3D geometry and other
+        /// canvases that are not on top, ignoring depth. Defaults to false.
+        #[serde(default)]
+        on_top: bool,
     },
 }
 
@@ -130,6 +201,18 @@ pub enum FactorySuccess {
 pub enum FactoryError {
     /// The request has failed to parse.
     ParseError,
+
+    /// The requested pixel buffer is empty or larger than the GPU supports.
+    InvalidSize {
+        /// The requested width in pixels.
+        width: u32,
+
+        /// The requested height in pixels.
+        height: u32,
+
+        /// The maximum width and height of a canvas in pixels.
+        max: u32,
+    },
 }
 
 /// A type shorthand for [FactorySuccess] and [FactoryError].
@@ -30,10 +30,25 @@ pub struct DebugDrawVertex {
     pub color: Color,
 }
 
+/// How the indices of a [DebugDrawMesh] are assembled into primitives.
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub enum DebugDrawTopology {
+    /// Each pair of indices is a line segment.
+    #[default]
+    Lines,
+
+    /// Each triple of indices is a filled triangle. Both faces are drawn.
+    Triangles,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct DebugDrawMesh {
     pub vertices: Vec<DebugDrawVertex>,
     pub indices: Vec<u32>,
+
+    /// The topology of this mesh. Defaults to lines.
+    #[serde(default)]
+    pub topology: DebugDrawTopology,
 }
 
 /// An update to a debug draw mesh.
@@ -45,6 +60,47 @@ pub enum DebugDrawUpdate {
     /// Sets whether to hide this mesh.
     Hide(bool),
 
+    /// Multiplies the color of this whole debug draw by a tint.
+    ///
+    /// Unlike vertex colors, the tint's alpha is used to blend the draw. The
+    /// default tint is opaque white.
+    SetTint(Color),
+
+    /// Adds a line segment to this debug draw.
+    ///
+    /// Shapes are drawn alongside this debug draw's [DebugDrawUpdate::Contents]
+    /// and are removed automatically after `ttl` seconds. Shapes without a TTL
+    /// stay until [DebugDrawUpdate::ClearShapes] or destruction.
+    AddLine {
+        start: Vec3,
+        end: Vec3,
+        color: Color,
+        ttl: Option<f32>,
+    },
+
+    /// Adds a wireframe axis-aligned box to this debug draw.
+    ///
+    /// See [DebugDrawUpdate::AddLine] for how shapes are removed.
+    AddBox {
+        center: Vec3,
+        half_extents: Vec3,
+        color: Color,
+        ttl: Option<f32>,
+    },
+
+    /// Adds a wireframe sphere to this debug draw.
+    ///
+    /// See [DebugDrawUpdate::AddLine] for how shapes are removed.
+    AddSphere {
+        center: Vec3,
+        radius: f32,
+        color: Color,
+        ttl: Option<f32>,
+    },
+
+    /// Removes all shapes from this debug draw, regardless of their TTL.
+    ClearShapes,
+
     /// Destroys this debug draw mesh.
     Destroy,
 }
@@ -19,11 +19,15 @@
 use std::{
     fmt::{Display, Formatter, Result as FmtResult},
     ops::{Deref, DerefMut},
+    str::FromStr,
 };
 
 use bytemuck::{Pod, Zeroable};
 use serde::{Deserialize, Serialize};
 
+// lets derives from hearth-macros refer to this crate by name from within it
+extern crate self as hearth_schema;
+
 /// Canvas protocol.
 pub mod canvas;
 
@@ -45,12 +49,22 @@ pub mod renderer;
 /// Terminal protocol.
 pub mod terminal;
 
+/// Timing services protocol.
+pub mod time;
+
+/// Versioned message encoding for protocols.
+pub mod versioned;
+
 /// WebAssembly process protocols and utilities.
 pub mod wasm;
 
 /// Windowing protocol.
 pub mod window;
 
+/// The default maximum number of capabilities that a runtime allows a
+/// process to attach to a single message.
+pub const DEFAULT_MAX_MESSAGE_CAPS: usize = 256;
+
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub struct ProcessId(pub u32);
 
@@ -69,6 +83,70 @@ impl Display for LumpId {
     }
 }
 
+impl FromStr for LumpId {
+    type Err = ParseLumpIdError;
+
+    /// Parses a lump ID from the 64 hexadecimal digits written by its
+    /// [Display] implementation. Both upper and lower case are accepted.
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let digits = src.as_bytes();
+        if digits.len() != 64 {
+            return Err(ParseLumpIdError::InvalidLength(digits.len()));
+        }
+
+        let digit = |index: usize| {
+            (digits[index] as char)
+                .to_digit(16)
+                .map(|value| value as u8)
+                .ok_or(ParseLumpIdError::InvalidDigit { index })
+        };
+
+        let mut id = [0u8; 32];
+        for (byte_index, byte) in id.iter_mut().enumerate() {
+            let index = byte_index * 2;
+            *byte = (digit(index)? << 4) | digit(index + 1)?;
+        }
+
+        Ok(LumpId(id))
+    }
+}
+
+impl TryFrom<&str> for LumpId {
+    type Error = ParseLumpIdError;
+
+    fn try_from(src: &str) -> Result<Self, Self::Error> {
+        src.parse()
+    }
+}
+
+/// An error while parsing a [LumpId] from a string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseLumpIdError {
+    /// The string was not 64 bytes long. Contains the actual length.
+    InvalidLength(usize),
+
+    /// The string contains a byte that is not a hexadecimal digit.
+    InvalidDigit {
+        /// The byte offset of the invalid digit.
+        index: usize,
+    },
+}
+
+impl Display for ParseLumpIdError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            ParseLumpIdError::InvalidLength(len) => {
+                write!(fmt, "expected 64 hex digits in lump ID, got {} bytes", len)
+            }
+            ParseLumpIdError::InvalidDigit { index } => {
+                write!(fmt, "invalid hex digit in lump ID at byte {}", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseLumpIdError {}
+
 bitflags::bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
     pub struct Permissions: u32 {
@@ -146,6 +224,7 @@ impl From<ProcessLogLevel> for tracing::Level {
 pub enum SignalKind {
     Message,
     Down,
+    Shutdown,
 }
 
 impl TryFrom<u32> for SignalKind {
@@ -156,6 +235,7 @@ impl TryFrom<u32> for SignalKind {
         match other {
             0 => Ok(Message),
             1 => Ok(Down),
+            2 => Ok(Shutdown),
             _ => Err(()),
         }
     }
@@ -167,8 +247,59 @@ impl From<SignalKind> for u32 {
         match val {
             Message => 0,
             Down => 1,
+            Shutdown => 2,
+        }
+    }
+}
+
+/// The capabilities that a message of a given protocol is expected to carry.
+///
+/// Capabilities are passed positionally, so each entry of `roles` names the
+/// capability at that index in the message. Messages carrying more than
+/// `roles.len()` capabilities (when `max` allows it) have unnamed extras.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct CapSpec {
+    /// The minimum number of capabilities the message must carry.
+    pub min: usize,
+
+    /// The maximum number of capabilities the message may carry, if bounded.
+    pub max: Option<usize>,
+
+    /// The name of the role of each capability, in order.
+    pub roles: &'static [&'static str],
+}
+
+impl CapSpec {
+    /// A spec for messages carrying no capabilities at all.
+    pub const NONE: Self = Self::exact(&[]);
+
+    /// Creates a spec for exactly one capability per given role.
+    pub const fn exact(roles: &'static [&'static str]) -> Self {
+        Self {
+            min: roles.len(),
+            max: Some(roles.len()),
+            roles,
         }
     }
+
+    /// Returns true if a message with `count` capabilities satisfies this spec.
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.map(|max| count <= max).unwrap_or(true)
+    }
+
+    /// Looks up the role of the capability at the given index, if it is named.
+    pub fn role(&self, index: usize) -> Option<&'static str> {
+        self.roles.get(index).copied()
+    }
+}
+
+/// Implemented by message types that declare the capabilities they carry.
+///
+/// This formalizes the capability conventions of each protocol so that
+/// generic code can validate incoming messages without per-protocol logic.
+pub trait CapProtocol {
+    /// Returns the capabilities expected to accompany this message.
+    fn expected_caps(&self) -> CapSpec;
 }
 
 /// An ARGB color value with 8 bits per channel.
@@ -195,6 +326,66 @@ impl Color {
             self.0 as u8,
         )
     }
+
+    /// Converts this color to normalized linear RGBA channels.
+    ///
+    /// The RGB channels are converted from sRGB to linear. Alpha is already
+    /// linear, so it is only normalized.
+    pub fn to_linear_f32(&self) -> [f32; 4] {
+        let (a, r, g, b) = self.to_argb();
+        let normalize = |c: u8| c as f32 / 255.0;
+
+        [
+            srgb_to_linear(normalize(r)),
+            srgb_to_linear(normalize(g)),
+            srgb_to_linear(normalize(b)),
+            normalize(a),
+        ]
+    }
+
+    /// Creates a color from normalized linear RGBA channels.
+    ///
+    /// This is the inverse of [Color::to_linear_f32]. Channels are clamped
+    /// between 0 and 1.
+    pub fn from_linear_f32(rgba: [f32; 4]) -> Self {
+        let [r, g, b, a] = rgba;
+        let quantize = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        Self::from_argb(
+            quantize(a),
+            quantize(linear_to_srgb(r)),
+            quantize(linear_to_srgb(g)),
+            quantize(linear_to_srgb(b)),
+        )
+    }
+
+    /// Linearly interpolates between two colors in linear color space.
+    ///
+    /// A `t` of 0 returns `a` and a `t` of 1 returns `b`.
+    pub fn lerp(a: Color, b: Color, t: f32) -> Self {
+        let a = a.to_linear_f32();
+        let b = b.to_linear_f32();
+        let mix = |i: usize| a[i] + (b[i] - a[i]) * t;
+        Self::from_linear_f32([mix(0), mix(1), mix(2), mix(3)])
+    }
+}
+
+/// Converts a normalized sRGB color channel to linear.
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a normalized linear color channel to sRGB.
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
 }
 
 /// Provides efficient byte-based de/serialization for `Vec`s of `T`.
@@ -225,10 +416,253 @@ impl<T: Pod> AsRef<[u8]> for ByteVec<T> {
     }
 }
 
+impl<T: Pod> ByteVec<T> {
+    /// Converts raw bytes into a `ByteVec`, checking that they contain exactly
+    /// `expected_elems` elements of `T`.
+    ///
+    /// Unlike the [TryFrom] implementation, the returned error describes the
+    /// element type and the lengths involved.
+    pub fn from_bytes_checked(bytes: Vec<u8>, expected_elems: usize) -> Result<Self, ByteVecError> {
+        let len = bytes.len();
+        let vec = Self::try_from(bytes).map_err(|_| ByteVecError::Misaligned {
+            type_name: std::any::type_name::<T>(),
+            elem_size: std::mem::size_of::<T>(),
+            len,
+        })?;
+
+        vec.check_len(expected_elems)?;
+        Ok(vec)
+    }
+
+    /// Checks that this vector contains exactly `expected_elems` elements.
+    pub fn check_len(&self, expected_elems: usize) -> Result<(), ByteVecError> {
+        if self.0.len() == expected_elems {
+            return Ok(());
+        }
+
+        let elem_size = std::mem::size_of::<T>();
+        Err(ByteVecError::LengthMismatch {
+            type_name: std::any::type_name::<T>(),
+            expected: expected_elems * elem_size,
+            actual: self.0.len() * elem_size,
+        })
+    }
+}
+
 impl<T: Pod> TryFrom<Vec<u8>> for ByteVec<T> {
     type Error = bytemuck::PodCastError;
 
     fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
-        bytemuck::try_cast_slice(bytes.as_slice()).map(|slice| Self(slice.to_vec()))
+        let elem_size = std::mem::size_of::<T>();
+        if elem_size == 0 {
+            return Err(bytemuck::PodCastError::SizeMismatch);
+        }
+
+        if !bytes.len().is_multiple_of(elem_size) {
+            return Err(bytemuck::PodCastError::OutputSliceWouldHaveSlop);
+        }
+
+        // read each element unaligned since the allocation of `bytes` is
+        // only guaranteed to be aligned for `u8`
+        let elems = bytes
+            .chunks_exact(elem_size)
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+
+        Ok(Self(elems))
+    }
+}
+
+/// An error while converting bytes into a [ByteVec].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ByteVecError {
+    /// The byte length is not a whole number of elements.
+    Misaligned {
+        /// The name of the element type.
+        type_name: &'static str,
+
+        /// The size in bytes of a single element.
+        elem_size: usize,
+
+        /// The length in bytes of the data.
+        len: usize,
+    },
+
+    /// The data does not contain the expected number of elements.
+    LengthMismatch {
+        /// The name of the element type.
+        type_name: &'static str,
+
+        /// The expected size in bytes.
+        expected: usize,
+
+        /// The actual size in bytes.
+        actual: usize,
+    },
+}
+
+impl Display for ByteVecError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            ByteVecError::Misaligned {
+                type_name,
+                elem_size,
+                len,
+            } => write!(
+                fmt,
+                "{} bytes is not a multiple of the {}-byte size of {}",
+                len, elem_size, type_name
+            ),
+            ByteVecError::LengthMismatch {
+                type_name,
+                expected,
+                actual,
+            } => write!(
+                fmt,
+                "expected {} bytes of {}, got {} bytes",
+                expected, type_name, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ByteVecError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_linear_round_trip() {
+        for value in [0x00000000, 0xffffffff, 0x80102030, 0x12fe7f01] {
+            let color = Color(value);
+            assert_eq!(Color::from_linear_f32(color.to_linear_f32()), color);
+        }
+    }
+
+    #[test]
+    fn color_linear_channels() {
+        let [r, g, b, a] = Color::from_argb(0x80, 0xff, 0x00, 0xbc).to_linear_f32();
+        assert_eq!(r, 1.0);
+        assert_eq!(g, 0.0);
+        assert!((b - 0.5).abs() < 0.01, "sRGB 0xbc is about half linear");
+        assert!((a - 0x80 as f32 / 255.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn color_from_linear_clamps() {
+        let color = Color::from_linear_f32([2.0, -1.0, 1.0, 0.0]);
+        assert_eq!(color, Color::from_argb(0x00, 0xff, 0x00, 0xff));
+    }
+
+    #[test]
+    fn color_lerp() {
+        let black = Color::from_argb(0xff, 0, 0, 0);
+        let white = Color::from_argb(0xff, 0xff, 0xff, 0xff);
+        assert_eq!(Color::lerp(black, white, 0.0), black);
+        assert_eq!(Color::lerp(black, white, 1.0), white);
+
+        // halfway in linear space is brighter than halfway in sRGB
+        let (a, r, g, b) = Color::lerp(black, white, 0.5).to_argb();
+        assert_eq!(a, 0xff);
+        assert_eq!((r, g), (b, b));
+        assert!((0xbb..=0xbd).contains(&r));
+    }
+
+    #[test]
+    fn lump_id_round_trip() {
+        let mut bytes = [0u8; 32];
+        bytes
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b = i as u8 * 7);
+        let id = LumpId(bytes);
+        assert_eq!(id.to_string().parse::<LumpId>(), Ok(id));
+    }
+
+    #[test]
+    fn lump_id_mixed_case() {
+        let lower = "ab".repeat(32);
+        let upper = "AB".repeat(32);
+        let expected = Ok(LumpId([0xab; 32]));
+        assert_eq!(LumpId::from_str(&lower), expected);
+        assert_eq!(LumpId::try_from(upper.as_str()), expected);
+    }
+
+    #[test]
+    fn lump_id_invalid_length() {
+        let result = "0".repeat(63).parse::<LumpId>();
+        assert_eq!(result, Err(ParseLumpIdError::InvalidLength(63)));
+        let result = "".parse::<LumpId>();
+        assert_eq!(result, Err(ParseLumpIdError::InvalidLength(0)));
+    }
+
+    #[test]
+    fn lump_id_invalid_digit() {
+        let mut src = "0".repeat(64);
+        src.replace_range(41..42, "g");
+        let result = src.parse::<LumpId>();
+        assert_eq!(result, Err(ParseLumpIdError::InvalidDigit { index: 41 }));
+
+        // multi-byte characters are rejected without panicking
+        let src = format!("é{}", "0".repeat(62));
+        let result = src.parse::<LumpId>();
+        assert_eq!(result, Err(ParseLumpIdError::InvalidDigit { index: 0 }));
+    }
+
+    #[test]
+    fn byte_vec_from_bytes() {
+        let bytes: Vec<u8> = [1u32, 2, 3].iter().flat_map(|x| x.to_ne_bytes()).collect();
+        let vec = ByteVec::<u32>::try_from(bytes).unwrap();
+        assert_eq!(vec.0, [1, 2, 3]);
+    }
+
+    #[test]
+    fn byte_vec_unaligned() {
+        // offset the data by one byte so that it's not aligned for u32
+        let bytes: Vec<u8> = std::iter::once(0)
+            .chain([5u32, 6].iter().flat_map(|x| x.to_ne_bytes()))
+            .collect();
+
+        let vec = ByteVec::<u32>::try_from(bytes[1..].to_vec()).unwrap();
+        assert_eq!(vec.0, [5, 6]);
+    }
+
+    #[test]
+    fn byte_vec_slop() {
+        let result = ByteVec::<u32>::try_from(vec![0; 6]);
+        assert_eq!(
+            result.unwrap_err(),
+            bytemuck::PodCastError::OutputSliceWouldHaveSlop
+        );
+    }
+
+    #[test]
+    fn byte_vec_checked_misaligned() {
+        let err = ByteVec::<u32>::from_bytes_checked(vec![0; 6], 1).unwrap_err();
+        assert_eq!(
+            err,
+            ByteVecError::Misaligned {
+                type_name: "u32",
+                elem_size: 4,
+                len: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn byte_vec_checked_length() {
+        let vec = ByteVec::<u16>::from_bytes_checked(vec![0; 8], 4).unwrap();
+        assert_eq!(vec.0.len(), 4);
+
+        let err = ByteVec::<u16>::from_bytes_checked(vec![0; 8], 3).unwrap_err();
+        assert_eq!(
+            err,
+            ByteVecError::LengthMismatch {
+                type_name: "u16",
+                expected: 6,
+                actual: 8,
+            }
+        );
     }
 }
@@ -18,6 +18,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::{CapProtocol, CapSpec};
+
 /// A message schema for messages sent to a registry process. All variants require
 /// that a reply cap is the first capability in the message.
 ///
@@ -36,6 +38,15 @@ pub enum RegistryRequest {
     List,
 }
 
+impl CapProtocol for RegistryRequest {
+    fn expected_caps(&self) -> CapSpec {
+        match self {
+            RegistryRequest::Get { .. } | RegistryRequest::List => CapSpec::exact(&["reply"]),
+            RegistryRequest::Register { .. } => CapSpec::exact(&["reply", "service"]),
+        }
+    }
+}
+
 /// A response to a [RegistryRequest].
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum RegistryResponse {
@@ -20,7 +20,7 @@ use glam::{Mat4, UVec2, Vec2, Vec3, Vec4};
 use serde::{Deserialize, Serialize};
 use serde_with::{base64::Base64, serde_as};
 
-use crate::{ByteVec, LumpId};
+use crate::{ByteVec, CapProtocol, CapSpec, Color, LumpId};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum RendererRequest {
@@ -34,6 +34,14 @@ pub enum RendererRequest {
         initial_state: DirectionalLightState,
     },
 
+    /// Adds a new point light to the scene.
+    ///
+    /// Returns [RendererSuccess::Ok] and a capability to the new light when
+    /// successful. The light accepts [PointLightUpdate] messages.
+    ///
+    /// When the capability is killed, the light is removed from the scene.
+    AddPointLight { initial_state: PointLightState },
+
     /// Adds a new object to the scene.
     ///
     /// Returns [RendererSuccess::Ok] and a capability to the new object when
@@ -54,6 +62,34 @@ pub enum RendererRequest {
         transform: Mat4,
     },
 
+    /// Adds a new object to the scene that switches between meshes of
+    /// decreasing detail as the camera moves away from it.
+    ///
+    /// Each level of detail is a lump ID of the [MeshData] to use and the
+    /// distance from the camera to the object's origin beyond which that
+    /// mesh is used. Levels may be given in any order, and the level with the
+    /// smallest distance is used when the camera is closer than all of them.
+    /// To avoid flickering when the camera hovers around a switching
+    /// distance, the camera must move past it by a fraction of the distance
+    /// before the object switches levels.
+    ///
+    /// Returns [RendererSuccess::Ok] and a capability to the new object when
+    /// successful. The object accepts [ObjectUpdate::Transform] messages.
+    /// Returns [RendererError::InvalidRequest] if `lods` is empty or any
+    /// distance is negative or not finite.
+    ///
+    /// When the capability is killed, the object is removed from the scene.
+    AddLodObject {
+        /// The levels of detail of this object.
+        lods: Vec<(LumpId, f32)>,
+
+        /// The lump ID of the [MaterialData] to use for all levels of detail.
+        material: LumpId,
+
+        /// The initial transform of this object.
+        transform: Mat4,
+    },
+
     /// Updates the scene's skybox.
     ///
     /// Returns [RendererSuccess::Ok] with no capabilities when successful.
@@ -66,6 +102,62 @@ pub enum RendererRequest {
     ///
     /// Returns [RendererSuccess::Ok] with no capabilities when successful.
     SetAmbientLighting { ambient: Vec4 },
+
+    /// Updates the color that the scene is cleared to behind all objects.
+    ///
+    /// The color is in sRGB and is tone mapped along with the rest of the
+    /// scene. A transparent clear color only results in a transparent
+    /// background if the window supports it.
+    ///
+    /// Returns [RendererSuccess::Ok] with no capabilities when successful.
+    SetClearColor { color: Color },
+
+    /// Updates the tone mapping applied to the final image.
+    ///
+    /// Returns [RendererSuccess::Ok] with no capabilities when successful.
+    /// Returns [RendererError::InvalidRequest] if `exposure` is not a positive,
+    /// finite number.
+    SetToneMapping {
+        /// The tone mapping operator to apply.
+        operator: ToneMapOperator,
+
+        /// A linear multiplier applied to scene colors before tone mapping.
+        exposure: f32,
+    },
+
+    /// Updates the camera that the scene is viewed from.
+    ///
+    /// This camera is used for every frame until it is changed again, unless
+    /// the window supplies a camera of its own.
+    ///
+    /// Returns [RendererSuccess::Ok] with no capabilities when successful.
+    /// Returns [RendererError::InvalidRequest] if the projection is
+    /// perspective and `fov` is not a positive, finite number.
+    SetCamera {
+        /// The camera's view matrix.
+        view: Mat4,
+
+        /// How the scene is projected onto the camera.
+        projection_mode: ProjectionMode,
+
+        /// Vertical field of view in degrees. Only used by perspective
+        /// projection.
+        fov: f32,
+    },
+
+    /// Captures the next frame that is rendered.
+    ///
+    /// Returns [RendererSuccess::Captured] with the lump ID of the frame
+    /// encoded as a PNG image when successful. Returns
+    /// [RendererError::CaptureFailed] if the frame could not be read back.
+    Capture,
+}
+
+/// All renderer requests only carry the reply capability for the response.
+impl CapProtocol for RendererRequest {
+    fn expected_caps(&self) -> CapSpec {
+        CapSpec::exact(&["reply"])
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -74,16 +166,60 @@ pub enum RendererSuccess {
     ///
     /// Capabilities returned by this response are defined by the request kind.
     Ok,
+
+    /// A frame was captured and uploaded as a PNG image to this lump.
+    Captured(LumpId),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum RendererError {
-    /// A lump involved in this operation was improperly formatted or not found.
-    LumpError,
+    /// A lump involved in this operation was not found.
+    NotFound,
+
+    /// A lump involved in this operation could not be decoded into its asset
+    /// data format.
+    DecodeError,
+
+    /// A lump involved in this operation was decoded, but its data was
+    /// invalid, such as a texture whose data doesn't match its size.
+    InvalidFormat,
+
+    /// The parameters of the request were invalid.
+    InvalidRequest,
+
+    /// A frame could not be captured.
+    CaptureFailed,
 }
 
 pub type RendererResponse = Result<RendererSuccess, RendererError>;
 
+/// How a camera projects the scene.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ProjectionMode {
+    /// Perspective projection with the given near plane distance. All
+    /// perspective projection uses an infinite far plane.
+    Perspective { near: f32 },
+
+    /// Orthographic projection of a box of the given size.
+    Orthographic { size: Vec3 },
+}
+
+/// An operator for mapping high dynamic range scene colors to the display.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ToneMapOperator {
+    /// Colors are clamped to the display range without any curve applied.
+    ///
+    /// This is the default, and matches the renderer's original output.
+    #[default]
+    None,
+
+    /// The simple Reinhard operator: `c / (c + 1)`.
+    Reinhard,
+
+    /// Krzysztof Narkowicz's fitted approximation of the ACES filmic curve.
+    Aces,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct DirectionalLightState {
     pub color: Vec3,
@@ -100,9 +236,30 @@ pub enum DirectionalLightUpdate {
     Distance(f32),
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PointLightState {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+
+    /// The distance from the light's position beyond which it has no effect.
+    pub range: f32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum PointLightUpdate {
+    Position(Vec3),
+    Color(Vec3),
+    Intensity(f32),
+    Range(f32),
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum ObjectUpdate {
     Transform(Mat4),
+
+    /// Shows or hides this object without releasing its resources.
+    SetVisible(bool),
     JointMatrices(Vec<Mat4>),
     JointTransforms {
         joint_global: Vec<Mat4>,
@@ -115,6 +272,41 @@ pub enum ObjectUpdate {
 pub struct MaterialData {
     /// The lump ID of the [TextureData] to use for the material's albedo.
     pub albedo: LumpId,
+
+    /// The metalness of the material, from 0 to 1.
+    #[serde(default)]
+    pub metallic: Option<f32>,
+
+    /// The roughness of the material, from 0 to 1.
+    #[serde(default)]
+    pub roughness: Option<f32>,
+
+    /// The lump ID of an optional tangent-space normal map [TextureData].
+    #[serde(default)]
+    pub normal_map: Option<LumpId>,
+
+    /// The linear color of light emitted by this material.
+    #[serde(default)]
+    pub emissive: Option<Vec3>,
+
+    /// How the albedo's alpha channel affects the material's transparency.
+    #[serde(default)]
+    pub alpha_mode: AlphaMode,
+}
+
+/// How a material's alpha channel is interpreted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum AlphaMode {
+    /// Alpha is ignored and the material is fully opaque.
+    #[default]
+    Opaque,
+
+    /// Fragments with an alpha below `cutoff` are discarded and the rest are
+    /// fully opaque.
+    Mask { cutoff: f32 },
+
+    /// The material is blended with what is behind it.
+    Blend,
 }
 
 /// A mesh lump's data format.
@@ -161,8 +353,89 @@ pub struct TextureData {
     /// The size of this texture.
     pub size: UVec2,
 
-    /// The data of this texture. Currently only supports RGBA sRGB. Must be
-    /// a size equivalent to `size.x * size.y * 4`.
+    /// The data of this texture, in the layout given by `format`. Must be the
+    /// length given by [TextureFormat::data_len].
     #[serde_as(as = "Base64")]
     pub data: Vec<u8>,
+
+    /// The format of this texture's data.
+    #[serde(default)]
+    pub format: TextureFormat,
+
+    /// Whether to generate a full chain of mipmaps for this texture when it's
+    /// loaded. Not supported for block-compressed formats.
+    #[serde(default)]
+    pub generate_mips: bool,
+
+    /// How this texture is filtered when it's sampled.
+    ///
+    /// Materials sample all of their textures using the mode of their albedo
+    /// texture. Textures always repeat outside of their bounds.
+    #[serde(default)]
+    pub sampling: TextureSampling,
+}
+
+/// How a texture is filtered when it's sampled.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TextureSampling {
+    /// Texels and mipmaps are blended together. Best for most textures.
+    #[default]
+    Linear,
+
+    /// The nearest texel is used without blending. Best for pixel art.
+    Nearest,
+}
+
+/// The pixel format of a [TextureData].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TextureFormat {
+    /// Four 8-bit channels in sRGB. Best for color textures.
+    #[default]
+    Rgba8UnormSrgb,
+
+    /// Four linear 8-bit channels. Best for normal maps and other data.
+    Rgba8Unorm,
+
+    /// Two linear 8-bit channels.
+    Rg8Unorm,
+
+    /// One linear 8-bit channel. Best for grayscale masks.
+    R8Unorm,
+
+    /// BC7-compressed RGBA in sRGB, in blocks of 4x4 pixels.
+    Bc7RgbaUnormSrgb,
+
+    /// BC7-compressed linear RGBA, in blocks of 4x4 pixels.
+    Bc7RgbaUnorm,
+}
+
+impl TextureFormat {
+    /// The width and height in pixels of a single block of this format.
+    pub fn block_dimensions(&self) -> u32 {
+        use TextureFormat::*;
+        match self {
+            Rgba8UnormSrgb | Rgba8Unorm | Rg8Unorm | R8Unorm => 1,
+            Bc7RgbaUnormSrgb | Bc7RgbaUnorm => 4,
+        }
+    }
+
+    /// The size in bytes of a single block of this format.
+    pub fn bytes_per_block(&self) -> usize {
+        use TextureFormat::*;
+        match self {
+            Rgba8UnormSrgb | Rgba8Unorm => 4,
+            Rg8Unorm => 2,
+            R8Unorm => 1,
+            Bc7RgbaUnormSrgb | Bc7RgbaUnorm => 16,
+        }
+    }
+
+    /// The length in bytes of a single 2D image of this format with the given
+    /// size in pixels. Partial blocks at the edges are rounded up.
+    pub fn data_len(&self, size: UVec2) -> usize {
+        let block = self.block_dimensions();
+        let blocks_x = size.x.div_ceil(block) as usize;
+        let blocks_y = size.y.div_ceil(block) as usize;
+        blocks_x * blocks_y * self.bytes_per_block()
+    }
 }
@@ -27,6 +27,58 @@ use crate::Color;
 pub enum FactoryError {
     /// The request has failed to parse.
     ParseError,
+
+    /// The requested command has an empty program path.
+    InvalidCommand,
+}
+
+/// The base colors of a terminal.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct TerminalPalette {
+    /// The 16 ANSI colors: the 8 normal colors followed by their bright
+    /// variants.
+    pub ansi: [Color; 16],
+
+    /// The default text color.
+    pub foreground: Color,
+
+    /// The default background color. Alpha is replaced by
+    /// [TerminalState::opacity].
+    pub background: Color,
+
+    /// The color of the cursor. Defaults to the foreground color.
+    pub cursor: Option<Color>,
+}
+
+impl Default for TerminalPalette {
+    /// A plain VGA-style palette with white text on a black background.
+    fn default() -> Self {
+        let c = Color::from_rgb;
+
+        Self {
+            ansi: [
+                c(0, 0, 0),       // black
+                c(187, 0, 0),     // red
+                c(0, 187, 0),     // green
+                c(187, 187, 0),   // yellow
+                c(0, 0, 187),     // blue
+                c(187, 0, 187),   // magenta
+                c(0, 187, 187),   // cyan
+                c(187, 187, 187), // white
+                c(85, 85, 85),    // bright black
+                c(255, 85, 85),   // bright red
+                c(85, 255, 85),   // bright green
+                c(255, 255, 85),  // bright yellow
+                c(85, 85, 255),   // bright blue
+                c(255, 85, 255),  // bright magenta
+                c(85, 255, 255),  // bright cyan
+                c(255, 255, 255), // bright white
+            ],
+            foreground: c(255, 255, 255),
+            background: c(0, 0, 0),
+            cursor: None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -37,7 +89,17 @@ pub struct TerminalState {
     pub opacity: f32,
     pub padding: Vec2,
     pub units_per_em: f32,
+
+    /// Overrides for individual colors in the palette, keyed by their
+    /// `alacritty_terminal` color index.
     pub colors: HashMap<usize, Color>,
+
+    /// Replaces the terminal's palette.
+    ///
+    /// If this is `None`, the terminal keeps its current palette, which
+    /// starts as the default palette of the terminal factory.
+    #[serde(default)]
+    pub palette: Option<TerminalPalette>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -45,11 +107,47 @@ pub enum TerminalUpdate {
     Quit,
     Input(String),
     State(TerminalState),
+
+    /// Scrolls the terminal's viewport by a number of lines.
+    ///
+    /// Positive values scroll up into the scrollback and negative values
+    /// scroll down towards the newest output. Scrolling stops at either end
+    /// of the scrollback.
+    Scroll(i32),
+
+    /// Sets the maximum number of lines kept in the scrollback.
+    ///
+    /// Shrinking the scrollback discards its oldest lines. The scrollback is
+    /// capped at 100,000 lines.
+    SetScrollbackLines(u32),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum FactoryRequest {
-    CreateTerminal(TerminalState),
+    /// Creates a new terminal.
+    ///
+    /// If a capability is passed after the reply address, it is sent a
+    /// [TerminalExit] when the terminal's command exits on its own.
+    CreateTerminal {
+        /// The initial state of the terminal.
+        state: TerminalState,
+
+        /// The program to run followed by its arguments.
+        ///
+        /// Defaults to the host's shell. Fails with
+        /// [FactoryError::InvalidCommand] if the program is empty.
+        #[serde(default)]
+        command: Option<Vec<String>>,
+    },
+}
+
+/// Sent to a terminal's exit listener when its command exits.
+///
+/// This is not sent when the terminal is quit with [TerminalUpdate::Quit].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TerminalExit {
+    /// The exit code of the command, if it is known.
+    pub code: Option<i32>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -0,0 +1,91 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{CapProtocol, CapSpec};
+
+/// A request to the sleep service to wait a number of seconds.
+///
+/// The first capability in the message is the reply capability, which is sent
+/// an empty message once the duration has elapsed.
+///
+/// Serialized transparently as the number of seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct SleepRequest(pub f32);
+
+impl CapProtocol for SleepRequest {
+    fn expected_caps(&self) -> CapSpec {
+        CapSpec::exact(&["reply"])
+    }
+}
+
+/// A request to the alarm service to wait until an absolute point in time.
+///
+/// The first capability in the message is the reply capability, which is sent
+/// an empty message once the deadline has passed. Deadlines that have already
+/// passed are replied to immediately.
+///
+/// Serialized transparently as the deadline in nanoseconds since the UNIX
+/// epoch, like the response of the UNIX time service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct AlarmRequest(pub u128);
+
+impl CapProtocol for AlarmRequest {
+    fn expected_caps(&self) -> CapSpec {
+        CapSpec::exact(&["reply"])
+    }
+}
+
+/// A request to a timer to wait a number of seconds since the end of its
+/// last wait.
+///
+/// The first capability in the message is the reply capability, which is sent
+/// an empty message once the timer's wait has elapsed.
+///
+/// Serialized transparently as the number of seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct TimerRequest(pub f32);
+
+impl CapProtocol for TimerRequest {
+    fn expected_caps(&self) -> CapSpec {
+        CapSpec::exact(&["reply"])
+    }
+}
+
+/// A request to the interval factory to start sending ticks at a fixed
+/// period in seconds.
+///
+/// The first capability in the message is the reply capability, which is sent
+/// an empty message every period until its route is closed. If the reply
+/// capability has the monitor permission, the interval stops as soon as the
+/// route closes, and otherwise it stops once a tick fails to send.
+///
+/// Serialized transparently as the period in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct IntervalRequest(pub f32);
+
+impl CapProtocol for IntervalRequest {
+    fn expected_caps(&self) -> CapSpec {
+        CapSpec::exact(&["reply"])
+    }
+}
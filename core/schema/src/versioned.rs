@@ -0,0 +1,208 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// A protocol whose messages are tagged with the version of the protocol
+/// that they were encoded with.
+///
+/// Usually implemented with `#[derive(HearthProtocol)]` from `hearth-macros`
+/// and a `#[protocol(version = N)]` attribute. The protocol's version must be
+/// incremented whenever a change would make older decoders misinterpret new
+/// messages, so that those decoders reject them instead.
+pub trait HearthProtocol: Serialize + DeserializeOwned {
+    /// The current version of this protocol.
+    const PROTOCOL_VERSION: u32;
+
+    /// The names of this protocol's variants, indexed by their discriminants.
+    const VARIANTS: &'static [&'static str];
+
+    /// Gets the stable discriminant of this message's variant.
+    ///
+    /// Discriminants are assigned in declaration order, so new variants must
+    /// be added at the end of the protocol.
+    fn discriminant(&self) -> u32;
+
+    /// Gets the name of this message's variant.
+    fn variant_name(&self) -> &'static str {
+        Self::VARIANTS[self.discriminant() as usize]
+    }
+
+    /// Encodes this message as JSON, tagged with [Self::PROTOCOL_VERSION].
+    fn encode_versioned(&self) -> Vec<u8> {
+        let versioned = Versioned {
+            version: Self::PROTOCOL_VERSION,
+            message: self,
+        };
+
+        serde_json::to_vec(&versioned).unwrap()
+    }
+
+    /// Decodes a message encoded with [Self::encode_versioned].
+    ///
+    /// Fails with [VersionError::Incompatible] if the message was encoded
+    /// with a newer version of this protocol.
+    fn try_decode_versioned(data: &[u8]) -> Result<Self, VersionError> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+
+        let header: Header =
+            serde_json::from_slice(data).map_err(|err| VersionError::Malformed(err.to_string()))?;
+
+        if header.version > Self::PROTOCOL_VERSION {
+            return Err(VersionError::Incompatible {
+                version: header.version,
+                supported: Self::PROTOCOL_VERSION,
+            });
+        }
+
+        let versioned: Versioned<Self> =
+            serde_json::from_slice(data).map_err(|err| VersionError::Malformed(err.to_string()))?;
+
+        Ok(versioned.message)
+    }
+}
+
+/// The wire format of a [HearthProtocol] message.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Versioned<T> {
+    /// The version of the protocol that the message was encoded with.
+    pub version: u32,
+
+    /// The message itself.
+    pub message: T,
+}
+
+/// An error in decoding a versioned message.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum VersionError {
+    /// The message was encoded with a newer version of the protocol.
+    Incompatible {
+        /// The version that the message was encoded with.
+        version: u32,
+
+        /// The newest version that the decoder supports.
+        supported: u32,
+    },
+
+    /// The message could not be parsed.
+    Malformed(String),
+}
+
+impl std::fmt::Display for VersionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionError::Incompatible { version, supported } => write!(
+                f,
+                "message has protocol version {version}, but only up to {supported} is supported"
+            ),
+            VersionError::Malformed(err) => write!(f, "malformed versioned message: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for VersionError {}
+
+#[cfg(test)]
+mod tests {
+    use hearth_macros::HearthProtocol;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize, HearthProtocol)]
+    #[protocol(version = 2)]
+    enum Current {
+        Unit,
+        Tuple(u32),
+        Struct { x: i32 },
+    }
+
+    /// A newer revision of [Current] from a peer that has been updated.
+    #[derive(Debug, PartialEq, Deserialize, Serialize, HearthProtocol)]
+    #[protocol(version = 3)]
+    enum Newer {
+        Unit,
+        Tuple(u32),
+        Struct { x: i32 },
+        Added,
+    }
+
+    #[test]
+    fn derived_discriminants() {
+        assert_eq!(Current::PROTOCOL_VERSION, 2);
+        assert_eq!(Current::VARIANTS, ["Unit", "Tuple", "Struct"]);
+        assert_eq!(Current::Unit.discriminant(), 0);
+        assert_eq!(Current::Tuple(5).discriminant(), 1);
+        assert_eq!(Current::Struct { x: -1 }.discriminant(), 2);
+        assert_eq!(Current::Struct { x: -1 }.variant_name(), "Struct");
+        assert_eq!(Newer::Added.discriminant(), 3);
+    }
+
+    #[test]
+    fn round_trip() {
+        for msg in [Current::Unit, Current::Tuple(7), Current::Struct { x: 3 }] {
+            let data = msg.encode_versioned();
+            assert_eq!(Current::try_decode_versioned(&data), Ok(msg));
+        }
+    }
+
+    #[test]
+    fn accepts_older_version() {
+        let data = serde_json::to_vec(&Versioned {
+            version: 1,
+            message: Current::Tuple(4),
+        })
+        .unwrap();
+
+        assert_eq!(Current::try_decode_versioned(&data), Ok(Current::Tuple(4)));
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        // even variants that the older decoder knows about are rejected
+        for msg in [Newer::Tuple(1), Newer::Added] {
+            let data = msg.encode_versioned();
+            assert_eq!(
+                Current::try_decode_versioned(&data),
+                Err(VersionError::Incompatible {
+                    version: 3,
+                    supported: 2,
+                })
+            );
+        }
+
+        let data = Current::Unit.encode_versioned();
+        assert_eq!(Newer::try_decode_versioned(&data), Ok(Newer::Unit));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let results = [
+            Current::try_decode_versioned(b"not json"),
+            Current::try_decode_versioned(br#""Unit""#),
+            Current::try_decode_versioned(br#"{"message":"Unit"}"#),
+            Current::try_decode_versioned(br#"{"version":2,"message":"Added"}"#),
+        ];
+
+        for result in results {
+            assert!(matches!(result, Err(VersionError::Malformed(_))));
+        }
+    }
+}
@@ -16,13 +16,14 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::LumpId;
+use crate::{CapProtocol, CapSpec, LumpId};
 use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
 
 /// A spawn message sent to the Wasm process spawner service.
 ///
-/// The service replies with a message containing the decimal representation of
-/// the new process's local process ID.
+/// The service replies with a [WasmSpawnResponse]. When successful, the reply
+/// carries a capability to the new process.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct WasmSpawnInfo {
     /// The [LumpId] of the Wasm module lump source.
@@ -31,4 +32,169 @@ pub struct WasmSpawnInfo {
     /// The identifier of the entrypoint to execute. If not specified, runs
     /// the exported "run" function.
     pub entrypoint: Option<u32>,
+
+    /// If set, waits for the new process to finish initializing before
+    /// replying, for at most this many seconds.
+    ///
+    /// The new process receives an additional capability after the request's
+    /// capability arguments, and signals that it is ready by sending a
+    /// [ProcessReady] message to it. If the process does not signal readiness
+    /// before the timeout, it is killed and no capability is replied with.
+    #[serde(default)]
+    pub ready_timeout: Option<f32>,
+
+    /// If set, kills the new process once it has executed this much fuel in
+    /// total.
+    ///
+    /// Fuel is roughly proportional to the number of Wasm instructions
+    /// executed. Spawning fails if the Wasm plugin does not meter fuel.
+    #[serde(default)]
+    pub fuel_limit: Option<u64>,
+
+    /// The scheduling priority of the new process. Defaults to zero.
+    ///
+    /// Higher priorities let a process run for longer before it yields to
+    /// others. The host may cap the priority to a maximum of its own.
+    #[serde(default)]
+    pub priority: u32,
+}
+
+/// Sent by a newly spawned process to signal that it has finished
+/// initializing and is ready to receive messages.
+///
+/// See [WasmSpawnInfo::ready_timeout].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProcessReady;
+
+/// An error in response to a [WasmSpawnInfo].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WasmSpawnError {
+    /// The module imports items that the host does not provide, listed as
+    /// `module::name`.
+    ///
+    /// This usually means that the guest was built against a different
+    /// version of the host ABI.
+    MissingImports(Vec<String>),
+
+    /// The module could not be loaded or compiled.
+    LoadFailed(String),
+
+    /// The module could not be instantiated.
+    InstantiateFailed(String),
+
+    /// The module's metadata could not be retrieved.
+    MetadataFailed(String),
+
+    /// The process could not be spawned for another reason.
+    SpawnFailed(String),
+}
+
+/// A response to a [WasmSpawnInfo].
+pub type WasmSpawnResponse = Result<(), WasmSpawnError>;
+
+/// A request to the Wasm process controller service.
+///
+/// All variants require that a reply cap is the first capability in the
+/// message, followed by a capability to the target Wasm process with the kill
+/// permission, such as the one returned by the Wasm spawner. The service
+/// replies with a [WasmControlResponse].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WasmControlRequest {
+    /// Stops scheduling the target Wasm process.
+    ///
+    /// The process is suspended the next time its time slice expires while it
+    /// is executing Wasm code. A process that is blocked on a host call, such
+    /// as waiting on a mailbox, is not suspended until that call returns.
+    /// Suspended processes can still be killed.
+    ///
+    /// Returns [WasmControlSuccess::Ok].
+    Suspend,
+
+    /// Resumes a suspended Wasm process.
+    ///
+    /// Returns [WasmControlSuccess::Ok].
+    Resume,
+
+    /// Retrieves a snapshot of a suspended Wasm process, taken at the moment
+    /// that it was suspended.
+    ///
+    /// Returns [WasmControlSuccess::Snapshot], or [WasmControlError::NotSuspended]
+    /// if the suspension has not taken effect yet.
+    Snapshot,
+}
+
+impl CapProtocol for WasmControlRequest {
+    fn expected_caps(&self) -> CapSpec {
+        CapSpec::exact(&["reply", "process"])
+    }
+}
+
+/// A successful response to a [WasmControlRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WasmControlSuccess {
+    /// The request succeeded.
+    Ok,
+
+    /// The requested snapshot.
+    Snapshot(WasmProcessSnapshot),
+}
+
+/// An error in response to a [WasmControlRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WasmControlError {
+    /// The target capability is not to a running Wasm process or does not
+    /// have the kill permission.
+    NoSuchProcess,
+
+    /// The process has not been suspended yet.
+    NotSuspended,
+}
+
+/// A response to a [WasmControlRequest].
+pub type WasmControlResponse = Result<WasmControlSuccess, WasmControlError>;
+
+/// A captured state of a suspended Wasm process.
+///
+/// Only the guest's linear memory, the signals it has received, and the
+/// signals queued in its mailboxes are captured. The Wasm call stack, unexported globals, and the process's
+/// capability table cannot be captured. Capability handles stored in the
+/// captured memory would refer to unrelated capabilities in a new process, so
+/// snapshots are only for inspecting a process and can't be restored.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WasmProcessSnapshot {
+    /// The [LumpId] of the Wasm module lump source.
+    pub lump: LumpId,
+
+    /// The entrypoint that the process was spawned with.
+    pub entrypoint: Option<u32>,
+
+    /// The contents of the guest's linear memory.
+    #[serde_as(as = "Base64")]
+    pub memory: Vec<u8>,
+
+    /// The signals received by the guest but not yet freed, by guest handle.
+    pub signals: Vec<(u32, SignalSnapshot)>,
+
+    /// The signals queued in each mailbox but not yet received by the guest,
+    /// in the order that they'll be received, by mailbox handle. The parent
+    /// mailbox's handle is 0.
+    pub queued: Vec<(u32, Vec<SignalSnapshot>)>,
+}
+
+/// A captured signal in a [WasmProcessSnapshot].
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum SignalSnapshot {
+    /// A down signal. The monitored capability is not captured.
+    Down,
+
+    /// A message signal. The message's capabilities are not captured.
+    Message {
+        #[serde_as(as = "Base64")]
+        data: Vec<u8>,
+    },
+
+    /// A shutdown request.
+    Shutdown,
 }
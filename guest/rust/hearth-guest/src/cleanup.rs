@@ -0,0 +1,168 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Cleanup of guest-owned resources when a process panics.
+//!
+//! Guests are built for `wasm32-unknown-unknown`, which only supports the
+//! `abort` panic strategy. When a guest panics, the panic hook installed by
+//! this crate logs the panic, and then the Wasm instance traps. The stack is
+//! never unwound, so no [Drop] implementations of values living on the stack
+//! are ever run.
+//!
+//! The host frees all of a process's capability and mailbox handles when the
+//! process ends, so handles themselves never leak. However, many guest-side
+//! wrappers perform side effects in [Drop], such as killing a child process
+//! or telling a service to destroy a resource. Those side effects are skipped
+//! on panic unless they are registered here.
+//!
+//! Use [on_panic] to register a cleanup callback that runs from the panic hook
+//! before the process traps. The returned [PanicCleanup] unregisters the
+//! callback when it is dropped, so it can be stored alongside the resource it
+//! cleans up. Callbacks run in reverse order of registration, mimicking the
+//! order that an unwinding stack would have dropped its values in.
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use crate::Capability;
+
+/// A registered panic cleanup callback.
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// The registry of panic cleanup callbacks, keyed by registration order.
+static CALLBACKS: Mutex<(usize, BTreeMap<usize, Callback>)> = Mutex::new((0, BTreeMap::new()));
+
+/// A handle to a registered panic cleanup callback.
+///
+/// Dropping this handle unregisters the callback without running it.
+#[must_use = "the cleanup callback is unregistered when this is dropped"]
+#[derive(Debug)]
+pub struct PanicCleanup {
+    id: usize,
+}
+
+impl Drop for PanicCleanup {
+    fn drop(&mut self) {
+        if let Ok(mut callbacks) = CALLBACKS.lock() {
+            callbacks.1.remove(&self.id);
+        }
+    }
+}
+
+impl PanicCleanup {
+    /// Unregisters this callback and returns it without running it.
+    ///
+    /// Returns `None` if the callback has already been run.
+    pub fn cancel(self) -> Option<Box<dyn FnOnce() + Send>> {
+        CALLBACKS.lock().ok()?.1.remove(&self.id)
+    }
+}
+
+/// Registers a callback to be run if this process panics.
+///
+/// The callback is unregistered when the returned [PanicCleanup] is dropped.
+pub fn on_panic(cb: impl FnOnce() + Send + 'static) -> PanicCleanup {
+    let mut callbacks = CALLBACKS.lock().unwrap();
+    let id = callbacks.0;
+    callbacks.0 += 1;
+    callbacks.1.insert(id, Box::new(cb));
+    PanicCleanup { id }
+}
+
+/// Registers a capability to be killed if this process panics.
+///
+/// Shorthand for calling [Capability::kill] from [on_panic].
+pub fn kill_on_panic(cap: &Capability) -> PanicCleanup {
+    let cap = cap.clone();
+    on_panic(move || cap.kill())
+}
+
+/// Runs all registered panic cleanup callbacks. Called from the panic hook.
+pub(crate) fn run_panic_cleanup() {
+    // take the callbacks out of the registry before running them so that
+    // callbacks may safely drop their own [PanicCleanup] handles
+    let callbacks = match CALLBACKS.lock() {
+        Ok(mut callbacks) => std::mem::take(&mut callbacks.1),
+        Err(_) => return,
+    };
+
+    for (_id, cb) in callbacks.into_iter().rev() {
+        cb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, MutexGuard};
+
+    use super::*;
+
+    /// Serializes tests, since they all share the global callback registry.
+    fn lock() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        LOCK.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Registers a callback that pushes `id` to `log` when it's run.
+    fn push_on_panic(log: &Arc<Mutex<Vec<u32>>>, id: u32) -> PanicCleanup {
+        let log = log.clone();
+        on_panic(move || log.lock().unwrap().push(id))
+    }
+
+    #[test]
+    fn runs_in_reverse_order() {
+        let _lock = lock();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let _a = push_on_panic(&log, 1);
+        let _b = push_on_panic(&log, 2);
+        let _c = push_on_panic(&log, 3);
+        run_panic_cleanup();
+        assert_eq!(*log.lock().unwrap(), [3, 2, 1]);
+    }
+
+    #[test]
+    fn runs_only_once() {
+        let _lock = lock();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let handle = push_on_panic(&log, 1);
+        run_panic_cleanup();
+        run_panic_cleanup();
+        assert_eq!(*log.lock().unwrap(), [1]);
+        assert!(handle.cancel().is_none());
+    }
+
+    #[test]
+    fn drop_unregisters() {
+        let _lock = lock();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let _a = push_on_panic(&log, 1);
+        drop(push_on_panic(&log, 2));
+        run_panic_cleanup();
+        assert_eq!(*log.lock().unwrap(), [1]);
+    }
+
+    #[test]
+    fn cancel_returns_callback() {
+        let _lock = lock();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let cb = push_on_panic(&log, 1).cancel().unwrap();
+        run_panic_cleanup();
+        assert!(log.lock().unwrap().is_empty());
+        cb();
+        assert_eq!(*log.lock().unwrap(), [1]);
+    }
+}
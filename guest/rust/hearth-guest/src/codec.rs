@@ -0,0 +1,82 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Serialization formats for message payloads.
+//!
+//! Message data is opaque bytes to the host, so the format of a message is
+//! purely an agreement between the processes exchanging it. JSON is used by
+//! default and by all of the native services, but processes exchanging large
+//! or numeric-heavy payloads between themselves may prefer a binary format.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A serialization format for message payloads.
+///
+/// Used with [Capability::send_with][crate::Capability::send_with] and
+/// [Mailbox::recv_with][crate::Mailbox::recv_with].
+pub trait Codec {
+    /// The error returned when encoding fails.
+    type EncodeError: std::fmt::Debug;
+
+    /// The error returned when decoding fails.
+    type DecodeError: std::fmt::Debug;
+
+    /// Encodes a value into a message payload.
+    fn encode(data: &impl Serialize) -> Result<Vec<u8>, Self::EncodeError>;
+
+    /// Decodes a value from a message payload.
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, Self::DecodeError>;
+}
+
+/// The JSON format. Used by [Capability::send][crate::Capability::send] and
+/// [Mailbox::recv][crate::Mailbox::recv].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    type EncodeError = serde_json::Error;
+    type DecodeError = serde_json::Error;
+
+    fn encode(data: &impl Serialize) -> Result<Vec<u8>, Self::EncodeError> {
+        serde_json::to_vec(data)
+    }
+
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, Self::DecodeError> {
+        serde_json::from_slice(data)
+    }
+}
+
+/// The [MessagePack](https://msgpack.org) format.
+///
+/// Structs are encoded as maps with named fields, so that payloads remain
+/// compatible with types that also round-trip through JSON.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsgpackCodec;
+
+impl Codec for MsgpackCodec {
+    type EncodeError = rmp_serde::encode::Error;
+    type DecodeError = rmp_serde::decode::Error;
+
+    fn encode(data: &impl Serialize) -> Result<Vec<u8>, Self::EncodeError> {
+        rmp_serde::to_vec_named(data)
+    }
+
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, Self::DecodeError> {
+        rmp_serde::from_slice(data)
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Loading of structured configuration files.
+//!
+//! Configuration files are read through a capability to a filesystem service
+//! (see [hearth_schema::fs]) and deserialized according to the format implied
+//! by their extension. See [ConfigFormat] for the supported formats.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use serde::de::DeserializeOwned;
+
+use crate::{fs, request::Request, Capability, Lump};
+
+/// A file format that configuration can be loaded from.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// TOML, detected by the `.toml` extension.
+    Toml,
+
+    /// JSON, detected by the `.json` extension.
+    Json,
+
+    /// YAML, detected by the `.yaml` or `.yml` extensions.
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Detects the format of a config file by its path's extension.
+    pub fn from_path(path: &str) -> Result<Self, ConfigError> {
+        let extension = path
+            .rsplit_once('/')
+            .map(|(_, name)| name)
+            .unwrap_or(path)
+            .rsplit_once('.')
+            .map(|(_, extension)| extension)
+            .unwrap_or_default();
+
+        match extension {
+            "toml" => Ok(Self::Toml),
+            "json" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            _ => Err(ConfigError::UnsupportedFormat(path.to_string())),
+        }
+    }
+
+    /// Deserializes config data in this format.
+    pub fn parse<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, ConfigError> {
+        match self {
+            Self::Toml => {
+                let text = std::str::from_utf8(data).map_err(ConfigError::Utf8)?;
+                toml::from_str(text).map_err(ConfigError::Toml)
+            }
+            Self::Json => serde_json::from_slice(data).map_err(ConfigError::Json),
+            Self::Yaml => serde_yaml::from_slice(data).map_err(ConfigError::Yaml),
+        }
+    }
+}
+
+/// An error that occurred while loading a config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The filesystem service failed to retrieve the file.
+    Fs(fs::Error),
+
+    /// The format of the file at the given path could not be detected.
+    UnsupportedFormat(String),
+
+    /// A text-based config file was not valid UTF-8.
+    Utf8(std::str::Utf8Error),
+
+    /// A TOML config file failed to parse.
+    Toml(toml::de::Error),
+
+    /// A JSON config file failed to parse.
+    Json(serde_json::Error),
+
+    /// A YAML config file failed to parse.
+    Yaml(serde_yaml::Error),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            ConfigError::Fs(err) => write!(f, "failed to read config file: {:?}", err),
+            ConfigError::UnsupportedFormat(path) => write!(
+                f,
+                "unsupported config format for {:?} (expected .toml, .json, .yaml, or .yml)",
+                path
+            ),
+            ConfigError::Utf8(err) => write!(f, "config file is not valid UTF-8: {}", err),
+            ConfigError::Toml(err) => write!(f, "invalid TOML config: {}", err),
+            ConfigError::Json(err) => write!(f, "invalid JSON config: {}", err),
+            ConfigError::Yaml(err) => write!(f, "invalid YAML config: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Loads and deserializes a config file from a filesystem service.
+///
+/// `filesystem` is a capability to a service implementing the
+/// [hearth_schema::fs] protocol. The format of the file is detected from the
+/// extension of `path` using [ConfigFormat::from_path].
+pub fn load<T: DeserializeOwned>(filesystem: &Capability, path: &str) -> Result<T, ConfigError> {
+    // detect the format first to avoid a needless request
+    let format = ConfigFormat::from_path(path)?;
+
+    let request = fs::Request {
+        target: path.to_string(),
+        kind: fs::RequestKind::Get,
+    };
+
+    let response: fs::Response = Request::new(filesystem).with_data(&request).call();
+
+    let lump = match response.map_err(ConfigError::Fs)? {
+        fs::Success::Get(lump) => lump,
+        other => panic!("expected Success::Get, got {:?}", other),
+    };
+
+    let data = Lump::load_by_id(&lump).get_data();
+    format.parse(&data)
+}
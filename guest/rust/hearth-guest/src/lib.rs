@@ -20,15 +20,30 @@
 
 #![warn(missing_docs)]
 
+pub mod cleanup;
+pub mod codec;
+pub mod config;
+pub mod request;
+pub mod sync;
+
 mod subscriber;
 
 use std::borrow::Borrow;
+use std::cell::Cell;
+use std::time::Duration;
 
+use codec::{Codec, JsonCodec};
 use serde::{Deserialize, Serialize};
 
 pub use hearth_schema::*;
 use subscriber::ProcessSubscriber;
 
+/// The version of the host ABI that this crate is built against.
+///
+/// This is declared to the host by [export_metadata], and the host refuses to
+/// spawn processes whose ABI version doesn't match its own.
+pub const ABI_VERSION: u32 = 1;
+
 /// Internal helper function to turn a string into a pointer and length.
 fn abi_string(str: &str) -> (u32, u32) {
     let bytes = str.as_bytes();
@@ -37,6 +52,61 @@ fn abi_string(str: &str) -> (u32, u32) {
     (ptr, len)
 }
 
+/// Signals to this process's spawner that it has finished initializing.
+///
+/// `ready` is the capability that the spawner passed after its other initial
+/// capabilities when spawned with [wasm::WasmSpawnInfo::ready_timeout].
+pub fn signal_ready(ready: &Capability) {
+    ready.send(&wasm::ProcessReady, &[]);
+}
+
+/// The Wasm process spawner service, used by [spawn_wasm].
+static WASM_SPAWNER: sync::ServiceHandle =
+    sync::ServiceHandle::new("hearth.wasm.WasmProcessSpawner");
+
+/// Spawns a Wasm module lump as a new process and returns a capability to it.
+///
+/// `entrypoint` selects the function to run like
+/// [wasm::WasmSpawnInfo::entrypoint]. The new process receives
+/// `initial_caps` in its first message.
+///
+/// Panics if the spawner service is unavailable or if the process fails to
+/// spawn.
+pub fn spawn_wasm(
+    lump: &LumpId,
+    entrypoint: Option<u32>,
+    initial_caps: &[&Capability],
+) -> Capability {
+    let info = wasm::WasmSpawnInfo {
+        lump: *lump,
+        entrypoint,
+        ready_timeout: None,
+        fuel_limit: None,
+        priority: 0,
+    };
+
+    spawn_wasm_with(&info, initial_caps)
+}
+
+/// Spawns a Wasm process with the given [wasm::WasmSpawnInfo] and returns a
+/// capability to it, like [spawn_wasm].
+///
+/// Use this to set options like the process's scheduling priority.
+pub fn spawn_wasm_with(info: &wasm::WasmSpawnInfo, initial_caps: &[&Capability]) -> Capability {
+    let spawner = WASM_SPAWNER.expect();
+
+    let (response, mut caps) = request::Request::new(&spawner)
+        .with_data(info)
+        .with_caps(initial_caps)
+        .call_with_caps::<wasm::WasmSpawnResponse>();
+
+    if let Err(err) = response {
+        panic!("failed to spawn Wasm process: {:?}", err);
+    }
+
+    caps.remove(0)
+}
+
 /// Fetches the lump ID of the module used to spawn the current process.
 pub fn this_lump() -> LumpId {
     // load lump ID from the host
@@ -45,12 +115,54 @@ pub fn this_lump() -> LumpId {
     id
 }
 
+/// Fills a buffer with cryptographically secure random bytes from the host.
+///
+/// Only available once the process is running, so this must not be called
+/// while the process's metadata is being retrieved.
+pub fn fill_random(dst: &mut [u8]) {
+    unsafe { abi::random::fill(dst.as_mut_ptr() as u32, dst.len() as u32) }
+}
+
+/// Sets a value in this process's host-side key-value store.
+///
+/// The store is private to this process and is cleared when it exits. Returns
+/// false if the value doesn't fit within the store's size limit.
+pub fn kv_set(key: &str, value: &[u8]) -> bool {
+    let (key_ptr, key_len) = abi_string(key);
+    let val_ptr = value.as_ptr() as u32;
+    let val_len = value.len() as u32;
+    unsafe { abi::kv::set(key_ptr, key_len, val_ptr, val_len) != 0 }
+}
+
+/// Gets a value from this process's key-value store, if it's set.
+pub fn kv_get(key: &str) -> Option<Vec<u8>> {
+    let (key_ptr, key_len) = abi_string(key);
+
+    let len = unsafe { abi::kv::get(key_ptr, key_len, 0, 0) };
+    if len == u32::MAX {
+        return None;
+    }
+
+    let mut value = vec![0u8; len as usize];
+    unsafe { abi::kv::get(key_ptr, key_len, value.as_mut_ptr() as u32, len) };
+    Some(value)
+}
+
+/// Removes a value from this process's key-value store. Returns true if it
+/// was set.
+pub fn kv_delete(key: &str) -> bool {
+    let (key_ptr, key_len) = abi_string(key);
+    unsafe { abi::kv::delete(key_ptr, key_len) != 0 }
+}
+
 /// An integer handle to a capability to a route.
 ///
-/// If two capabilities are to the same route and have the same permissions,
-/// then testing their equality (`cap1 == cap2`) will evaluate to true.
-/// However, if the permissions are different on either capability, they will
-/// never be identical.
+/// Testing the equality of two capabilities (`cap1 == cap2`) only compares
+/// their handles. Equal handles always refer to the same route with the same
+/// permissions. The host currently reuses handles for identical capabilities,
+/// so unequal handles usually mean different capabilities, but guests should
+/// not rely on this: use [Capability::equals] to authoritatively compare two
+/// capabilities, or [Capability::same_route] to ignore their permissions.
 ///
 /// Capability handles are reference-counted, so you can clone and drop this
 /// type to increase and decrease the reference count of this capability in the
@@ -82,12 +194,22 @@ impl Capability {
 
     /// Sends a type, serialized as JSON, to this capability.
     pub fn send(&self, data: &impl Serialize, caps: &[&Capability]) {
-        let json_msg = serde_json::to_string(data).unwrap();
-        let bytes_msg = json_msg.into_bytes();
-        self.send_raw(&bytes_msg, &caps);
+        self.send_with::<JsonCodec>(data, caps);
+    }
+
+    /// Sends a type, serialized with the given [Codec], to this capability.
+    ///
+    /// Panics if serialization fails.
+    pub fn send_with<C: Codec>(&self, data: &impl Serialize, caps: &[&Capability]) {
+        let bytes_msg = C::encode(data).unwrap();
+        self.send_raw(&bytes_msg, caps);
     }
 
     /// Sends a raw message to this capability.
+    ///
+    /// The host limits how many capabilities can be attached to one message
+    /// ([DEFAULT_MAX_MESSAGE_CAPS] unless the runtime is configured
+    /// otherwise) and kills this process if a message exceeds it.
     pub fn send_raw(&self, data: &[u8], caps: &[&Capability]) {
         let caps: Vec<u32> = caps.iter().map(|cap| (*cap).borrow().0).collect();
         unsafe {
@@ -101,21 +223,104 @@ impl Capability {
         }
     }
 
+    /// Sends the same JSON-serialized message to multiple capabilities.
+    ///
+    /// The message is delivered to each target in the order that they're
+    /// given, in a single host call, so no other messages from this process
+    /// are interleaved between them.
+    ///
+    /// Panics if serialization fails.
+    pub fn broadcast(targets: &[&Capability], data: &impl Serialize, caps: &[&Capability]) {
+        let bytes_msg = JsonCodec::encode(data).unwrap();
+        Self::broadcast_raw(targets, &bytes_msg, caps);
+    }
+
+    /// Sends the same raw message to multiple capabilities, like
+    /// [Capability::broadcast].
+    pub fn broadcast_raw(targets: &[&Capability], data: &[u8], caps: &[&Capability]) {
+        let targets: Vec<u32> = targets.iter().map(|cap| cap.0).collect();
+        let caps: Vec<u32> = caps.iter().map(|cap| (*cap).borrow().0).collect();
+        unsafe {
+            abi::table::send_many(
+                targets.as_ptr() as u32,
+                targets.len() as u32,
+                data.as_ptr() as u32,
+                data.len() as u32,
+                caps.as_ptr() as u32,
+                caps.len() as u32,
+            );
+        }
+    }
+
+    /// Asks this capability's process to shut down gracefully.
+    ///
+    /// Wasm processes receive [Signal::Shutdown] on their parent mailbox and
+    /// are killed if they haven't exited within a few seconds. Other processes
+    /// are killed right away. Requires the kill permission.
+    pub fn shutdown(&self) {
+        unsafe { abi::table::shutdown(self.0) }
+    }
+
     /// Kills this capability.
     pub fn kill(&self) {
         unsafe { abi::table::kill(self.0) }
     }
 
     /// Demotes this capability to a capability with fewer permissions.
+    ///
+    /// Demoting to the same permissions that this capability already has is
+    /// cheap: the host shares this capability's handle instead of creating a
+    /// new one.
     pub fn demote(&self, new_perms: Permissions) -> Capability {
         let handle = unsafe { abi::table::demote(self.0, new_perms.bits()) };
         Capability(handle)
     }
 
+    /// Sends a copy of this capability without the given permissions to
+    /// another capability, in a message with no data.
+    ///
+    /// Permissions in `strip` that this capability already lacks are ignored.
+    pub fn forward(&self, to: &Capability, strip: Permissions) {
+        let forwarded = self.demote(self.get_flags().difference(strip));
+        to.send_raw(&[], &[&forwarded]);
+    }
+
     /// Gets the permission flags for this capability.
     pub fn get_flags(&self) -> Permissions {
         Permissions::from_bits_retain(unsafe { abi::table::get_permissions(self.0) })
     }
+
+    /// Checks if this capability has all of the given permissions.
+    pub fn has(&self, perms: Permissions) -> bool {
+        self.get_flags().contains(perms)
+    }
+
+    /// Checks if this capability has all of the given permissions, failing
+    /// with the permissions that it lacks if it doesn't.
+    pub fn require(&self, perms: Permissions) -> Result<(), MissingPermissions> {
+        let missing = perms.difference(self.get_flags());
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingPermissions(missing))
+        }
+    }
+
+    /// Asks the host if this capability is to the same route as another,
+    /// regardless of either capability's permissions or handles.
+    ///
+    /// Useful for coalescing equivalent capabilities that were received in
+    /// different messages, such as when building a routing table.
+    pub fn same_route(&self, other: &Capability) -> bool {
+        self.0 == other.0 || unsafe { abi::table::same_route(self.0, other.0) } != 0
+    }
+
+    /// Asks the host if this capability is to the same route and has the same
+    /// permissions as another, regardless of their handles.
+    pub fn equals(&self, other: &Capability) -> bool {
+        self.0 == other.0 || (self.get_flags() == other.get_flags() && self.same_route(other))
+    }
 }
 
 /// A signal.
@@ -125,10 +330,22 @@ pub enum Signal {
     Down {
         /// A capability to the monitored route with no permissions.
         subject: Capability,
+
+        /// The tag given to [Mailbox::monitor_tagged], or zero if the
+        /// subject was monitored with [Mailbox::monitor].
+        tag: u64,
     },
 
     /// A [Message] signal.
     Message(Message),
+
+    /// A request for this process to clean up and exit, sent with
+    /// [Capability::shutdown].
+    ///
+    /// Only the parent mailbox receives these. Messages can't be mistaken for
+    /// them. If the process doesn't exit soon after receiving this, it is
+    /// killed.
+    Shutdown,
 }
 
 impl Signal {
@@ -142,10 +359,12 @@ impl Signal {
         let signal = match kind {
             SignalKind::Message => Signal::Message(Message::load_from_handle(handle)),
             SignalKind::Down => {
+                let tag = abi::mailbox::get_down_tag(handle);
                 let handle = abi::mailbox::get_down_capability(handle);
                 let subject = Capability(handle);
-                Signal::Down { subject }
+                Signal::Down { subject, tag }
             }
+            SignalKind::Shutdown => Signal::Shutdown,
         };
 
         abi::mailbox::destroy_signal(handle);
@@ -154,6 +373,75 @@ impl Signal {
     }
 }
 
+/// What [Mailbox::messages_with] does when it receives a [Signal::Down].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum DownPolicy {
+    /// Discard the down signal and keep waiting for messages.
+    #[default]
+    Skip,
+
+    /// End the iteration.
+    Stop,
+
+    /// Panic, like [Mailbox::recv] does.
+    Panic,
+}
+
+/// The error returned by [Capability::require], containing the permissions
+/// that the capability was missing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MissingPermissions(pub Permissions);
+
+impl std::fmt::Display for MissingPermissions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "capability is missing permissions: ")?;
+        bitflags::parser::to_writer(&self.0, f)
+    }
+}
+
+impl std::error::Error for MissingPermissions {}
+
+/// The error returned by [Mailbox::recv_checked] and [Mailbox::try_recv_typed].
+#[derive(Debug)]
+pub enum RecvError {
+    /// A down signal was received instead of a message.
+    UnexpectedDown {
+        /// A capability to the monitored route with no permissions.
+        subject: Capability,
+    },
+
+    /// The message's data failed to deserialize from JSON.
+    Deserialize(serde_json::Error),
+
+    /// This process has been killed, so its mailboxes can no longer receive.
+    Killed,
+
+    /// A shutdown request was received instead of a message.
+    Shutdown,
+}
+
+impl std::fmt::Display for RecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecvError::UnexpectedDown { subject } => {
+                write!(f, "expected message, received down signal on {:?}", subject)
+            }
+            RecvError::Deserialize(err) => write!(f, "failed to deserialize message: {}", err),
+            RecvError::Killed => write!(f, "process has been killed"),
+            RecvError::Shutdown => write!(f, "expected message, received shutdown request"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+/// Returned by the host in place of a signal handle when there is no signal.
+const EMPTY_SIGNAL: u32 = u32::MAX;
+
+/// Returned by the host in place of a signal handle when this process has
+/// been killed.
+const KILLED_SIGNAL: u32 = u32::MAX - 1;
+
 /// An un-closeable mailbox that receives signals from the parent of this process.
 pub static PARENT: Mailbox = Mailbox(0);
 
@@ -186,6 +474,17 @@ impl Mailbox {
         Capability(handle)
     }
 
+    /// Make a capability to this process's [PARENT] mailbox with the given
+    /// permission flags.
+    ///
+    /// Because the parent mailbox is never destroyed, this capability can be
+    /// handed to other processes (such as this process's children's
+    /// children) to message this process for as long as it's alive.
+    pub fn parent_capability(perms: Permissions) -> Capability {
+        let handle = unsafe { abi::mailbox::make_self_capability(perms.bits()) };
+        Capability(handle)
+    }
+
     /// Observe a subject capability for when it becomes unavailable.
     ///
     /// When it does, this mailbox will receive [Signal::Down] with a
@@ -194,11 +493,62 @@ impl Mailbox {
         unsafe { abi::mailbox::monitor(self.0, subject.0) }
     }
 
+    /// Observe a subject capability like [Mailbox::monitor], but with a tag
+    /// to identify it by in the [Signal::Down] this mailbox receives.
+    ///
+    /// This lets a mailbox that monitors many subjects, like a supervisor's,
+    /// tell which of them went down without comparing capabilities. Monitoring
+    /// the same route again replaces its tag.
+    pub fn monitor_tagged(&self, subject: &Capability, tag: u64) {
+        unsafe { abi::mailbox::monitor_tagged(self.0, subject.0, tag) }
+    }
+
+    /// Link this process's lifetime to a subject capability's, so that when
+    /// either one exits, the other is killed.
+    ///
+    /// This mailbox monitors the subject, and this process is killed when it
+    /// receives the subject's down signal. The subject is killed when this
+    /// process exits. Destroying this mailbox removes the link. Requires the
+    /// subject to have both the [Permissions::MONITOR] and
+    /// [Permissions::KILL] permissions.
+    pub fn link(&self, subject: &Capability) {
+        unsafe { abi::mailbox::link(self.0, subject.0) }
+    }
+
     /// Wait for this mailbox to receive a [Signal].
+    ///
+    /// Panics if this process has been killed.
     pub fn recv_signal(&self) -> Signal {
+        match self.recv_signal_checked() {
+            Ok(signal) => signal,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Waits for this mailbox to receive a [Signal], returning
+    /// [RecvError::Killed] if this process has been killed.
+    fn recv_signal_checked(&self) -> Result<Signal, RecvError> {
         unsafe {
-            let handle = abi::mailbox::recv(self.0);
-            Signal::from_handle(handle)
+            match abi::mailbox::recv(self.0) {
+                KILLED_SIGNAL => Err(RecvError::Killed),
+                handle => Ok(Signal::from_handle(handle)),
+            }
+        }
+    }
+
+    /// Waits for this mailbox to receive a [Signal] for at most `timeout`.
+    ///
+    /// Returns `None` if no signal was received in time. Panics if this
+    /// process has been killed.
+    pub fn recv_signal_timeout(&self, timeout: Duration) -> Option<Signal> {
+        let timeout_us = timeout.as_micros().try_into().unwrap_or(u64::MAX);
+
+        unsafe {
+            match abi::mailbox::recv_timeout(self.0, timeout_us) {
+                EMPTY_SIGNAL => None,
+                KILLED_SIGNAL => panic!("{}", RecvError::Killed),
+                handle => Some(Signal::from_handle(handle)),
+            }
         }
     }
 
@@ -213,15 +563,72 @@ impl Mailbox {
         (index, signal)
     }
 
+    /// Returns a blocking iterator over every [Signal] this mailbox receives.
+    ///
+    /// The iterator never ends on its own.
+    pub fn signals(&self) -> impl Iterator<Item = Signal> + '_ {
+        std::iter::repeat_with(|| self.recv_signal())
+    }
+
+    /// Returns a blocking iterator over the JSON messages this mailbox
+    /// receives, skipping any down signals.
+    ///
+    /// Panics if deserialization fails. Equivalent to
+    /// [Self::messages_with] with [DownPolicy::Skip].
+    pub fn messages<T>(&self) -> impl Iterator<Item = (T, Vec<Capability>)> + '_
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        self.messages_with(DownPolicy::Skip)
+    }
+
+    /// Returns a blocking iterator over the JSON messages this mailbox
+    /// receives, handling down signals according to the given [DownPolicy].
+    ///
+    /// The iteration ends when a [Signal::Shutdown] is received so that the
+    /// process can clean up. Panics if deserialization fails.
+    pub fn messages_with<T>(
+        &self,
+        policy: DownPolicy,
+    ) -> impl Iterator<Item = (T, Vec<Capability>)> + '_
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        self.signals()
+            .map_while(move |signal| match signal {
+                Signal::Message(msg) => Some(Some(msg)),
+                Signal::Down { .. } if policy == DownPolicy::Skip => Some(None),
+                Signal::Down { .. } if policy == DownPolicy::Stop => None,
+                Signal::Down { subject, .. } => {
+                    panic!("received down signal on subject {:?}", subject)
+                }
+                Signal::Shutdown => None,
+            })
+            .flatten()
+            .map(|msg| {
+                let data = serde_json::from_slice(&msg.data).unwrap();
+                (data, msg.caps)
+            })
+    }
+
     /// Receives a JSON message. Panics if the next signal isn't a message or
     /// if deserialization fails.
     pub fn recv<T>(&self) -> (T, Vec<Capability>)
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        self.recv_with::<JsonCodec, T>()
+    }
+
+    /// Receives a message serialized with the given [Codec]. Panics if the
+    /// next signal isn't a message or if deserialization fails.
+    pub fn recv_with<C: Codec, T>(&self) -> (T, Vec<Capability>)
     where
         T: for<'a> Deserialize<'a>,
     {
         let (bytes_data, caps) = self.recv_raw();
-        let json_data = serde_json::from_slice(&bytes_data).unwrap();
-        (json_data, caps)
+        let data = C::decode(&bytes_data).unwrap();
+        (data, caps)
     }
 
     /// Receives a raw bytes message. Panics if the next signal isn't a message or
@@ -237,15 +644,90 @@ impl Mailbox {
     }
 
     /// Check if this mailbox has received any signals without waiting.
+    ///
+    /// Panics if this process has been killed.
     pub fn try_recv_signal(&self) -> Option<Signal> {
+        match self.try_recv_signal_checked() {
+            Ok(signal) => signal,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Receives up to `max` signals that this mailbox has already received,
+    /// without waiting.
+    ///
+    /// Returns the signals in the order they were received, or an empty
+    /// vector if none were queued. Unlike calling [Self::try_recv_signal] in a
+    /// loop, all of the signals are dequeued with a single host call.
+    ///
+    /// Panics if this process has been killed.
+    pub fn recv_batch(&self, max: usize) -> Vec<Signal> {
+        let mut handles = vec![0u32; max];
+        let ptr = handles.as_mut_ptr() as u32;
+        let count = unsafe { abi::mailbox::recv_many(self.0, ptr, max as u32) };
+
+        if count == KILLED_SIGNAL {
+            panic!("{}", RecvError::Killed);
+        }
+
+        handles.truncate(count as usize);
+
+        handles
+            .into_iter()
+            .map(|handle| unsafe { Signal::from_handle(handle) })
+            .collect()
+    }
+
+    /// Check if this mailbox has received any signals without waiting,
+    /// returning [RecvError::Killed] if this process has been killed.
+    fn try_recv_signal_checked(&self) -> Result<Option<Signal>, RecvError> {
         unsafe {
-            let handle = abi::mailbox::try_recv(self.0);
+            match abi::mailbox::try_recv(self.0) {
+                EMPTY_SIGNAL => Ok(None),
+                KILLED_SIGNAL => Err(RecvError::Killed),
+                handle => Ok(Some(Signal::from_handle(handle))),
+            }
+        }
+    }
+
+    /// Receives a JSON message, returning an error instead of panicking if the
+    /// next signal isn't a message or if deserialization fails.
+    ///
+    /// Use this instead of [Self::recv] when messages may come from untrusted
+    /// processes, so that malformed messages can be rejected gracefully.
+    pub fn recv_checked<T>(&self) -> Result<(T, Vec<Capability>), RecvError>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        Self::decode_checked(self.recv_signal_checked()?)
+    }
+
+    /// Check if this mailbox has received a JSON message without waiting.
+    ///
+    /// Returns `Ok(None)` if no signal has been received. Like
+    /// [Self::recv_checked], returns an error instead of panicking if the
+    /// signal isn't a message or if deserialization fails.
+    pub fn try_recv_typed<T>(&self) -> Result<Option<(T, Vec<Capability>)>, RecvError>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        self.try_recv_signal_checked()?
+            .map(Self::decode_checked)
+            .transpose()
+    }
 
-            if handle == u32::MAX {
-                None
-            } else {
-                Some(Signal::from_handle(handle))
+    /// Internal helper function to decode a signal as a JSON message.
+    fn decode_checked<T>(signal: Signal) -> Result<(T, Vec<Capability>), RecvError>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        match signal {
+            Signal::Message(msg) => {
+                let data = serde_json::from_slice(&msg.data).map_err(RecvError::Deserialize)?;
+                Ok((data, msg.caps))
             }
+            Signal::Down { subject, .. } => Err(RecvError::UnexpectedDown { subject }),
+            Signal::Shutdown => Err(RecvError::Shutdown),
         }
     }
 
@@ -256,9 +738,10 @@ impl Mailbox {
 
         match signal {
             Some(Signal::Message(msg)) => Some((msg.data, msg.caps)),
-            Some(Signal::Down { subject }) => {
+            Some(Signal::Down { subject, .. }) => {
                 panic!("received down signal on subject {:?}", subject)
             }
+            Some(Signal::Shutdown) => panic!("received shutdown request"),
             None => None,
         }
     }
@@ -291,18 +774,35 @@ pub struct Message {
 impl Message {
     /// Loads a message signal by its handle.
     unsafe fn load_from_handle(handle: u32) -> Self {
-        let data_len = abi::mailbox::get_message_data_len(handle) as usize;
+        let header = abi::mailbox::get_message_header(handle);
+        let data_len = (header >> 32) as usize;
+        let caps_num = (header & 0xffff_ffff) as usize;
+
         let mut data = Vec::with_capacity(data_len);
         data.set_len(data_len);
         abi::mailbox::get_message_data(handle, data.as_ptr() as u32);
 
-        let caps_num = abi::mailbox::get_message_caps_num(handle) as usize;
+        // skip copying the capability list if there are none
         let mut caps = Vec::with_capacity(caps_num);
-        caps.set_len(caps_num);
-        abi::mailbox::get_message_caps(handle, caps.as_ptr() as u32);
+        if caps_num > 0 {
+            caps.set_len(caps_num);
+            abi::mailbox::get_message_caps(handle, caps.as_ptr() as u32);
+        }
 
         Self { data, caps }
     }
+
+    /// Pairs each of this message's capabilities with its permission flags.
+    ///
+    /// Capabilities may arrive with fewer permissions than their sender
+    /// intended, so services should check these before trusting a capability
+    /// with sensitive data.
+    pub fn caps_with_flags(&self) -> Vec<(Capability, Permissions)> {
+        self.caps
+            .iter()
+            .map(|cap| (cap.clone(), cap.get_flags()))
+            .collect()
+    }
 }
 
 /// A loaded lump.
@@ -315,6 +815,47 @@ impl Drop for Lump {
     }
 }
 
+/// Loads a lump from data written in chunks.
+///
+/// Unlike [Lump::load_raw], the lump's complete data never needs to be held in
+/// this process's memory at once, which is useful for large assets. The
+/// resulting lump is identical to one loaded from the same data in one piece.
+///
+/// Dropping a writer without calling [LumpWriter::finish] discards its data.
+#[derive(Debug)]
+pub struct LumpWriter(u32);
+
+impl Drop for LumpWriter {
+    fn drop(&mut self) {
+        unsafe { abi::lump::abort_stream(self.0) }
+    }
+}
+
+impl Default for LumpWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LumpWriter {
+    /// Begins writing a new lump.
+    pub fn new() -> Self {
+        Self(unsafe { abi::lump::begin_stream() })
+    }
+
+    /// Appends a chunk of data to the lump.
+    pub fn push(&mut self, data: &[u8]) {
+        unsafe { abi::lump::write_stream(self.0, data.as_ptr() as u32, data.len() as u32) }
+    }
+
+    /// Finishes writing and loads the lump.
+    pub fn finish(self) -> Lump {
+        let handle = unsafe { abi::lump::finish_stream(self.0) };
+        std::mem::forget(self);
+        Lump(handle)
+    }
+}
+
 impl Lump {
     /// Loads a new lump directly from in-process bytes.
     pub fn load_raw(data: &[u8]) -> Self {
@@ -340,6 +881,21 @@ impl Lump {
         }
     }
 
+    /// Checks if a lump with the given ID is available to load with
+    /// [Lump::load_by_id].
+    pub fn exists(id: &LumpId) -> bool {
+        unsafe { abi::lump::exists(id as *const LumpId as u32) != 0 }
+    }
+
+    /// Removes a lump from the host's lump store to free its memory.
+    ///
+    /// Fails and returns false if any process still has the lump loaded or is
+    /// running it as its module. Afterwards, [Lump::load_by_id] can only load
+    /// the lump if the host re-fetches it from one of its lump sources.
+    pub fn unload_store(id: &LumpId) -> bool {
+        unsafe { abi::lump::unload_store(id as *const LumpId as u32) != 0 }
+    }
+
     /// Gets the ID of this lump.
     pub fn get_id(&self) -> LumpId {
         unsafe {
@@ -350,6 +906,39 @@ impl Lump {
         }
     }
 
+    /// Gets the length of the data stored in this lump in bytes.
+    pub fn get_len(&self) -> usize {
+        unsafe { abi::lump::get_len(self.0) as usize }
+    }
+
+    /// Passes the data stored in this lump to a closure.
+    ///
+    /// The data is copied into a buffer that is reused between calls, so
+    /// repeatedly reading lumps this way avoids allocating for each read.
+    pub fn with_data<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        // take the buffer out so that nested calls don't share it
+        let mut buffer = LUMP_BUFFER.with(Cell::take);
+        buffer.resize(self.get_len(), 0);
+        unsafe { abi::lump::get_data(self.0, buffer.as_mut_ptr() as u32) }
+        let result = f(&buffer);
+        LUMP_BUFFER.with(|cell| cell.set(buffer));
+        result
+    }
+
+    /// Copies the data stored in this lump into a buffer.
+    ///
+    /// Fails with the length of the lump's data if `dst` is a different length.
+    pub fn copy_into(&self, dst: &mut [u8]) -> Result<(), usize> {
+        let len = self.get_len();
+
+        if dst.len() != len {
+            return Err(len);
+        }
+
+        unsafe { abi::lump::get_data(self.0, dst.as_mut_ptr() as u32) }
+        Ok(())
+    }
+
     /// Retrieves the data stored in this lump.
     pub fn get_data(&self) -> Vec<u8> {
         #[allow(clippy::uninit_vec)]
@@ -363,12 +952,43 @@ impl Lump {
     }
 }
 
+thread_local! {
+    /// The buffer reused by [Lump::with_data].
+    static LUMP_BUFFER: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
 /// Log a message.
 pub fn log(level: ProcessLogLevel, module: &str, content: &str) {
+    log_raw(level, module, content, "");
+}
+
+/// Log a message with structured fields.
+///
+/// `fields` must serialize to a JSON object, whose entries are attached to
+/// the host's log event. Panics if serialization fails.
+pub fn log_fields(level: ProcessLogLevel, module: &str, content: &str, fields: &impl Serialize) {
+    let fields = serde_json::to_string(fields).unwrap();
+    log_raw(level, module, content, &fields);
+}
+
+/// Internal helper function to log a message with optional JSON fields.
+fn log_raw(level: ProcessLogLevel, module: &str, content: &str, fields: &str) {
     let level = level.into();
     let (module_ptr, module_len) = abi_string(module);
     let (content_ptr, content_len) = abi_string(content);
-    unsafe { abi::log::log(level, module_ptr, module_len, content_ptr, content_len) }
+    let (fields_ptr, fields_len) = abi_string(fields);
+
+    unsafe {
+        abi::log::log(
+            level,
+            module_ptr,
+            module_len,
+            content_ptr,
+            content_len,
+            fields_ptr,
+            fields_len,
+        )
+    }
 }
 
 #[allow(clashing_extern_declarations)]
@@ -382,16 +1002,40 @@ mod abi {
                 module_len: u32,
                 content_ptr: u32,
                 content_len: u32,
+                fields_ptr: u32,
+                fields_len: u32,
             );
         }
     }
 
+    pub mod kv {
+        #[link(wasm_import_module = "hearth::kv")]
+        extern "C" {
+            pub fn set(key_ptr: u32, key_len: u32, val_ptr: u32, val_len: u32) -> u32;
+            pub fn get(key_ptr: u32, key_len: u32, val_ptr: u32, val_cap: u32) -> u32;
+            pub fn delete(key_ptr: u32, key_len: u32) -> u32;
+        }
+    }
+
+    pub mod random {
+        #[link(wasm_import_module = "hearth::random")]
+        extern "C" {
+            pub fn fill(ptr: u32, len: u32);
+        }
+    }
+
     pub mod lump {
         #[link(wasm_import_module = "hearth::lump")]
         extern "C" {
             pub fn this_lump(ptr: u32);
             pub fn load_by_id(id_ptr: u32) -> u32;
+            pub fn exists(id_ptr: u32) -> u32;
+            pub fn unload_store(id_ptr: u32) -> u32;
             pub fn load(ptr: u32, len: u32) -> u32;
+            pub fn begin_stream() -> u32;
+            pub fn write_stream(handle: u32, ptr: u32, len: u32);
+            pub fn finish_stream(handle: u32) -> u32;
+            pub fn abort_stream(handle: u32);
             pub fn get_id(handle: u32, id_ptr: u32);
             pub fn get_len(handle: u32) -> u32;
             pub fn get_data(handle: u32, ptr: u32);
@@ -406,7 +1050,17 @@ mod abi {
             pub fn dec_ref(handle: u32);
             pub fn get_permissions(handle: u32) -> u32;
             pub fn demote(handle: u32, perms: u32) -> u32;
+            pub fn same_route(a: u32, b: u32) -> u32;
             pub fn send(handle: u32, data_ptr: u32, data_len: u32, caps_ptr: u32, caps_len: u32);
+            pub fn send_many(
+                handles_ptr: u32,
+                handles_len: u32,
+                data_ptr: u32,
+                data_len: u32,
+                caps_ptr: u32,
+                caps_len: u32,
+            );
+            pub fn shutdown(handle: u32);
             pub fn kill(handle: u32);
         }
     }
@@ -417,16 +1071,21 @@ mod abi {
             pub fn create() -> u32;
             pub fn destroy(handle: u32);
             pub fn make_capability(handle: u32, perms: u32) -> u32;
+            pub fn make_self_capability(perms: u32) -> u32;
             pub fn monitor(mailbox: u32, cap: u32);
+            pub fn monitor_tagged(mailbox: u32, cap: u32, tag: u64);
+            pub fn link(mailbox: u32, cap: u32);
             pub fn recv(handle: u32) -> u32;
+            pub fn recv_timeout(handle: u32, timeout_us: u64) -> u32;
             pub fn try_recv(handle: u32) -> u32;
+            pub fn recv_many(handle: u32, dst_ptr: u32, max: u32) -> u32;
             pub fn poll(handles_ptr: u32, handles_len: u32) -> u64;
             pub fn destroy_signal(handle: u32);
             pub fn get_signal_kind(handle: u32) -> u32;
             pub fn get_down_capability(handle: u32) -> u32;
-            pub fn get_message_data_len(handle: u32) -> u32;
+            pub fn get_down_tag(handle: u32) -> u64;
+            pub fn get_message_header(handle: u32) -> u64;
             pub fn get_message_data(handle: u32, dst_ptr: u32);
-            pub fn get_message_caps_num(handle: u32) -> u32;
             pub fn get_message_caps(handle: u32, dst_ptr: u32);
         }
     }
@@ -445,6 +1104,9 @@ mod abi {
 /// - `repository`: a link to the home source repository of the package.
 /// - `homepage`: a link to the package's homepage.
 /// - `license`: an SPDX license identifier for the package's source code.
+/// - `version`: the package's semantic version.
+///
+/// Also declares the [ABI_VERSION] that the process was built against.
 ///
 /// See [Cargo's documentation](https://doc.rust-lang.org/cargo/reference/manifest.html#the-package-section) for more info.
 #[macro_export]
@@ -461,6 +1123,8 @@ macro_rules! export_metadata {
                 fn set_repository(ptr: u32, len: u32);
                 fn set_homepage(ptr: u32, len: u32);
                 fn set_license(ptr: u32, len: u32);
+                fn set_version(ptr: u32, len: u32);
+                fn set_abi_version(version: u32);
             }
 
             // helper function to return Some(str) when str is not empty and None if empty
@@ -505,10 +1169,33 @@ macro_rules! export_metadata {
             if let Some((ptr, len)) = some_or_empty(env!("CARGO_PKG_LICENSE")) {
                 unsafe { set_license(ptr, len) };
             }
+
+            if let Some((ptr, len)) = some_or_empty(env!("CARGO_PKG_VERSION")) {
+                unsafe { set_version(ptr, len) };
+            }
+
+            unsafe { set_abi_version($crate::ABI_VERSION) };
         }
     };
 }
 
+/// Lists the spans that were entered when a panic happened for the panic
+/// hook, outermost first.
+///
+/// Guests are built for wasm32-unknown-unknown, which aborts on panic and
+/// can't capture backtraces, so the entered tracing spans stand in for one.
+/// Empty unless the `panic-spans` feature is enabled, so that small modules
+/// don't pay for tracking spans.
+#[cfg(feature = "panic-spans")]
+fn panic_spans() -> Vec<String> {
+    subscriber::entered_spans()
+}
+
+#[cfg(not(feature = "panic-spans"))]
+fn panic_spans() -> Vec<String> {
+    Vec::new()
+}
+
 #[no_mangle]
 extern "C" fn _hearth_init() {
     // set panic handler that prints error to log
@@ -527,7 +1214,18 @@ extern "C" fn _hearth_init() {
         };
 
         let log_message = format!("panicked at '{msg}', {location}");
-        log(ProcessLogLevel::Error, "panic", &log_message);
+
+        let spans = panic_spans();
+        if spans.is_empty() {
+            log(ProcessLogLevel::Error, "panic", &log_message);
+        } else {
+            let fields = serde_json::json!({ "spans": spans });
+            log_fields(ProcessLogLevel::Error, "panic", &log_message, &fields);
+        }
+
+        // the process traps without unwinding once this hook returns, so run
+        // any registered cleanup now
+        cleanup::run_panic_cleanup();
     }));
 
     // initialize tracing subscriber
@@ -0,0 +1,98 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! A helper for making requests to request-response services.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    codec::{Codec, JsonCodec},
+    Capability, Mailbox, Permissions,
+};
+
+/// A builder for a single request to a request-response service.
+///
+/// Services following the request-response pattern expect a capability to
+/// reply to as the first capability of each request. [Request::call] makes a
+/// temporary mailbox for the reply, attaches a send-only capability to it,
+/// and waits for the response. The mailbox is destroyed once the response is
+/// received.
+///
+/// ```ignore
+/// let response: RegistryResponse = Request::new(&registry)
+///     .with_data(&RegistryRequest::List)
+///     .call();
+/// ```
+pub struct Request<'a> {
+    target: &'a Capability,
+    data: Vec<u8>,
+    caps: Vec<&'a Capability>,
+}
+
+impl<'a> Request<'a> {
+    /// Creates a request to the given capability with no data or
+    /// capabilities.
+    pub fn new(target: &'a Capability) -> Self {
+        Self {
+            target,
+            data: Vec::new(),
+            caps: Vec::new(),
+        }
+    }
+
+    /// Sets the data of this request, serialized as JSON.
+    pub fn with_data(mut self, data: &impl Serialize) -> Self {
+        self.data = JsonCodec::encode(data).unwrap();
+        self
+    }
+
+    /// Adds capabilities to this request, following the reply capability.
+    pub fn with_caps(mut self, caps: &[&'a Capability]) -> Self {
+        self.caps.extend_from_slice(caps);
+        self
+    }
+
+    /// Sends this request and waits for a JSON response.
+    ///
+    /// Panics if the response is not a message or fails to deserialize.
+    pub fn call<R>(self) -> R
+    where
+        R: for<'de> Deserialize<'de>,
+    {
+        self.call_with_caps().0
+    }
+
+    /// Sends this request and waits for a JSON response, returning the
+    /// capabilities of the response along with its data.
+    ///
+    /// Panics if the response is not a message or fails to deserialize.
+    pub fn call_with_caps<R>(self) -> (R, Vec<Capability>)
+    where
+        R: for<'de> Deserialize<'de>,
+    {
+        let reply = Mailbox::new();
+        let reply_cap = reply.make_capability(Permissions::SEND);
+
+        let mut caps = Vec::with_capacity(self.caps.len() + 1);
+        caps.push(&reply_cap);
+        caps.extend(self.caps);
+
+        self.target.send_raw(&self.data, &caps);
+        reply.recv()
+    }
+}
@@ -21,17 +21,58 @@ use std::{
     sync::atomic::{AtomicUsize, Ordering},
 };
 
-use tracing::{field::Visit, span, Subscriber};
+#[cfg(feature = "panic-spans")]
+use std::{cell::RefCell, collections::HashMap, sync::Mutex};
+
+use serde_json::{Map, Value};
+#[cfg(feature = "panic-spans")]
+use tracing::Metadata;
+use tracing::{
+    field::{Field, Visit},
+    span, Subscriber,
+};
+
+#[cfg(feature = "panic-spans")]
+thread_local! {
+    /// The spans entered on this thread by ID, innermost last.
+    static ENTERED: RefCell<Vec<(u64, &'static Metadata<'static>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Returns the module path and name of each span entered on this thread,
+/// outermost first.
+#[cfg(feature = "panic-spans")]
+pub fn entered_spans() -> Vec<String> {
+    ENTERED.with(|entered| {
+        // a panic while entering or exiting leaves the stack borrowed
+        let Ok(entered) = entered.try_borrow() else {
+            return Vec::new();
+        };
+
+        entered
+            .iter()
+            .map(|(_, meta)| {
+                let module = meta.module_path().unwrap_or_else(|| meta.target());
+                format!("{}::{}", module, meta.name())
+            })
+            .collect()
+    })
+}
 
 /// Subscribes to tracing events and formats them through the API to the host
 pub struct ProcessSubscriber {
     next_span_id: AtomicUsize,
+
+    /// The metadata and reference count of each open span by ID.
+    #[cfg(feature = "panic-spans")]
+    spans: Mutex<HashMap<u64, (&'static Metadata<'static>, usize)>>,
 }
 
 impl ProcessSubscriber {
     pub fn new() -> Self {
         Self {
             next_span_id: AtomicUsize::new(1),
+            #[cfg(feature = "panic-spans")]
+            spans: Default::default(),
         }
     }
 }
@@ -42,9 +83,12 @@ impl Subscriber for ProcessSubscriber {
     }
 
     fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
-        let id = self.next_span_id.fetch_add(1, Ordering::SeqCst);
+        let id = self.next_span_id.fetch_add(1, Ordering::SeqCst) as u64;
 
-        span::Id::from_u64(id as u64)
+        #[cfg(feature = "panic-spans")]
+        self.spans.lock().unwrap().insert(id, (_span.metadata(), 1));
+
+        span::Id::from_u64(id)
     }
 
     fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
@@ -52,43 +96,147 @@ impl Subscriber for ProcessSubscriber {
     fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
 
     fn event(&self, event: &tracing::Event<'_>) {
-        let mut message = String::new();
-
-        let mut visitor = FmtEvent {
-            message: &mut message,
-            needs_comma: false,
-        };
-
+        let mut visitor = EventVisitor::default();
         event.record(&mut visitor);
 
         let module = event.metadata().target();
         let level = (*event.metadata().level()).into();
 
-        // TODO: Support structured logging
-        crate::log(level, module, &message);
+        if visitor.fields.is_empty() {
+            crate::log(level, module, &visitor.message);
+        } else {
+            crate::log_fields(level, module, &visitor.message, &visitor.fields);
+        }
+    }
+
+    fn enter(&self, _span: &span::Id) {
+        #[cfg(feature = "panic-spans")]
+        if let Some((meta, _)) = self.spans.lock().unwrap().get(&_span.into_u64()) {
+            let entry = (_span.into_u64(), *meta);
+            ENTERED.with(|entered| entered.borrow_mut().push(entry));
+        }
+    }
+
+    fn exit(&self, _span: &span::Id) {
+        #[cfg(feature = "panic-spans")]
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(index) = entered.iter().rposition(|(id, _)| *id == _span.into_u64()) {
+                entered.remove(index);
+            }
+        });
+    }
+
+    #[cfg(feature = "panic-spans")]
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        if let Some((_, refs)) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            *refs += 1;
+        }
+
+        span.clone()
     }
 
-    fn enter(&self, _span: &span::Id) {}
+    #[cfg(feature = "panic-spans")]
+    fn try_close(&self, span: span::Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let id = span.into_u64();
 
-    fn exit(&self, _span: &span::Id) {}
+        let Some((_, refs)) = spans.get_mut(&id) else {
+            return false;
+        };
+
+        *refs -= 1;
+        if *refs > 0 {
+            return false;
+        }
+
+        spans.remove(&id);
+        true
+    }
 }
 
-pub struct FmtEvent<'a> {
-    pub message: &'a mut String,
-    pub needs_comma: bool,
+/// Collects an event's message and its structured fields.
+#[derive(Default)]
+struct EventVisitor {
+    message: String,
+    fields: Map<String, Value>,
 }
 
-impl<'a> Visit for FmtEvent<'a> {
-    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
-        let comma = if self.needs_comma { ", " } else { "" };
+impl EventVisitor {
+    fn record_value(&mut self, field: &Field, value: impl Into<Value>) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+}
+
+impl Visit for EventVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record_value(field, value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record_value(field, value);
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record_value(field, value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record_value(field, value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
         match field.name() {
-            "message" => {
-                write!(self.message, "{comma}{value:?}").unwrap();
-                self.needs_comma = true;
-            }
-            name => {
-                write!(self.message, "{comma}{name}={value:?}").unwrap();
-            }
+            "message" => self.message.push_str(value),
+            _ => self.record_value(field, value),
         }
     }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => write!(self.message, "{value:?}").unwrap(),
+            _ => self.record_value(field, format!("{value:?}")),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "panic-spans"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entered_spans_follow_enter_and_exit() {
+        tracing::subscriber::with_default(ProcessSubscriber::new(), || {
+            let outer = tracing::info_span!("outer");
+            let _outer = outer.enter();
+
+            {
+                let inner = tracing::info_span!("inner");
+                let _inner = inner.enter();
+
+                let module = module_path!();
+                let expected = [format!("{module}::outer"), format!("{module}::inner")];
+                assert_eq!(entered_spans(), expected);
+            }
+
+            assert_eq!(entered_spans(), [format!("{}::outer", module_path!())]);
+        });
+
+        assert!(entered_spans().is_empty());
+    }
+
+    #[test]
+    fn closed_spans_are_forgotten() {
+        let subscriber = std::sync::Arc::new(ProcessSubscriber::new());
+
+        tracing::subscriber::with_default(subscriber.clone(), || {
+            let span = tracing::info_span!("cloned");
+            let clone = span.clone();
+            drop(span);
+            assert_eq!(subscriber.spans.lock().unwrap().len(), 1);
+            drop(clone);
+        });
+
+        assert!(subscriber.spans.lock().unwrap().is_empty());
+    }
 }
@@ -0,0 +1,184 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Lazily-initialized singletons for sharing resources within a process.
+//!
+//! Wasm guests are single-threaded, so the locks used by these types are
+//! never contended. They exist so that these types are `Sync` and can be
+//! placed in statics. Initialization may block on the host, but no other
+//! code in the process runs while it does, so it is never observed halfway
+//! done. Initializing a value from within its own initializer deadlocks.
+
+use std::{
+    ops::Deref,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{
+    registry::{RegistryRequest, RegistryResponse},
+    request::Request,
+    Capability, Mailbox, Signal,
+};
+
+/// A capability to the registry that this process has base access to.
+static REGISTRY: Capability = unsafe { Capability::new_raw(0) };
+
+/// A value that is initialized on first access.
+///
+/// ```ignore
+/// static CONFIG: Lazy<Config> = Lazy::new(|| load_config());
+/// ```
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceLock<T>,
+    init: Mutex<Option<F>>,
+}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Creates a new lazy value with the given initializer.
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceLock::new(),
+            init: Mutex::new(Some(init)),
+        }
+    }
+
+    /// Forces the initialization of this value and returns a reference to it.
+    ///
+    /// Panics if a previous initialization attempt panicked.
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| {
+            let init = this
+                .init
+                .lock()
+                .unwrap()
+                .take()
+                .expect("lazy value initializer previously panicked");
+
+            init()
+        })
+    }
+
+    /// Returns a reference to this value if it has been initialized.
+    pub fn get(this: &Self) -> Option<&T> {
+        this.cell.get()
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Self::force(self)
+    }
+}
+
+/// A resolved service and the mailbox monitoring it.
+struct ResolvedService {
+    cap: Capability,
+    monitor: Mailbox,
+}
+
+/// A cached capability to a named service in the registry.
+///
+/// The service is looked up the first time it is requested and shared from
+/// then on. The cached capability is monitored, and if the service's route
+/// goes down, it is looked up again on the next request.
+///
+/// ```ignore
+/// static FS: ServiceHandle = ServiceHandle::new("hearth.fs.Filesystem");
+///
+/// let fs = FS.get().expect("filesystem is unavailable");
+/// ```
+pub struct ServiceHandle {
+    name: &'static str,
+    resolved: Mutex<Option<ResolvedService>>,
+}
+
+impl ServiceHandle {
+    /// Creates a handle to the service with the given name.
+    ///
+    /// Does not look the service up until it is first requested.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            resolved: Mutex::new(None),
+        }
+    }
+
+    /// Gets the name of this service.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Gets a capability to this service, looking it up if it has not been
+    /// looked up yet or if its route has gone down since.
+    ///
+    /// Returns `None` if the service is not in the registry.
+    pub fn get(&self) -> Option<Capability> {
+        let mut resolved = self.resolved.lock().unwrap();
+
+        if let Some(service) = resolved.as_ref() {
+            let down = std::iter::from_fn(|| service.monitor.try_recv_signal())
+                .any(|signal| matches!(signal, Signal::Down { .. }));
+
+            if down {
+                *resolved = None;
+            }
+        }
+
+        if resolved.is_none() {
+            *resolved = Self::resolve(self.name);
+        }
+
+        resolved.as_ref().map(|service| service.cap.clone())
+    }
+
+    /// Gets a capability to this service.
+    ///
+    /// Panics if the service is not in the registry.
+    pub fn expect(&self) -> Capability {
+        self.get()
+            .unwrap_or_else(|| panic!("requested service {:?} is unavailable", self.name))
+    }
+
+    /// Forgets the cached capability so that the next request looks the
+    /// service up again.
+    pub fn invalidate(&self) {
+        *self.resolved.lock().unwrap() = None;
+    }
+
+    /// Looks up a service in the registry and starts monitoring it.
+    fn resolve(name: &str) -> Option<ResolvedService> {
+        let request = RegistryRequest::Get {
+            name: name.to_string(),
+        };
+
+        let (response, mut caps) = Request::new(&REGISTRY)
+            .with_data(&request)
+            .call_with_caps::<RegistryResponse>();
+
+        let RegistryResponse::Get(true) = response else {
+            return None;
+        };
+
+        let cap = caps.remove(0);
+        let monitor = Mailbox::new();
+        monitor.monitor(&cap);
+        Some(ResolvedService { cap, monitor })
+    }
+}
@@ -26,6 +26,9 @@ fn spawn(spawner: &Process, cb: fn()) -> Process {
         &WasmSpawnInfo {
             lump: hearth_guest::this_lump(),
             entrypoint: Some(unsafe { std::mem::transmute::<fn(), usize>(cb) } as u32),
+            ready_timeout: None,
+            fuel_limit: None,
+            priority: 0,
         },
     );
 
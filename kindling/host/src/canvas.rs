@@ -18,7 +18,7 @@
 
 use super::*;
 
-use hearth_guest::canvas::*;
+use hearth_guest::{canvas::*, versioned::HearthProtocol};
 
 lazy_static::lazy_static! {
     /// A lazily-initialized handle to the canvas factory service.
@@ -35,12 +35,20 @@ impl Canvas {
     /// Creates a new Canvas.
     ///
     /// Panics if the factory responds with an error.
-    pub fn new(position: Position, pixels: Pixels, sampling: CanvasSamplingMode) -> Self {
+    pub fn new(
+        position: Position,
+        pixels: Pixels,
+        sampling: CanvasSamplingMode,
+        blend: CanvasBlendMode,
+        on_top: bool,
+    ) -> Self {
         let resp = CANVAS_FACTORY.request(
             FactoryRequest::CreateCanvas {
                 position,
                 pixels,
                 sampling,
+                blend,
+                on_top,
             },
             &[],
         );
@@ -52,16 +60,37 @@ impl Canvas {
 
     /// Update this canvas with a new buffer of pixels to draw.
     pub fn update(&self, buffer: Pixels) {
-        self.cap.send(&CanvasUpdate::Resize(buffer), &[]);
+        self.send_update(CanvasUpdate::Resize(buffer), &[]);
     }
 
     /// Move this canvas to a new position in 3D space.
     pub fn relocate(&self, position: Position) {
-        self.cap.send(&CanvasUpdate::Relocate(position), &[])
+        self.send_update(CanvasUpdate::Relocate(position), &[])
     }
 
     /// Blit a recatangular buffer to a part of this canvas.
     pub fn blit(&self, blit: Blit) {
-        self.cap.send(&CanvasUpdate::Blit(blit), &[])
+        self.send_update(CanvasUpdate::Blit(blit), &[])
+    }
+
+    /// Blit multiple rectangular buffers to this canvas in one message.
+    pub fn blit_batch(&self, blits: Vec<Blit>) {
+        self.send_update(CanvasUpdate::BlitBatch(blits), &[])
+    }
+
+    /// Read back the pixels in a region of this canvas.
+    ///
+    /// The region is clipped to the bounds of the canvas. Returns an empty
+    /// buffer if the pixels could not be read.
+    pub fn readback(&self, region: Region) -> Pixels {
+        let reply = Mailbox::new();
+        let reply_cap = reply.make_capability(Permissions::SEND);
+        self.send_update(CanvasUpdate::Readback(region), &[&reply_cap]);
+        reply.recv().0
+    }
+
+    /// Sends a versioned [CanvasUpdate] to this canvas.
+    fn send_update(&self, update: CanvasUpdate, caps: &[&Capability]) {
+        self.cap.send_raw(&update.encode_versioned(), caps);
     }
 }
@@ -18,7 +18,8 @@
 
 use super::*;
 
-use hearth_guest::debug_draw::*;
+use glam::Vec3;
+use hearth_guest::{debug_draw::*, Color};
 
 lazy_static::lazy_static! {
     static ref DEBUG_DRAW_FACTORY: RequestResponse<(), ()> =
@@ -67,8 +68,65 @@ impl DebugDraw {
         self.cap.send(&DebugDrawUpdate::Hide(false), &[]);
     }
 
+    /// Tint the color of this whole debug draw.
+    ///
+    /// The tint's alpha can be used to fade the draw out.
+    pub fn set_tint(&self, tint: Color) {
+        self.cap.send(&DebugDrawUpdate::SetTint(tint), &[]);
+    }
+
     /// Update the contents of this debug draw mesh.
     pub fn update(&self, mesh: DebugDrawMesh) {
         self.cap.send(&DebugDrawUpdate::Contents(mesh), &[]);
     }
+
+    /// Add a line segment to this debug draw.
+    ///
+    /// If `ttl` is set, the line is removed after that many seconds.
+    pub fn add_line(&self, start: Vec3, end: Vec3, color: Color, ttl: Option<f32>) {
+        self.cap.send(
+            &DebugDrawUpdate::AddLine {
+                start,
+                end,
+                color,
+                ttl,
+            },
+            &[],
+        );
+    }
+
+    /// Add a wireframe axis-aligned box to this debug draw.
+    ///
+    /// If `ttl` is set, the box is removed after that many seconds.
+    pub fn add_box(&self, center: Vec3, half_extents: Vec3, color: Color, ttl: Option<f32>) {
+        self.cap.send(
+            &DebugDrawUpdate::AddBox {
+                center,
+                half_extents,
+                color,
+                ttl,
+            },
+            &[],
+        );
+    }
+
+    /// Add a wireframe sphere to this debug draw.
+    ///
+    /// If `ttl` is set, the sphere is removed after that many seconds.
+    pub fn add_sphere(&self, center: Vec3, radius: f32, color: Color, ttl: Option<f32>) {
+        self.cap.send(
+            &DebugDrawUpdate::AddSphere {
+                center,
+                radius,
+                color,
+                ttl,
+            },
+            &[],
+        );
+    }
+
+    /// Remove all shapes added to this debug draw.
+    pub fn clear_shapes(&self) {
+        self.cap.send(&DebugDrawUpdate::ClearShapes, &[]);
+    }
 }
@@ -19,7 +19,12 @@
 use super::*;
 use core::panic;
 
-use hearth_guest::{fs::*, Lump, LumpId};
+use hearth_guest::{
+    config::{self, ConfigError},
+    fs::*,
+    Lump, LumpId,
+};
+use serde::de::DeserializeOwned;
 
 lazy_static::lazy_static! {
     static ref FILESYSTEM: RequestResponse<Request, Response> =
@@ -50,6 +55,13 @@ pub fn read_file(path: &str) -> Result<Vec<u8>, Error> {
     Ok(lump.get_data())
 }
 
+/// Load and deserialize a config file, detecting its format by extension.
+///
+/// See [hearth_guest::config] for the supported formats.
+pub fn load_config<T: DeserializeOwned>(path: &str) -> Result<T, ConfigError> {
+    config::load(FILESYSTEM.as_ref(), path)
+}
+
 /// List all files and directories inside of a path.
 pub fn list_files(path: &str) -> Result<Vec<FileInfo>, Error> {
     let success = FILESYSTEM
@@ -44,11 +44,11 @@ pub mod prelude {
     pub use crate::{
         canvas::Canvas,
         debug_draw::DebugDraw,
-        fs::{get_file, list_files, read_file},
+        fs::{get_file, list_files, load_config, read_file},
         glam,
         registry::REGISTRY,
         terminal::Terminal,
-        time::{sleep, Stopwatch, Timer},
+        time::{sleep, Interval, Stopwatch, Timer},
         wasm::{spawn_fn, spawn_mod},
         window::MAIN_WINDOW,
         RequestResponse,
@@ -19,7 +19,7 @@
 use super::*;
 
 use glam::{Mat4, Vec3};
-use hearth_guest::{renderer::*, Lump};
+use hearth_guest::{renderer::*, Color, Lump};
 
 lazy_static::lazy_static! {
     static ref RENDERER: RequestResponse<RendererRequest, RendererResponse> =
@@ -38,6 +38,49 @@ pub fn set_ambient_lighting(color: Vec3) {
     let _ = result.unwrap();
 }
 
+/// Set the color that the scene is cleared to behind all objects.
+pub fn set_clear_color(color: Color) {
+    let (result, _) = RENDERER.request(RendererRequest::SetClearColor { color }, &[]);
+    let _ = result.unwrap();
+}
+
+/// Set the tone mapping operator and exposure of the final image.
+///
+/// Panics if `exposure` is not a positive, finite number.
+pub fn set_tone_mapping(operator: ToneMapOperator, exposure: f32) {
+    let (result, _) = RENDERER.request(RendererRequest::SetToneMapping { operator, exposure }, &[]);
+
+    let _ = result.unwrap();
+}
+
+/// Set the camera that the scene is viewed from.
+///
+/// `fov` is the vertical field of view in degrees and is only used by
+/// perspective projection. Panics if it is not a positive, finite number when
+/// it is used.
+pub fn set_camera(view: Mat4, projection_mode: ProjectionMode, fov: f32) {
+    let (result, _) = RENDERER.request(
+        RendererRequest::SetCamera {
+            view,
+            projection_mode,
+            fov,
+        },
+        &[],
+    );
+
+    let _ = result.unwrap();
+}
+
+/// Capture the next rendered frame as a lump containing a PNG image.
+pub fn capture_frame() -> Lump {
+    let (result, _) = RENDERER.request(RendererRequest::Capture, &[]);
+
+    match result.expect("failed to capture frame") {
+        RendererSuccess::Captured(id) => Lump::load_by_id(&id),
+        other => panic!("unexpected capture response: {other:?}"),
+    }
+}
+
 /// Update the skybox with the given lump containing [TextureData].
 pub fn set_skybox(texture: &Lump) {
     let (result, _) = RENDERER.request(
@@ -100,6 +143,56 @@ impl DirectionalLight {
     }
 }
 
+/// A point light.
+pub struct PointLight(Capability);
+
+impl Drop for PointLight {
+    fn drop(&mut self) {
+        self.0.kill();
+    }
+}
+
+impl PointLight {
+    /// Create a new point light.
+    pub fn new(state: PointLightState) -> Self {
+        let (result, caps) = RENDERER.request(
+            RendererRequest::AddPointLight {
+                initial_state: state,
+            },
+            &[],
+        );
+
+        let _ = result.expect("failed to create point light");
+
+        Self(caps.first().unwrap().clone())
+    }
+
+    /// Internal helper function to update this light.
+    fn update(&self, update: PointLightUpdate) {
+        self.0.send(&update, &[]);
+    }
+
+    /// Set this point light's position.
+    pub fn set_position(&self, position: Vec3) {
+        self.update(PointLightUpdate::Position(position));
+    }
+
+    /// Set this point light's color.
+    pub fn set_color(&self, color: Vec3) {
+        self.update(PointLightUpdate::Color(color));
+    }
+
+    /// Set this point light's intensity.
+    pub fn set_intensity(&self, intensity: f32) {
+        self.update(PointLightUpdate::Intensity(intensity));
+    }
+
+    /// Set this point light's range.
+    pub fn set_range(&self, range: f32) {
+        self.update(PointLightUpdate::Range(range));
+    }
+}
+
 /// Configuration for the creation of an [Object].
 #[derive(Clone, Debug)]
 pub struct ObjectConfig<'a> {
@@ -143,11 +236,39 @@ impl Object {
         Self(caps.first().unwrap().clone())
     }
 
+    /// Create a new object in the scene that switches between levels of
+    /// detail depending on the camera's distance to it.
+    ///
+    /// Each level is a lump containing [MeshData] and the distance beyond
+    /// which that level is used. LOD objects cannot be skinned.
+    pub fn new_lod(lods: &[(&Lump, f32)], material: &Lump, transform: Mat4) -> Self {
+        let (result, caps) = RENDERER.request(
+            RendererRequest::AddLodObject {
+                lods: lods
+                    .iter()
+                    .map(|(mesh, distance)| (mesh.get_id(), *distance))
+                    .collect(),
+                material: material.get_id(),
+                transform,
+            },
+            &[],
+        );
+
+        let _ = result.expect("failed to create LOD object");
+
+        Self(caps.first().unwrap().clone())
+    }
+
     /// Updates the transform of this object.
     pub fn set_transform(&self, transform: Mat4) {
         self.0.send(&ObjectUpdate::Transform(transform), &[]);
     }
 
+    /// Shows or hides this object.
+    pub fn set_visible(&self, visible: bool) {
+        self.0.send(&ObjectUpdate::SetVisible(visible), &[]);
+    }
+
     /// Update the joint matrices of this mesh.
     pub fn set_joint_matrices(&self, joints: Vec<Mat4>) {
         self.0.send(&ObjectUpdate::JointMatrices(joints), &[]);
@@ -41,7 +41,49 @@ impl Terminal {
     ///
     /// Panics if the factory responds with an error.
     pub fn new(state: TerminalState) -> Self {
-        let resp = TERMINAL_FACTORY.request(FactoryRequest::CreateTerminal(state), &[]);
+        let request = FactoryRequest::CreateTerminal {
+            state,
+            command: None,
+        };
+
+        let resp = TERMINAL_FACTORY.request(request, &[]);
+        let _ = resp.0.unwrap();
+        Terminal {
+            cap: resp.1.get(0).unwrap().clone(),
+        }
+    }
+
+    /// Creates a new terminal that sends a [TerminalExit] to `listener` when
+    /// its command exits.
+    ///
+    /// Panics if the factory responds with an error.
+    pub fn new_with_exit_listener(state: TerminalState, listener: &Capability) -> Self {
+        let request = FactoryRequest::CreateTerminal {
+            state,
+            command: None,
+        };
+
+        let resp = TERMINAL_FACTORY.request(request, &[listener]);
+        let _ = resp.0.unwrap();
+        Terminal {
+            cap: resp.1.get(0).unwrap().clone(),
+        }
+    }
+
+    /// Creates a new terminal running a specific program with arguments
+    /// instead of the default shell.
+    ///
+    /// Panics if the factory responds with an error.
+    pub fn new_with_command(state: TerminalState, program: String, args: Vec<String>) -> Self {
+        let mut command = vec![program];
+        command.extend(args);
+
+        let request = FactoryRequest::CreateTerminal {
+            state,
+            command: Some(command),
+        };
+
+        let resp = TERMINAL_FACTORY.request(request, &[]);
         let _ = resp.0.unwrap();
         Terminal {
             cap: resp.1.get(0).unwrap().clone(),
@@ -57,4 +99,15 @@ impl Terminal {
     pub fn update(&self, state: TerminalState) {
         self.cap.send(&TerminalUpdate::State(state), &[])
     }
+
+    /// Scroll this terminal's viewport. Positive values scroll up.
+    pub fn scroll(&self, lines: i32) {
+        self.cap.send(&TerminalUpdate::Scroll(lines), &[])
+    }
+
+    /// Set the maximum number of lines in this terminal's scrollback.
+    pub fn set_scrollback_lines(&self, lines: u32) {
+        self.cap
+            .send(&TerminalUpdate::SetScrollbackLines(lines), &[])
+    }
 }
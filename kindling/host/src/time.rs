@@ -23,12 +23,20 @@ lazy_static::lazy_static! {
         registry::REGISTRY.get_service("hearth.Sleep")
             .expect("requested service \"hearth.Sleep\" is unavailable");
 
+    static ref ALARM_SERVICE: Capability =
+        registry::REGISTRY.get_service("hearth.Alarm")
+            .expect("requested service \"hearth.Alarm\" is unavailable");
+
     static ref TIMER_FACTORY: RequestResponse<(), ()> =
         RequestResponse::expect_service("hearth.TimerFactory");
 
     static ref STOPWATCH_FACTORY: RequestResponse<(), ()> =
         RequestResponse::expect_service("hearth.StopwatchFactory");
 
+    static ref INTERVAL_FACTORY: Capability =
+        registry::REGISTRY.get_service("hearth.IntervalFactory")
+            .expect("requested service \"hearth.IntervalFactory\" is unavailable");
+
     static ref UNIX_TIME: RequestResponse<(), u128> =
         RequestResponse::expect_service("hearth.UnixTime");
 }
@@ -44,6 +52,18 @@ pub fn sleep(duration: f32) {
     let _ = reply.recv_raw();
 }
 
+/// Sleeps until the given time in nanoseconds since the UNIX epoch, as
+/// returned by [get_unix_time]. Returns immediately if the time has passed.
+pub fn sleep_until(deadline: u128) {
+    let reply = Mailbox::new();
+    let reply_cap = reply.make_capability(Permissions::SEND);
+    reply.monitor(&ALARM_SERVICE);
+
+    ALARM_SERVICE.send(&deadline, &[&reply_cap]);
+
+    let _ = reply.recv_raw();
+}
+
 /// Gets the time since the UNIX epoch in nanoseconds as a unsigned 128-bit
 /// integer.
 pub fn get_unix_time() -> u128 {
@@ -91,3 +111,25 @@ impl Stopwatch {
         self.0.request((), &[]).0
     }
 }
+
+/// Receives ticks at a fixed period.
+///
+/// The interval keeps running until this is dropped.
+pub struct Interval(Mailbox);
+
+impl Interval {
+    /// Creates a new Interval that ticks every `period` seconds.
+    pub fn new(period: f32) -> Self {
+        let reply = Mailbox::new();
+        let reply_cap = reply.make_capability(Permissions::SEND | Permissions::MONITOR);
+
+        INTERVAL_FACTORY.send(&period, &[&reply_cap]);
+
+        Self(reply)
+    }
+
+    /// Waits for the next tick.
+    pub fn tick(&self) {
+        let _ = self.0.recv_raw();
+    }
+}
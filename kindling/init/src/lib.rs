@@ -16,12 +16,24 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
-
-use hearth_guest::Capability;
-use kindling_host::{prelude::*, registry::Registry};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use hearth_guest::{
+    config::ConfigError, spawn_wasm_with, wasm::WasmSpawnInfo, Capability, Mailbox, Signal,
+};
+use kindling_host::{
+    prelude::*,
+    registry::{Registry, REGISTRY},
+    time::get_unix_time,
+};
 use kindling_utils::registry::*;
-use petgraph::{algo::toposort, prelude::DiGraph};
+use petgraph::{
+    algo::{tarjan_scc, toposort},
+    prelude::{DiGraph, NodeIndex},
+};
 use serde::Deserialize;
 
 hearth_guest::export_metadata!();
@@ -29,6 +41,20 @@ hearth_guest::export_metadata!();
 /// The subpath within the filesystem root where services are scanned.
 const SEARCH_DIR: &str = "init";
 
+/// The delay before a service is first restarted. Doubles with each
+/// consecutive restart.
+const RESTART_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// The longest delay before a service is restarted.
+const RESTART_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How long a service must run before its restart delay resets.
+const RESTART_RESET_TIME: Duration = Duration::from_secs(30);
+
+/// Pairs of each dependency's name in a service's registry and the name of
+/// the service providing it.
+type ResolvedDeps = Vec<(String, String)>;
+
 /// A persistent service container object.
 pub struct Service {
     /// A capability to this process, stays as `None` until this process is started.
@@ -36,6 +62,54 @@ pub struct Service {
 
     name: String,
     config: ServiceConfig,
+
+    /// This service's resolved dependencies. Filled in by [build_graph].
+    deps: ResolvedDeps,
+
+    /// The registry that this service was spawned with, to respawn it with.
+    registry: Option<Capability>,
+
+    /// The number of times this service has been restarted in a row.
+    restarts: u32,
+
+    /// The UNIX time in nanoseconds when this service was last started while
+    /// supervised.
+    started_at: u128,
+}
+
+/// A node in the service graph.
+pub enum Node {
+    /// A guest service to be spawned.
+    Service(Box<Service>),
+
+    /// A synthetic node for a milestone. It's ordered after every service
+    /// listing it in [ServiceConfig::targets] and before every service listing
+    /// it in [Dependencies::milestone], so it gates the latter group on the
+    /// former starting.
+    Milestone(String),
+}
+
+impl Node {
+    /// Gets the name of this node's service or milestone.
+    pub fn name(&self) -> &str {
+        match self {
+            Node::Service(service) => &service.name,
+            Node::Milestone(name) => name,
+        }
+    }
+}
+
+/// The kind of ordering that an edge in the service graph represents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    /// The dependent needs a capability to the dependency.
+    Need,
+
+    /// The dependent only starts after the dependency, if it's present.
+    WaitsFor,
+
+    /// Links a milestone to a service in its group or to a service it gates.
+    Milestone,
 }
 
 impl Service {
@@ -44,13 +118,33 @@ impl Service {
             name,
             process: None,
             config,
+            deps: Vec::new(),
+            registry: None,
+            restarts: 0,
+            started_at: 0,
         }
     }
 
     pub fn spawn(&mut self, registry: Option<Registry>) -> Capability {
+        self.registry = registry.map(|x| x.as_ref().to_owned());
+        self.respawn()
+    }
+
+    /// Spawns this service again with the registry it was first spawned with.
+    pub fn respawn(&mut self) -> Capability {
         let lump = get_file(&format!("{}/{}/service.wasm", SEARCH_DIR, self.name))
             .expect("WASM module not found");
-        let cap = spawn_mod(lump, registry.map(|x| x.as_ref().to_owned()));
+        let registry = self.registry.as_ref().unwrap_or(REGISTRY.as_ref());
+
+        let info = WasmSpawnInfo {
+            lump,
+            entrypoint: None,
+            ready_timeout: None,
+            fuel_limit: None,
+            priority: self.config.priority,
+        };
+
+        let cap = spawn_wasm_with(&info, &[registry]);
         self.process = Some(cap.to_owned());
         cap
     }
@@ -63,72 +157,46 @@ pub extern "C" fn run() {
     // first of all, enumerate available native services
     let native_services = REGISTRY.list_services();
 
-    // add all guest services into a dependency graph structure
-    let mut graph = DiGraph::<Service, ()>::new();
-
-    // map of service names to indices within the graph
-    let mut names_to_idxs = HashMap::new();
+    // collect all guest services
+    let mut services = Vec::new();
 
     // list all service directories
     for file in list_files(SEARCH_DIR).unwrap() {
         info!("file: {}", file.name);
 
         // attempt to parse config
-        let Some(config) = get_config(&file.name) else {
-            error!("Failed to get config");
-            continue;
+        let config = match get_config(&file.name) {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Failed to get config: {err}");
+                continue;
+            }
         };
 
         info!("config: {:?}", config);
+        services.push(Service::new(file.name, config));
+    }
 
-        // add service node to graph
-        let name = file.name;
-        let service = Service::new(name.clone(), config);
-        let idx = graph.add_node(service);
-        names_to_idxs.insert(name, idx);
+    // resolve each service's dependencies and link them into a graph
+    let (graph, missing) = build_graph(services, &native_services);
+
+    for (name, dep) in missing {
+        error!("Dependency \'{dep}\' not found");
+        info!("Service \'{name}\' will not be spawned");
     }
 
-    // add dependency edges to graph
-    for idx in graph.node_indices() {
-        let node = graph.node_weight(idx).unwrap();
-        let name = node.name.clone();
-        info!("Collecting dependencies of service \'{name}\'");
-
-        // track whether this service has any missing deps
-        let mut remove = false;
-
-        // iterate all needed deps
-        for dep in node.config.dependencies.need.clone() {
-            match names_to_idxs.get(&dep.clone()) {
-                // is this service an existing guest process?
-                Some(dep_idx) => {
-                    graph.add_edge(*dep_idx, idx, ());
-                }
-                // guest service not found
-                None => {
-                    // check if the service is native
-                    // if the service is native, we skip adding this edge, and
-                    // its capability will be retrieved during service startup
-                    if !native_services.contains(&dep) {
-                        // if it isn't, this dep is missing
-                        remove = true;
-                        error!("Dependency \'{dep}\' not found");
-                    }
-                }
-            };
-        }
+    // services in a dependency cycle can never start, so leave them out
+    let mut graph = graph;
+    for cycle in remove_cycles(&mut graph, Node::name) {
+        error!("Dependency cycle between {} nodes:", cycle.len());
 
-        // if this service can't start, remove the service from the graph
-        if remove {
-            info!("Service \'{name}\' will not be spawned");
-            graph.remove_node(idx);
-            names_to_idxs.remove(&name);
+        for name in cycle {
+            error!("- \'{name}\' is in the cycle and will not be spawned");
         }
     }
 
     // order service graph so that dependencies start before dependents
-    // panic may occur here if the dep graph has a cycle
-    let sorted_services = toposort(&graph, None).unwrap();
+    let sorted_services = toposort(&graph, None).expect("service graph has a cycle");
 
     // create a cache of service names to their started capabilities
     let mut names_to_caps: HashMap<String, Capability> = HashMap::new();
@@ -141,15 +209,28 @@ pub extern "C" fn run() {
 
     // start up all guest services in dependency order
     for idx in sorted_services {
-        // get service data
-        let service = graph.node_weight_mut(idx).unwrap();
+        // get service data, skipping milestones since they only order services
+        let Node::Service(service) = graph.node_weight_mut(idx).unwrap() else {
+            continue;
+        };
 
-        // create associated list of all deps' caps
+        // create associated list of all deps' caps, registered under the
+        // name or protocol that the service depends on them by
         let mut deps = Vec::new();
-        for dep in service.config.dependencies.need.clone() {
-            // look up service cap (either guest or host)
-            let cap = names_to_caps.get(&dep).unwrap().to_owned();
-            deps.push((dep, cap));
+        for (dep, provider) in service.deps.clone() {
+            // look up service cap (either guest or host), which is missing
+            // if its provider was left out of a cycle
+            let Some(cap) = names_to_caps.get(&provider) else {
+                error!("Dependency \'{dep}\' was not started");
+                break;
+            };
+
+            deps.push((dep, cap.to_owned()));
+        }
+
+        if deps.len() < service.deps.len() {
+            info!("Service \'{}\' will not be spawned", service.name);
+            continue;
         }
 
         // create a new registry with this service's deps
@@ -161,20 +242,372 @@ pub extern "C" fn run() {
         // provide this service to its dependents
         names_to_caps.insert(service.name.clone(), cap);
     }
+
+    supervise(&mut graph);
+}
+
+/// Restarts services that go down according to their [RestartPolicy].
+///
+/// Returns immediately if no service has a restart policy, and otherwise runs
+/// forever. Restarted services are provided with the same dependencies, but
+/// their dependents keep their capabilities to the old process.
+fn supervise(graph: &mut DiGraph<Node, Edge>) {
+    let supervisor = Mailbox::new();
+    let mut supervised = false;
+
+    // tag each monitored service with its node index to look it up when down
+    for idx in graph.node_indices() {
+        let Node::Service(service) = &mut graph[idx] else {
+            continue;
+        };
+
+        if service.config.restart == RestartPolicy::Never {
+            continue;
+        }
+
+        if let Some(process) = service.process.as_ref() {
+            supervisor.monitor_tagged(process, idx.index() as u64);
+            service.started_at = get_unix_time();
+            supervised = true;
+        }
+    }
+
+    if !supervised {
+        return;
+    }
+
+    // pairs of the UNIX time to restart each down service at and its index
+    let mut pending: Vec<(u128, NodeIndex)> = Vec::new();
+
+    loop {
+        // restart every service whose delay has passed
+        let now = get_unix_time();
+        let (due, waiting) = pending.into_iter().partition(|(at, _)| *at <= now);
+        pending = waiting;
+
+        for (_, idx) in due {
+            let Node::Service(service) = &mut graph[idx] else {
+                continue;
+            };
+
+            info!("Restarting service \'{}\'", service.name);
+            let process = service.respawn();
+            service.started_at = get_unix_time();
+            supervisor.monitor_tagged(&process, idx.index() as u64);
+        }
+
+        // wait for a service to go down or for the next restart to be due
+        let signal = match pending.iter().map(|(at, _)| *at).min() {
+            Some(at) => {
+                let timeout = at.saturating_sub(get_unix_time());
+                let timeout = Duration::from_nanos(timeout.try_into().unwrap_or(u64::MAX));
+                match supervisor.recv_signal_timeout(timeout) {
+                    Some(signal) => signal,
+                    None => continue,
+                }
+            }
+            None => supervisor.recv_signal(),
+        };
+
+        let Signal::Down { tag, .. } = signal else {
+            continue;
+        };
+
+        let idx = NodeIndex::new(tag as usize);
+        let Some(Node::Service(service)) = graph.node_weight_mut(idx) else {
+            continue;
+        };
+
+        // services that ran for long enough start over with a short delay
+        let now = get_unix_time();
+        if now.saturating_sub(service.started_at) >= RESTART_RESET_TIME.as_nanos() {
+            service.restarts = 0;
+        }
+
+        let delay = RESTART_BASE_DELAY
+            .saturating_mul(1 << service.restarts.min(16))
+            .min(RESTART_MAX_DELAY);
+
+        service.restarts += 1;
+
+        warn!(
+            "Service \'{}\' went down; restarting in {:?}",
+            service.name, delay
+        );
+
+        pending.push((now + delay.as_nanos(), idx));
+    }
+}
+
+/// Resolves the dependencies of each service and links them into a graph.
+///
+/// Services whose needed dependencies can't all be resolved are left out of
+/// the graph. Services that they wait for which are left out or don't exist
+/// are ignored. Returns the graph and a list of each left-out service's name
+/// with the dependency that it was missing.
+pub fn build_graph(
+    services: Vec<Service>,
+    native_services: &[String],
+) -> (DiGraph<Node, Edge>, Vec<(String, String)>) {
+    let configs = services
+        .iter()
+        .map(|service| (service.name.as_str(), &service.config))
+        .collect();
+
+    let (mut resolved, missing) = resolve_dependencies(configs, native_services);
+
+    // add all resolved guest services into a dependency graph structure
+    let mut graph = DiGraph::<Node, Edge>::new();
+
+    // map of service names to indices within the graph
+    let mut names_to_idxs = HashMap::new();
+
+    for mut service in services {
+        let Some(deps) = resolved.remove(&service.name) else {
+            continue;
+        };
+
+        service.deps = deps;
+        let name = service.name.clone();
+        let idx = graph.add_node(Node::Service(Box::new(service)));
+        names_to_idxs.insert(name, idx);
+    }
+
+    // map of milestone names to indices within the graph
+    let mut milestones = HashMap::new();
+
+    // add dependency edges to graph
+    for idx in graph.node_indices().collect::<Vec<_>>() {
+        let Node::Service(service) = &graph[idx] else {
+            continue;
+        };
+
+        let deps = service.deps.clone();
+        let config = service.config.clone();
+
+        for (_dep, provider) in deps {
+            // native services have no node and are skipped, since their
+            // capabilities are retrieved during service startup
+            if let Some(dep_idx) = names_to_idxs.get(&provider) {
+                graph.add_edge(*dep_idx, idx, Edge::Need);
+            }
+        }
+
+        // soft ordering edges; unavailable services are ignored
+        for name in config.dependencies.waits_for {
+            if let Some(dep_idx) = names_to_idxs.get(&name) {
+                graph.add_edge(*dep_idx, idx, Edge::WaitsFor);
+            }
+        }
+
+        for name in config.dependencies.milestone {
+            let milestone_idx = get_milestone(&mut graph, &mut milestones, name);
+            graph.add_edge(milestone_idx, idx, Edge::Milestone);
+        }
+
+        for name in config.targets {
+            let milestone_idx = get_milestone(&mut graph, &mut milestones, name);
+            graph.add_edge(idx, milestone_idx, Edge::Milestone);
+        }
+    }
+
+    (graph, missing)
+}
+
+/// Removes every node that's part of a dependency cycle from the service
+/// graph.
+///
+/// Returns the names of the nodes in each removed cycle, as given by `name`.
+fn remove_cycles<N, E>(graph: &mut DiGraph<N, E>, name: impl Fn(&N) -> &str) -> Vec<Vec<String>> {
+    let mut cycles = Vec::new();
+    let mut cyclic = Vec::new();
+
+    for scc in tarjan_scc(&*graph) {
+        // a lone node is only a cycle if it depends on itself
+        if scc.len() == 1 && !graph.contains_edge(scc[0], scc[0]) {
+            continue;
+        }
+
+        cycles.push(
+            scc.iter()
+                .map(|idx| name(&graph[*idx]).to_string())
+                .collect(),
+        );
+        cyclic.extend(scc);
+    }
+
+    // removing a node moves the last node into its index, so remove from the
+    // back to keep the remaining indices valid
+    cyclic.sort_unstable_by(|a, b| b.cmp(a));
+    for idx in cyclic {
+        graph.remove_node(idx);
+    }
+
+    cycles
+}
+
+/// Gets the index of a milestone's node in the service graph, adding the node
+/// if it doesn't exist yet.
+fn get_milestone(
+    graph: &mut DiGraph<Node, Edge>,
+    milestones: &mut HashMap<String, NodeIndex>,
+    name: String,
+) -> NodeIndex {
+    *milestones
+        .entry(name.clone())
+        .or_insert_with(|| graph.add_node(Node::Milestone(name)))
+}
+
+/// Resolves the dependencies of each named service config.
+///
+/// Returns the resolved dependencies of each service by name (see
+/// [Providers::resolve]), and a list of each unresolvable service's name with
+/// the dependency that it was missing. Services that depend on unresolvable
+/// services are unresolvable themselves.
+fn resolve_dependencies(
+    mut services: Vec<(&str, &ServiceConfig)>,
+    native_services: &[String],
+) -> (HashMap<String, ResolvedDeps>, Vec<(String, String)>) {
+    let mut missing = Vec::new();
+
+    // repeat until no more services are removed, since removing a service may
+    // leave its dependents unresolvable
+    loop {
+        let providers = Providers::new(&services, native_services);
+        let mut resolved = HashMap::new();
+
+        for (name, config) in services.iter() {
+            match providers.resolve(&config.dependencies) {
+                Ok(deps) => {
+                    resolved.insert(name.to_string(), deps);
+                }
+                Err(dep) => missing.push((name.to_string(), dep)),
+            }
+        }
+
+        if resolved.len() == services.len() {
+            return (resolved, missing);
+        }
+
+        services.retain(|(name, _)| resolved.contains_key(*name));
+    }
+}
+
+/// A lookup of which service provides each service name and protocol.
+struct Providers<'a> {
+    /// The names of all guest services.
+    guest: HashSet<&'a str>,
+
+    /// The names of all native services.
+    native: &'a [String],
+
+    /// Maps each protocol to the name of the guest service implementing it.
+    protocols: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Providers<'a> {
+    fn new(services: &[(&'a str, &'a ServiceConfig)], native: &'a [String]) -> Self {
+        let guest = services.iter().map(|(name, _)| *name).collect();
+
+        // when multiple guest services implement a protocol, pick the first
+        // by name so that resolution doesn't depend on directory order
+        let mut protocols = HashMap::new();
+        for (name, config) in services.iter() {
+            for protocol in config.protocols.iter() {
+                protocols
+                    .entry(protocol.as_str())
+                    .and_modify(|provider: &mut &str| *provider = (*provider).min(name))
+                    .or_insert(*name);
+            }
+        }
+
+        Self {
+            guest,
+            native,
+            protocols,
+        }
+    }
+
+    /// Resolves a service's dependencies into pairs of the name that each is
+    /// depended on by and the name of the service providing it.
+    ///
+    /// Fails with the first dependency that no service provides.
+    fn resolve(&self, deps: &Dependencies) -> Result<ResolvedDeps, String> {
+        let mut resolved = Vec::new();
+
+        for dep in deps.need.iter() {
+            if !self.guest.contains(dep.as_str()) && !self.native.contains(dep) {
+                return Err(dep.clone());
+            }
+
+            resolved.push((dep.clone(), dep.clone()));
+        }
+
+        for protocol in deps.need_protocol.iter() {
+            // guest services advertise their protocols in their configs, and
+            // native services implement the protocol of their own name
+            let provider = match self.protocols.get(protocol.as_str()) {
+                Some(provider) => provider.to_string(),
+                None if self.native.contains(protocol) => protocol.clone(),
+                None => return Err(protocol.clone()),
+            };
+
+            resolved.push((protocol.clone(), provider));
+        }
+
+        Ok(resolved)
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct Dependencies {
+    /// Services that this service needs, by name.
+    ///
+    /// The service is not spawned if any of these are unavailable.
     #[serde(default)]
     pub need: Vec<String>,
 
+    /// Protocols that this service needs an implementation of.
+    ///
+    /// Each is satisfied by a guest service listing the protocol in its
+    /// [ServiceConfig::protocols], or else by a native service named after the
+    /// protocol, and is registered under the protocol's name.
+    #[serde(default)]
+    pub need_protocol: Vec<String>,
+
+    /// Milestones that this service starts after.
+    ///
+    /// A milestone is reached once every service listing it in its
+    /// [ServiceConfig::targets] has started.
     #[serde(default)]
     pub milestone: Vec<String>,
 
+    /// Services that this service starts after if they're available, by name.
+    ///
+    /// Unlike [Dependencies::need], these are not registered with the service
+    /// and don't prevent it from spawning when they're unavailable.
     #[serde(default)]
     pub waits_for: Vec<String>,
 }
 
+/// When a service is restarted after its process goes down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// The service is never restarted.
+    #[default]
+    Never,
+
+    /// The service is restarted when it fails.
+    ///
+    /// Hearth doesn't report why a process exits yet, so every exit counts
+    /// as a failure and this behaves like [RestartPolicy::Always].
+    OnFailure,
+
+    /// The service is always restarted.
+    Always,
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct License {
     pub name: String,
@@ -191,13 +624,140 @@ pub struct ServiceConfig {
     #[serde(default)]
     pub license: Vec<License>,
 
+    /// The milestones that this service is a part of.
     #[serde(default)]
     pub targets: Vec<String>,
+
+    /// The protocols that this service implements.
+    #[serde(default)]
+    pub protocols: Vec<String>,
+
+    /// When this service is restarted after its process goes down.
+    #[serde(default)]
+    pub restart: RestartPolicy,
+
+    /// The scheduling priority to spawn this service's process with.
+    #[serde(default)]
+    pub priority: u32,
 }
 
-fn get_config(name: &str) -> Option<ServiceConfig> {
+fn get_config(name: &str) -> Result<ServiceConfig, ConfigError> {
     let config_path = format!("{}/{}/service.toml", SEARCH_DIR, name);
-    let config_data = read_file(&config_path).ok()?;
-    let config_str = String::from_utf8(config_data).unwrap();
-    toml::from_str(&config_str).ok()
+    load_config(&config_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn need_protocol() {
+        let configs: Vec<(&str, ServiceConfig)> = vec![
+            ("renderer", "protocols = [\"hearth.Renderer\"]"),
+            ("app", "dependencies.need_protocol = [\"hearth.Renderer\"]"),
+            (
+                "fs_user",
+                "dependencies.need_protocol = [\"hearth.fs.Filesystem\"]",
+            ),
+            (
+                "orphan",
+                "dependencies.need_protocol = [\"hearth.Missing\"]",
+            ),
+            ("orphan_dependent", "dependencies.need = [\"orphan\"]"),
+        ]
+        .into_iter()
+        .map(|(name, config)| (name, toml::from_str(config).unwrap()))
+        .collect();
+
+        let services = configs
+            .iter()
+            .map(|(name, config)| (*name, config))
+            .collect();
+        let native = vec!["hearth.fs.Filesystem".to_string()];
+        let (resolved, mut missing) = resolve_dependencies(services, &native);
+        missing.sort();
+
+        let dep = |dep: &str, provider: &str| vec![(dep.to_string(), provider.to_string())];
+        assert_eq!(resolved.len(), 3);
+        assert_eq!(resolved["renderer"], vec![]);
+        assert_eq!(resolved["app"], dep("hearth.Renderer", "renderer"));
+        assert_eq!(
+            resolved["fs_user"],
+            dep("hearth.fs.Filesystem", "hearth.fs.Filesystem")
+        );
+
+        assert_eq!(
+            missing,
+            [
+                ("orphan".to_string(), "hearth.Missing".to_string()),
+                ("orphan_dependent".to_string(), "orphan".to_string()),
+            ]
+        );
+    }
+
+    /// Builds a graph of named nodes with the given edges.
+    fn named_graph(names: &[&str], edges: &[(usize, usize)]) -> DiGraph<String, ()> {
+        let mut graph = DiGraph::new();
+
+        let nodes: Vec<_> = names
+            .iter()
+            .map(|name| graph.add_node(name.to_string()))
+            .collect();
+
+        for (from, to) in edges {
+            graph.add_edge(nodes[*from], nodes[*to], ());
+        }
+
+        graph
+    }
+
+    /// Gets the sorted names of the nodes left in a graph.
+    fn node_names(graph: &DiGraph<String, ()>) -> Vec<&str> {
+        let mut names: Vec<_> = graph.node_weights().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// Sorts each cycle's names and then the cycles themselves.
+    fn sorted(mut cycles: Vec<Vec<String>>) -> Vec<Vec<String>> {
+        cycles.iter_mut().for_each(|cycle| cycle.sort());
+        cycles.sort();
+        cycles
+    }
+
+    #[test]
+    fn remove_two_node_cycle() {
+        let mut graph = named_graph(&["a", "b", "c"], &[(0, 1), (1, 0), (2, 0)]);
+        let cycles = remove_cycles(&mut graph, String::as_str);
+        assert_eq!(sorted(cycles), vec![vec!["a", "b"]]);
+        assert_eq!(node_names(&graph), ["c"]);
+        assert_eq!(graph.edge_count(), 0);
+    }
+
+    #[test]
+    fn remove_self_loop() {
+        let mut graph = named_graph(&["a", "b"], &[(0, 0), (1, 0)]);
+        let cycles = remove_cycles(&mut graph, String::as_str);
+        assert_eq!(cycles, vec![vec!["a"]]);
+        assert_eq!(node_names(&graph), ["b"]);
+    }
+
+    #[test]
+    fn remove_multiple_cycles() {
+        let edges = [(0, 1), (1, 0), (2, 3), (3, 4), (4, 2), (5, 0), (5, 2)];
+        let mut graph = named_graph(&["a", "b", "c", "d", "e", "f"], &edges);
+        let cycles = remove_cycles(&mut graph, String::as_str);
+        assert_eq!(sorted(cycles), vec![vec!["a", "b"], vec!["c", "d", "e"]]);
+        assert_eq!(node_names(&graph), ["f"]);
+    }
+
+    #[test]
+    fn acyclic_graph_unchanged() {
+        let edges = [(0, 1), (1, 2), (0, 2)];
+        let mut graph = named_graph(&["a", "b", "c"], &edges);
+        let cycles = remove_cycles(&mut graph, String::as_str);
+        assert!(cycles.is_empty());
+        assert_eq!(node_names(&graph), ["a", "b", "c"]);
+        assert_eq!(graph.edge_count(), 3);
+    }
 }
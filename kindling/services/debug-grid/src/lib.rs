@@ -47,6 +47,7 @@ pub extern "C" fn run() {
     dd.update(DebugDrawMesh {
         indices: (0..vertices.len() as u32).collect(),
         vertices,
+        topology: DebugDrawTopology::Lines,
     });
     std::mem::forget(dd);
 }
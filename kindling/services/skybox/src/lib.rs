@@ -39,6 +39,9 @@ pub extern "C" fn run() {
         label: None,
         size: (1024, 1024).into(),
         data,
+        format: TextureFormat::Rgba8UnormSrgb,
+        generate_mips: false,
+        sampling: TextureSampling::Linear,
     });
 
     set_skybox(&texture);
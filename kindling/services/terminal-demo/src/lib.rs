@@ -16,9 +16,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
-
-use hearth_guest::{terminal::TerminalState, Color};
+use hearth_guest::{
+    terminal::{TerminalPalette, TerminalState},
+    Color,
+};
 use kindling_host::prelude::{
     glam::{vec3, Mat4, Vec3},
     *,
@@ -45,7 +46,8 @@ pub extern "C" fn run() {
             opacity: 1.0,
             padding: Default::default(),
             units_per_em: 0.06,
-            colors: palette.to_ansi(),
+            colors: Default::default(),
+            palette: Some(palette.to_terminal_palette()),
         })
     });
 
@@ -86,28 +88,30 @@ fn c(rgb: u32) -> Color {
 }
 
 impl Palette {
-    /// Convert a palette into a standard terminal color map.
-    pub fn to_ansi(&self) -> HashMap<usize, Color> {
-        FromIterator::from_iter([
-            (0x0, self.black),   // black
-            (0x1, self.red),     // red
-            (0x2, self.green),   // green
-            (0x3, self.yellow),  // yellow
-            (0x4, self.blue),    // blue
-            (0x5, self.magenta), // magenta
-            (0x6, self.cyan),    // cyan
-            (0x7, self.white),   // white
-            (0x8, self.black),   // bright black
-            (0x9, self.red),     // bright red
-            (0xA, self.green),   // bright green
-            (0xB, self.yellow),  // bright yellow
-            (0xC, self.blue),    // bright blue
-            (0xD, self.magenta), // bright magenta
-            (0xE, self.cyan),    // bright cyan
-            (0xF, self.white),   // bright white
-            (0x100, self.fg),    // foreground
-            (0x101, self.bg),    // background
-        ])
+    /// Convert a palette into a terminal palette.
+    pub fn to_terminal_palette(&self) -> TerminalPalette {
+        // the bright variants reuse the normal colors
+        let normal = [
+            self.black,
+            self.red,
+            self.green,
+            self.yellow,
+            self.blue,
+            self.magenta,
+            self.cyan,
+            self.white,
+        ];
+
+        let mut ansi = [Color(0); 16];
+        ansi[..8].copy_from_slice(&normal);
+        ansi[8..].copy_from_slice(&normal);
+
+        TerminalPalette {
+            ansi,
+            foreground: self.fg,
+            background: self.bg,
+            cursor: None,
+        }
     }
 
     pub fn rose_pine() -> Self {
@@ -25,7 +25,7 @@ use std::{
 
 use clap::Parser;
 use hearth_network::{auth::login, connection::Connection};
-use hearth_rend3::Rend3Plugin;
+use hearth_rend3::{rend3::types::SampleCount, Rend3Plugin};
 use hearth_runtime::{
     flue::OwnedCapability,
     runtime::{Plugin, Runtime, RuntimeBuilder, RuntimeConfig},
@@ -62,6 +62,19 @@ pub struct Args {
     /// A path to the guest-side filesystem root.
     #[clap(short, long)]
     pub root: PathBuf,
+
+    /// The maximum total size in bytes of stored lumps. Least recently used
+    /// lumps are evicted past it.
+    #[clap(long)]
+    pub lump_capacity: Option<usize>,
+
+    /// The size in bytes from which stored lumps are compressed.
+    #[clap(long)]
+    pub lump_compression: Option<usize>,
+
+    /// Anti-alias 3D rendering with 4x MSAA.
+    #[clap(long)]
+    pub msaa: bool,
 }
 
 fn main() {
@@ -105,7 +118,11 @@ fn main() {
     window.run();
 }
 
-async fn async_main(args: Args, rend3_plugin: Rend3Plugin, window_plugin: WindowPlugin) {
+async fn async_main(args: Args, mut rend3_plugin: Rend3Plugin, window_plugin: WindowPlugin) {
+    if args.msaa {
+        rend3_plugin.set_sample_count(SampleCount::Four);
+    }
+
     let init = args.init.unwrap_or(args.root.join("init.wasm"));
     let mut builder = RuntimeBuilder::new();
     builder.add_plugin(hearth_time::TimePlugin);
@@ -118,7 +135,9 @@ async fn async_main(args: Args, rend3_plugin: Rend3Plugin, window_plugin: Window
     builder.add_plugin(hearth_debug_draw::DebugDrawPlugin::default());
     builder.add_plugin(hearth_canvas::CanvasPlugin);
     builder.add_plugin(hearth_terminal::TerminalPlugin::default());
-    builder.add_plugin(hearth_daemon::DaemonPlugin::default());
+    let daemon = hearth_daemon::DaemonPlugin::default();
+    let daemon_shutdown = daemon.shutdown_handle();
+    builder.add_plugin(daemon);
 
     if let (Some(server), password) = (args.server, args.password) {
         builder.add_plugin(ClientPlugin { server, password });
@@ -126,12 +145,17 @@ async fn async_main(args: Args, rend3_plugin: Rend3Plugin, window_plugin: Window
         info!("Running in serverless mode");
     }
 
-    let config = RuntimeConfig {};
+    let config = RuntimeConfig {
+        lump_capacity: args.lump_capacity,
+        lump_compression: args.lump_compression,
+        ..Default::default()
+    };
 
     let _runtime = builder.run(config).await;
 
     hearth_runtime::wait_for_interrupt().await;
     info!("Ctrl+C hit; quitting client");
+    daemon_shutdown.shutdown().await;
 }
 
 /// The plugin that implements the client side of a network connection.
@@ -116,8 +116,10 @@ struct Window {
     /// Sender of frame requests to the rend3 renderer.
     frame_request_tx: mpsc::UnboundedSender<FrameRequest>,
 
-    /// This window's current camera in the rend3 world..
-    camera: Camera,
+    /// This window's current camera in the rend3 world, if it has set one.
+    ///
+    /// When unset, the renderer's own camera is used.
+    camera: Option<Camera>,
 
     /// Outgoing window events.
     events_tx: mpsc::UnboundedSender<WindowEvent>,
@@ -160,7 +162,7 @@ impl Window {
             iad,
             surface,
             config,
-            camera: Camera::default(),
+            camera: None,
             frame_request_tx,
             events_tx,
             last_redraw: Instant::now(),
@@ -371,10 +373,10 @@ impl WindowCtx {
                         window.window.set_cursor_visible(visible)
                     }
                     WindowRxMessage::SetCamera { vfov, near, view } => {
-                        window.camera = Camera {
+                        window.camera = Some(Camera {
                             projection: CameraProjection::Perspective { vfov, near },
                             view,
-                        }
+                        });
                     }
                     WindowRxMessage::BroadcastState => window.broadcast_state(),
                     WindowRxMessage::Quit => control_flow.set_exit(),
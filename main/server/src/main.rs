@@ -54,6 +54,15 @@ pub struct Args {
     /// A path to the guest-side filesystem root.
     #[clap(short, long)]
     pub root: PathBuf,
+
+    /// The maximum total size in bytes of stored lumps. Least recently used
+    /// lumps are evicted past it.
+    #[clap(long)]
+    pub lump_capacity: Option<usize>,
+
+    /// The size in bytes from which stored lumps are compressed.
+    #[clap(long)]
+    pub lump_compression: Option<usize>,
 }
 
 #[tokio::main]
@@ -65,7 +74,11 @@ async fn main() {
     let authenticator = Arc::new(authenticator);
 
     debug!("Initializing runtime");
-    let config = RuntimeConfig {};
+    let config = RuntimeConfig {
+        lump_capacity: args.lump_capacity,
+        lump_compression: args.lump_compression,
+        ..Default::default()
+    };
 
     let (network_root_tx, network_root_rx) = oneshot::channel();
     let init = args.init.unwrap_or(args.root.join("init.wasm"));
@@ -77,7 +90,9 @@ async fn main() {
     builder.add_plugin(hearth_wasm::WasmPlugin::default());
     builder.add_plugin(hearth_fs::FsPlugin::new(args.root));
     builder.add_plugin(init);
-    builder.add_plugin(hearth_daemon::DaemonPlugin::default());
+    let daemon = hearth_daemon::DaemonPlugin::default();
+    let daemon_shutdown = daemon.shutdown_handle();
+    builder.add_plugin(daemon);
     let runtime = builder.run(config).await;
 
     if let Some(addr) = args.bind {
@@ -91,6 +106,7 @@ async fn main() {
     hearth_runtime::wait_for_interrupt().await;
 
     info!("Interrupt received; exiting server");
+    daemon_shutdown.shutdown().await;
 }
 
 async fn bind(
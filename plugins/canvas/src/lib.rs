@@ -29,14 +29,28 @@ use hearth_rend3::{
     Node, Rend3Plugin, Routine, RoutineInfo,
 };
 use hearth_runtime::{
-    async_trait,
+    anyhow, async_trait,
     flue::Permissions,
     hearth_macros::GetProcessMetadata,
-    hearth_schema::canvas::*,
+    hearth_schema::{canvas::*, versioned::HearthProtocol},
     runtime::{Plugin, RuntimeBuilder},
+    tokio::sync::oneshot,
+    tracing::{debug, warn},
     utils::*,
 };
 
+/// The order that canvases are drawn in, by blend mode and whether they're on
+/// top.
+///
+/// Blended canvases are drawn after opaque ones so that they blend over them,
+/// and canvases on top are drawn last so that nothing is drawn over them.
+const CANVAS_DRAW_ORDER: [(CanvasBlendMode, bool); 4] = [
+    (CanvasBlendMode::Opaque, false),
+    (CanvasBlendMode::Alpha, false),
+    (CanvasBlendMode::Opaque, true),
+    (CanvasBlendMode::Alpha, true),
+];
+
 /// A specific kind of operation on a canvas.
 pub enum CanvasOperationKind {
     /// Create a new canvas with this ID.
@@ -44,6 +58,8 @@ pub enum CanvasOperationKind {
         position: Position,
         pixels: Pixels,
         sampling: CanvasSamplingMode,
+        blend: CanvasBlendMode,
+        on_top: bool,
     },
 
     /// Destroy this canvas.
@@ -51,6 +67,15 @@ pub enum CanvasOperationKind {
 
     /// Update this canvas.
     Update(CanvasUpdate),
+
+    /// Copy a region of this canvas into a [PendingReadback].
+    ///
+    /// `on_complete` is dropped without a response if the canvas doesn't
+    /// exist.
+    Readback {
+        region: Region,
+        on_complete: oneshot::Sender<PendingReadback>,
+    },
 }
 
 /// An identifier for a specific canvas within a [CanvasRoutine].
@@ -74,6 +99,8 @@ pub struct CanvasDraw {
     position: Position,
     ubo: Buffer,
     sampling_mode: CanvasSamplingMode,
+    blend_mode: CanvasBlendMode,
+    on_top: bool,
     width: u32,
     height: u32,
     texture: Texture,
@@ -81,12 +108,15 @@ pub struct CanvasDraw {
 }
 
 impl CanvasDraw {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &Device,
         queue: &Queue,
         bgl: &BindGroupLayout,
         sampler: &Sampler,
         sampling_mode: CanvasSamplingMode,
+        blend_mode: CanvasBlendMode,
+        on_top: bool,
         position: Position,
         pixels: Pixels,
     ) -> Self {
@@ -109,6 +139,8 @@ impl CanvasDraw {
             height,
             texture,
             sampling_mode,
+            blend_mode,
+            on_top,
             bind_group,
         }
     }
@@ -220,6 +252,66 @@ impl CanvasDraw {
         );
     }
 
+    /// Encodes a copy of a region of this canvas into a new staging buffer.
+    ///
+    /// The region is clipped to the canvas's bounds.
+    pub fn readback(&self, device: &Device, queue: &Queue, region: Region) -> PendingReadback {
+        let width = region.width.min(self.width.saturating_sub(region.x));
+        let height = region.height.min(self.height.saturating_sub(region.y));
+
+        // buffer copies need each row to be aligned
+        let align = COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (width * 4).div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("canvas readback buffer"),
+            size: (padded_bytes_per_row * height) as BufferAddress,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        if width > 0 && height > 0 {
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("canvas readback encoder"),
+            });
+
+            encoder.copy_texture_to_buffer(
+                ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: region.x,
+                        y: region.y,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                        rows_per_image: None,
+                    },
+                },
+                Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+
+            queue.submit(Some(encoder.finish()));
+        }
+
+        PendingReadback {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+        }
+    }
+
     /// Helper function to recreate the canvas's texture object with the given pixels.
     fn create_texture(device: &Device, queue: &Queue, mut pixels: Pixels) -> Texture {
         // correct the pixel data length
@@ -240,7 +332,9 @@ impl CanvasDraw {
                 sample_count: 1,
                 dimension: TextureDimension::D2,
                 format: TextureFormat::Rgba8UnormSrgb,
-                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::COPY_SRC,
             },
             &pixels.data,
         )
@@ -278,13 +372,50 @@ impl CanvasDraw {
     }
 }
 
+/// A copy of a region of a canvas that is waiting to be read from the GPU.
+pub struct PendingReadback {
+    buffer: Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl PendingReadback {
+    /// Waits for the copy to finish and reads the copied pixels.
+    ///
+    /// The mapping completes when the device is next maintained, which
+    /// happens whenever the render loop submits a frame, so this doesn't
+    /// block on polling the device.
+    pub async fn read(self) -> Result<Pixels, BufferAsyncError> {
+        let row_len = self.width as usize * 4;
+        let mut data = Vec::with_capacity(row_len * self.height as usize);
+
+        if row_len > 0 && self.height > 0 {
+            let slice = self.buffer.slice(..);
+            slice.map_async(MapMode::Read).await?;
+
+            let mapped = slice.get_mapped_range();
+            for row in mapped.chunks(self.padded_bytes_per_row as usize) {
+                data.extend_from_slice(&row[..row_len]);
+            }
+        }
+
+        Ok(Pixels {
+            width: self.width,
+            height: self.height,
+            data,
+        })
+    }
+}
+
 /// The canvas rend3 draw routine.
 pub struct CanvasRoutine {
     ops_rx: Receiver<CanvasOperation>,
     device: Arc<Device>,
     queue: Arc<Queue>,
     bgl: BindGroupLayout,
-    pipeline: RenderPipeline,
+    /// The canvas pipelines, keyed by blend mode and whether they draw on top.
+    pipelines: HashMap<(CanvasBlendMode, bool), RenderPipeline>,
     sampler: Sampler,
     draws: HashMap<CanvasId, CanvasDraw>,
 }
@@ -333,37 +464,59 @@ impl CanvasRoutine {
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("canvas pipeline"),
-            layout: Some(&layout),
-            vertex: VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleStrip,
-                ..Default::default()
-            },
-            depth_stencil: Some(DepthStencilState {
-                format: TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: CompareFunction::GreaterEqual,
-                stencil: Default::default(),
-                bias: Default::default(),
-            }),
-            multisample: Default::default(),
-            fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[ColorTargetState {
-                    format: rend3.surface_format,
-                    blend: None,
-                    write_mask: ColorWrites::COLOR,
-                }],
-            }),
-            multiview: None,
-        });
+        let create_pipeline = |blend_mode, on_top| {
+            let (blend, depth_write_enabled) = match blend_mode {
+                CanvasBlendMode::Opaque => (None, true),
+                // blended canvases must not hide what is drawn behind them later
+                CanvasBlendMode::Alpha => (Some(BlendState::ALPHA_BLENDING), false),
+            };
+
+            let (depth_write_enabled, depth_compare) = if on_top {
+                (false, CompareFunction::Always)
+            } else {
+                (depth_write_enabled, CompareFunction::GreaterEqual)
+            };
+
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("canvas pipeline"),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: PrimitiveState {
+                    topology: PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: Some(DepthStencilState {
+                    format: TextureFormat::Depth32Float,
+                    depth_write_enabled,
+                    depth_compare,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: MultisampleState {
+                    count: rend3.sample_count as u32,
+                    ..Default::default()
+                },
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[ColorTargetState {
+                        format: rend3.surface_format,
+                        blend,
+                        write_mask: ColorWrites::COLOR,
+                    }],
+                }),
+                multiview: None,
+            })
+        };
+
+        let pipelines = CANVAS_DRAW_ORDER
+            .into_iter()
+            .map(|key| (key, create_pipeline(key.0, key.1)))
+            .collect();
 
         let sampler = device.create_sampler(&SamplerDescriptor {
             address_mode_u: AddressMode::ClampToEdge,
@@ -380,7 +533,7 @@ impl CanvasRoutine {
             device: rend3.iad.device.to_owned(),
             queue: rend3.iad.queue.to_owned(),
             bgl,
-            pipeline,
+            pipelines,
             sampler,
             draws: HashMap::new(),
         }
@@ -399,15 +552,24 @@ impl Routine for CanvasRoutine {
                     match update {
                         CanvasUpdate::Relocate(position) => draw.set_position(position),
                         CanvasUpdate::Blit(blit) => draw.blit(&self.queue, blit),
+                        CanvasUpdate::BlitBatch(blits) => {
+                            for blit in blits {
+                                draw.blit(&self.queue, blit);
+                            }
+                        }
                         CanvasUpdate::Resize(pixels) => {
                             draw.resize(&self.device, &self.queue, pixels, &self.bgl, &self.sampler)
                         }
+                        // instances send readbacks as their own operation
+                        CanvasUpdate::Readback(_) => {}
                     }
                 }
                 CanvasOperationKind::Create {
                     position,
                     pixels,
                     sampling,
+                    blend,
+                    on_top,
                 } => {
                     self.draws.insert(
                         id,
@@ -417,6 +579,8 @@ impl Routine for CanvasRoutine {
                             &self.bgl,
                             &self.sampler,
                             sampling,
+                            blend,
+                            on_top,
                             position,
                             pixels,
                         ),
@@ -425,6 +589,17 @@ impl Routine for CanvasRoutine {
                 CanvasOperationKind::Destroy => {
                     self.draws.remove(&id);
                 }
+                CanvasOperationKind::Readback {
+                    region,
+                    on_complete,
+                } => {
+                    let Some(draw) = self.draws.get(&id) else {
+                        continue;
+                    };
+
+                    let pending = draw.readback(&self.device, &self.queue, region);
+                    let _ = on_complete.send(pending); // ignore hangup
+                }
             }
         }
 
@@ -439,7 +614,7 @@ pub struct CanvasNode<'a> {
 
 impl<'a> Node<'a> for CanvasNode<'a> {
     fn draw<'graph>(&'graph self, info: &mut RoutineInfo<'_, 'graph>) {
-        let output = info.graph.add_surface_texture();
+        let output = info.output;
         let depth = info.state.depth;
 
         let mut builder = info.graph.add_node("canvas");
@@ -449,7 +624,7 @@ impl<'a> Node<'a> for CanvasNode<'a> {
         let rpass_handle = builder.add_renderpass(RenderPassTargets {
             targets: vec![RenderPassTarget {
                 color: output_handle,
-                clear: Color::BLACK,
+                clear: info.clear_color,
                 resolve: None,
             }],
             depth_stencil: Some(RenderPassDepthTarget {
@@ -467,18 +642,39 @@ impl<'a> Node<'a> for CanvasNode<'a> {
                 let rpass = encoder_or_pass.get_rpass(rpass_handle);
                 let vp = graph_data.camera_manager.view_proj();
 
-                rpass.set_pipeline(&routine.pipeline);
+                for key in CANVAS_DRAW_ORDER {
+                    rpass.set_pipeline(&routine.pipelines[&key]);
 
-                for draw in routine.draws.values() {
-                    draw.update_ubo(&routine.queue, vp);
-                    rpass.set_bind_group(0, &draw.bind_group, &[]);
-                    rpass.draw(0..4, 0..1);
+                    for draw in routine.draws.values() {
+                        if (draw.blend_mode, draw.on_top) != key {
+                            continue;
+                        }
+
+                        draw.update_ubo(&routine.queue, vp);
+                        rpass.set_bind_group(0, &draw.bind_group, &[]);
+                        rpass.draw(0..4, 0..1);
+                    }
                 }
             },
         );
     }
 }
 
+/// Checks that a pixel buffer fits within a GPU texture.
+///
+/// wgpu panics when creating empty textures or textures larger than the
+/// device's limits, so guest-supplied sizes must be checked before they reach
+/// the canvas routine.
+fn validate_size(pixels: &Pixels, max: u32) -> Result<(), FactoryError> {
+    let Pixels { width, height, .. } = *pixels;
+
+    if width == 0 || height == 0 || width > max || height > max {
+        Err(FactoryError::InvalidSize { width, height, max })
+    } else {
+        Ok(())
+    }
+}
+
 /// A canvas process. Processes [CanvasUpdate].
 #[derive(GetProcessMetadata)]
 pub struct CanvasInstance {
@@ -487,6 +683,9 @@ pub struct CanvasInstance {
 
     /// A sender to the canvas routine.
     ops_tx: Sender<CanvasOperation>,
+
+    /// The maximum width and height of this canvas's pixel buffer.
+    max_size: u32,
 }
 
 impl Drop for CanvasInstance {
@@ -499,10 +698,59 @@ impl Drop for CanvasInstance {
 impl SinkProcess for CanvasInstance {
     type Message = CanvasUpdate;
 
+    fn decode(data: &[u8]) -> anyhow::Result<Self::Message> {
+        Ok(CanvasUpdate::try_decode_versioned(data)?)
+    }
+
     async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
-        let _ = self
-            .ops_tx
-            .send((self.id, CanvasOperationKind::Update(message.data)));
+        if let CanvasUpdate::Resize(pixels) = &message.data {
+            if let Err(err) = validate_size(pixels, self.max_size) {
+                warn!("ignoring canvas resize: {err:?}");
+                return;
+            }
+        }
+
+        let CanvasUpdate::Readback(region) = message.data else {
+            let _ = self
+                .ops_tx
+                .send((self.id, CanvasOperationKind::Update(message.data)));
+            return;
+        };
+
+        let Some(reply) = message.caps.first() else {
+            debug!("canvas readback has no reply address");
+            return;
+        };
+
+        let (on_complete, on_complete_rx) = oneshot::channel();
+        let readback = CanvasOperationKind::Readback {
+            region,
+            on_complete,
+        };
+
+        let _ = self.ops_tx.send((self.id, readback));
+
+        // the routine only handles operations when it draws the next frame
+        let result = match on_complete_rx.await {
+            Ok(pending) => pending.read().await.map_err(|err| format!("{err:?}")),
+            Err(_) => Err("canvas routine hung up".to_string()),
+        };
+
+        // always reply so that the reader doesn't wait forever
+        let pixels = result.unwrap_or_else(|err| {
+            warn!("failed to read back canvas: {err}");
+
+            Pixels {
+                width: 0,
+                height: 0,
+                data: vec![],
+            }
+        });
+
+        let data = serde_json::to_vec(&pixels).unwrap();
+        if let Err(err) = reply.send(&data, &[]).await {
+            debug!("canvas readback reply error: {err:?}");
+        }
     }
 }
 
@@ -514,6 +762,11 @@ pub struct CanvasFactory {
 
     /// A sender to the canvas routine.
     ops_tx: Sender<CanvasOperation>,
+
+    /// The maximum width and height of canvas pixel buffers.
+    ///
+    /// Taken from the device's `max_texture_dimension_2d` limit.
+    max_size: u32,
 }
 
 #[async_trait]
@@ -530,7 +783,17 @@ impl RequestResponseProcess for CanvasFactory {
                 position,
                 pixels,
                 sampling,
+                blend,
+                on_top,
             } => {
+                // reject sizes that would crash the render thread
+                if let Err(err) = validate_size(pixels, self.max_size) {
+                    return ResponseInfo {
+                        data: Err(err),
+                        caps: vec![],
+                    };
+                }
+
                 // allocate a new ID
                 let id = self.next_id;
                 self.next_id += 1;
@@ -542,6 +805,8 @@ impl RequestResponseProcess for CanvasFactory {
                         position: position.to_owned(),
                         pixels: pixels.to_owned(),
                         sampling: sampling.to_owned(),
+                        blend: blend.to_owned(),
+                        on_top: *on_top,
                     },
                 ));
 
@@ -549,6 +814,7 @@ impl RequestResponseProcess for CanvasFactory {
                 let instance = CanvasInstance {
                     id,
                     ops_tx: self.ops_tx.clone(),
+                    max_size: self.max_size,
                 };
 
                 // spawn the instance child process
@@ -592,9 +858,15 @@ impl Plugin for CanvasPlugin {
             .get_plugin_mut::<Rend3Plugin>()
             .expect("rend3 plugin was not found");
 
+        let max_size = rend3.iad.device.limits().max_texture_dimension_2d;
         let (ops_tx, ops_rx) = flume::unbounded();
         let routine = CanvasRoutine::new(rend3, ops_rx);
-        rend3.add_routine(routine);
-        builder.add_plugin(CanvasFactory { next_id: 0, ops_tx });
+        // draw canvases above debug geometry
+        rend3.add_routine_ordered(200, routine);
+        builder.add_plugin(CanvasFactory {
+            next_id: 0,
+            ops_tx,
+            max_size,
+        });
     }
 }
@@ -16,54 +16,112 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::{
-    ops::{Deref, DerefMut},
-    path::PathBuf,
-    sync::Arc,
-};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use hearth_init::InitPlugin;
-use hearth_ipc::get_socket_path;
+use hearth_ipc::{get_auth_token, get_socket_path, server_handshake};
 use hearth_runtime::{
     connection::Connection,
     flue::OwnedCapability,
     runtime::{Plugin, Runtime, RuntimeBuilder},
     tokio::{
         self,
-        net::{UnixListener, UnixStream},
-        sync::oneshot,
+        io::{AsyncRead, AsyncWrite},
+        net::{TcpListener, UnixListener, UnixStream},
+        sync::{mpsc, oneshot, watch},
+        task::JoinHandle,
     },
 };
 
-pub struct Listener {
-    pub uds: UnixListener,
-    pub path: PathBuf,
+/// The transport that the IPC daemon listens for connections on.
+#[derive(Clone, Debug, Default)]
+pub enum DaemonTransport {
+    /// A Unix domain socket at the path given by [get_socket_path].
+    #[default]
+    Unix,
+
+    /// A TCP socket bound to the given address.
+    ///
+    /// Anyone who can reach this address could obtain the daemon's root
+    /// capability, so the daemon refuses to listen on TCP unless an auth
+    /// token is configured. See [get_auth_token].
+    Tcp(SocketAddr),
+}
+
+/// How long a client has to complete the authentication handshake.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Authenticates a client, if a token is given. Returns false if the client
+/// should be disconnected.
+async fn authenticate(
+    rx: &mut (impl AsyncRead + Unpin),
+    tx: &mut (impl AsyncWrite + Unpin),
+    token: Option<&[u8]>,
+) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+
+    let handshake = server_handshake(rx, tx, token);
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(Ok(true)) => true,
+        Ok(Ok(false)) => {
+            tracing::warn!("IPC client presented an invalid auth token");
+            false
+        }
+        Ok(Err(err)) => {
+            tracing::warn!("IPC auth handshake error: {:?}", err);
+            false
+        }
+        Err(_) => {
+            tracing::warn!("IPC auth handshake timed out");
+            false
+        }
+    }
+}
+
+/// A listener for incoming IPC connections.
+pub enum Listener {
+    /// A Unix domain socket listener. The socket file is removed on drop.
+    Unix { uds: UnixListener, path: PathBuf },
+
+    /// A TCP listener.
+    Tcp(TcpListener),
 }
 
 impl Drop for Listener {
     fn drop(&mut self) {
-        match std::fs::remove_file(&self.path) {
+        let Listener::Unix { path, .. } = self else {
+            return;
+        };
+
+        match std::fs::remove_file(path) {
             Ok(_) => {}
             Err(e) => tracing::error!("Could not delete UnixListener {:?}", e),
         }
     }
 }
 
-impl Deref for Listener {
-    type Target = UnixListener;
-
-    fn deref(&self) -> &UnixListener {
-        &self.uds
+impl Listener {
+    /// Creates a listener for the given transport.
+    pub async fn bind(transport: &DaemonTransport) -> std::io::Result<Self> {
+        match transport {
+            DaemonTransport::Unix => Self::new().await,
+            DaemonTransport::Tcp(addr) => Self::new_tcp(*addr).await,
+        }
     }
-}
 
-impl DerefMut for Listener {
-    fn deref_mut(&mut self) -> &mut UnixListener {
-        &mut self.uds
+    /// Creates a listener on a TCP socket.
+    ///
+    /// See [DaemonTransport::Tcp] for why this is not safe to expose without
+    /// authentication.
+    pub async fn new_tcp(addr: SocketAddr) -> std::io::Result<Self> {
+        tracing::info!("Listening on TCP address: {}", addr);
+        let tcp = TcpListener::bind(addr).await?;
+        Ok(Listener::Tcp(tcp))
     }
-}
 
-impl Listener {
+    /// Creates a listener on the Unix domain socket given by [get_socket_path].
     pub async fn new() -> std::io::Result<Self> {
         use std::io::{Error, ErrorKind};
 
@@ -97,32 +155,115 @@ impl Listener {
         tracing::info!("Making socket at: {:?}", sock_path);
         let uds = UnixListener::bind(&sock_path)?;
         let path = sock_path.to_path_buf();
-        Ok(Self { uds, path })
+        Ok(Listener::Unix { uds, path })
     }
 
-    pub async fn accept_next(&self) -> hearth_ipc::Connection {
-        let stream = loop {
-            match self.accept().await {
-                Ok((socket, addr)) => {
-                    tracing::debug!("Accepting IPC connection from {:?}", addr);
-                    break socket;
-                }
-                Err(err) => {
-                    tracing::error!("IPC listen error: {:?}", err);
-                }
+    /// Accepts incoming connections, logging any errors, until `authenticated`
+    /// is closed.
+    ///
+    /// Each client is authenticated in its own task so that slow clients
+    /// don't hold up the others. If a token is given, clients must present it
+    /// in a handshake before their connection is sent to `authenticated`.
+    /// Clients that fail the handshake are disconnected.
+    pub async fn accept_all(
+        &self,
+        token: Option<Arc<[u8]>>,
+        authenticated: mpsc::UnboundedSender<hearth_ipc::Connection>,
+    ) {
+        while !authenticated.is_closed() {
+            match self {
+                Listener::Unix { uds, .. } => match uds.accept().await {
+                    Ok((socket, addr)) => {
+                        tracing::debug!("Accepting IPC connection from {:?}", addr);
+                        let (rx, tx) = socket.into_split();
+                        spawn_handshake(rx, tx, token.clone(), authenticated.clone());
+                    }
+                    Err(err) => {
+                        tracing::error!("IPC listen error: {:?}", err);
+                    }
+                },
+                Listener::Tcp(tcp) => match tcp.accept().await {
+                    Ok((socket, addr)) => {
+                        tracing::debug!("Accepting TCP IPC connection from {}", addr);
+                        let (rx, tx) = socket.into_split();
+                        spawn_handshake(rx, tx, token.clone(), authenticated.clone());
+                    }
+                    Err(err) => {
+                        tracing::error!("TCP IPC listen error: {:?}", err);
+                    }
+                },
             }
-        };
+        }
+    }
+}
+
+/// Spawns a task that authenticates a newly-accepted client and sends its
+/// connection to `authenticated` if it succeeds.
+fn spawn_handshake(
+    mut rx: impl AsyncRead + Unpin + Send + 'static,
+    mut tx: impl AsyncWrite + Unpin + Send + 'static,
+    token: Option<Arc<[u8]>>,
+    authenticated: mpsc::UnboundedSender<hearth_ipc::Connection>,
+) {
+    tokio::spawn(async move {
+        if authenticate(&mut rx, &mut tx, token.as_deref()).await {
+            // the daemon may have stopped while the client authenticated
+            let _ = authenticated.send(hearth_ipc::Connection::new(rx, tx));
+        }
+    });
+}
+
+/// How long a shutting-down daemon waits for its connections to finish
+/// writing before closing them.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
 
-        let (rx, tx) = stream.into_split();
-        hearth_ipc::Connection::new(rx, tx)
+/// Waits until a stop signal has been sent or its sender has been dropped.
+async fn wait_for_stop(stop: &mut watch::Receiver<bool>) {
+    while !*stop.borrow_and_update() {
+        if stop.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// A handle for gracefully shutting down a [DaemonPlugin].
+#[derive(Clone)]
+pub struct DaemonShutdown {
+    stop: Arc<watch::Sender<bool>>,
+    stopped: Arc<watch::Sender<bool>>,
+}
+
+impl DaemonShutdown {
+    /// Stops the daemon from accepting new connections, then waits for
+    /// existing connections to finish writing for up to [DRAIN_TIMEOUT].
+    pub async fn shutdown(&self) {
+        let mut stopped = self.stopped.subscribe();
+        self.stop.send_replace(true);
+        wait_for_stop(&mut stopped).await;
     }
 }
 
-#[derive(Default)]
-pub struct DaemonPlugin {}
+/// A plugin that serves the root capability from the init system to IPC
+/// clients.
+pub struct DaemonPlugin {
+    /// The transport to listen on. Defaults to a Unix domain socket.
+    pub transport: DaemonTransport,
+
+    /// Signals the daemon to stop.
+    stop: Arc<watch::Sender<bool>>,
+
+    /// Signaled by the daemon once it has stopped.
+    stopped: Arc<watch::Sender<bool>>,
+}
+
+impl Default for DaemonPlugin {
+    fn default() -> Self {
+        Self::new(DaemonTransport::default())
+    }
+}
 
 impl Plugin for DaemonPlugin {
-    fn finalize(mut self, builder: &mut RuntimeBuilder) {
+    fn finalize(self, builder: &mut RuntimeBuilder) {
         let init = builder
             .get_plugin_mut::<InitPlugin>()
             .expect("InitPlugin not found");
@@ -132,48 +273,133 @@ impl Plugin for DaemonPlugin {
 
         builder.add_runner(move |runtime| {
             tokio::spawn(async move {
-                tracing::info!("Waiting for IPC daemon hook...");
-
-                let root_cap = match root_rx.await {
-                    Ok(root) => root,
-                    Err(err) => {
-                        tracing::warn!("error while waiting for daemon root cap: {}", err);
-                        return;
-                    }
-                };
-
-                tracing::info!("Listening on IPC daemon...");
-
-                let listener = match Listener::new().await {
-                    Ok(l) => l,
-                    Err(err) => {
-                        tracing::warn!("error while listening on IPC daemon: {}", err);
-                        return;
-                    }
-                };
-
-                loop {
-                    let transport = listener.accept_next().await;
-                    self.on_accept(root_cap.clone(), &runtime, transport);
-                }
+                let stopped = self.stopped.clone();
+                self.run(root_rx, runtime).await;
+                stopped.send_replace(true);
             });
         });
     }
 }
 
 impl DaemonPlugin {
+    /// Creates a daemon plugin that listens on the given transport.
+    pub fn new(transport: DaemonTransport) -> Self {
+        Self {
+            transport,
+            stop: Arc::new(watch::channel(false).0),
+            stopped: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Returns a handle that can be used to gracefully shut down this daemon
+    /// once the runtime is running.
+    pub fn shutdown_handle(&self) -> DaemonShutdown {
+        DaemonShutdown {
+            stop: self.stop.clone(),
+            stopped: self.stopped.clone(),
+        }
+    }
+
+    /// Serves IPC connections until the daemon is stopped, then drains them.
+    async fn run(mut self, root_rx: oneshot::Receiver<OwnedCapability>, runtime: Arc<Runtime>) {
+        let mut stop = self.stop.subscribe();
+
+        tracing::info!("Waiting for IPC daemon hook...");
+
+        let root = tokio::select! {
+            root = root_rx => root,
+            _ = wait_for_stop(&mut stop) => return,
+        };
+
+        let root_cap = match root {
+            Ok(root) => root,
+            Err(err) => {
+                tracing::warn!("error while waiting for daemon root cap: {}", err);
+                return;
+            }
+        };
+
+        let token = match get_auth_token() {
+            Ok(token) => token,
+            Err(err) => {
+                tracing::warn!("error while reading IPC auth token: {}", err);
+                return;
+            }
+        };
+
+        if token.is_none() && matches!(self.transport, DaemonTransport::Tcp(_)) {
+            tracing::warn!("refusing to listen on TCP without an IPC auth token");
+            return;
+        }
+
+        tracing::info!("Listening on IPC daemon...");
+
+        let listener = match Listener::bind(&self.transport).await {
+            Ok(l) => l,
+            Err(err) => {
+                tracing::warn!("error while listening on IPC daemon: {}", err);
+                return;
+            }
+        };
+
+        let token = token.map(Arc::from);
+        let (authenticated_tx, mut authenticated) = mpsc::unbounded_channel();
+        let accept = listener.accept_all(token, authenticated_tx);
+        tokio::pin!(accept);
+
+        let mut writers = Vec::new();
+        loop {
+            let transport = tokio::select! {
+                Some(transport) = authenticated.recv() => transport,
+                _ = &mut accept => break,
+                _ = wait_for_stop(&mut stop) => break,
+            };
+
+            writers.retain(|writer: &JoinHandle<()>| !writer.is_finished());
+            writers.push(self.on_accept(root_cap.clone(), &runtime, transport));
+        }
+
+        // disconnect clients that are still authenticating
+        drop(authenticated);
+        drop(accept);
+
+        // stop accepting connections and remove the socket
+        drop(listener);
+
+        tracing::info!("Draining {} IPC connection(s)...", writers.len());
+
+        let deadline = tokio::time::Instant::now() + DRAIN_TIMEOUT;
+        for writer in writers.iter_mut() {
+            if tokio::time::timeout_at(deadline, writer).await.is_err() {
+                tracing::warn!("timed out while draining IPC connections");
+                break;
+            }
+        }
+
+        for writer in writers {
+            writer.abort();
+        }
+
+        tracing::info!("IPC daemon stopped");
+    }
+
     /// Performs a connection handshake with an IPC client and adds the new
     /// connection to the runtime.
+    ///
+    /// Returns the connection's writer task, which finishes once the
+    /// connection has written all of its outgoing operations.
     pub fn on_accept(
         &mut self,
         root_cap: OwnedCapability,
         runtime: &Arc<Runtime>,
         transport: hearth_ipc::Connection,
-    ) {
+    ) -> JoinHandle<()> {
         tracing::info!("Beginning IPC connection");
         let conn = Connection::begin(runtime.post.clone(), transport.op_rx, transport.op_tx, None);
 
         tracing::info!("Sending the IPC client our root cap");
         conn.export_root(root_cap);
+
+        transport.writer
     }
 }
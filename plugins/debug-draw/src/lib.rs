@@ -16,15 +16,20 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    f32::consts::TAU,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bytemuck::{Pod, Zeroable};
 use flume::{unbounded, Receiver, Sender};
-use glam::Vec3;
+use glam::{vec3, Vec3, Vec4};
 use hearth_rend3::{
     rend3::graph::{DepthHandle, RenderPassDepthTarget, RenderPassTarget, RenderPassTargets},
     utils::DynamicMesh,
-    wgpu::*,
+    wgpu::{util::DeviceExt, *},
     Node, Rend3Plugin, Routine, RoutineInfo,
 };
 use hearth_runtime::{
@@ -33,7 +38,7 @@ use hearth_runtime::{
     runtime::{Plugin, RuntimeBuilder},
     utils::*,
 };
-use hearth_schema::debug_draw::*;
+use hearth_schema::{debug_draw::*, Color};
 use itertools::Itertools;
 
 #[repr(C)]
@@ -42,6 +47,19 @@ pub struct CameraUniform {
     pub mvp: glam::Mat4,
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct TintUniform {
+    pub tint: Vec4,
+}
+
+impl From<Color> for TintUniform {
+    fn from(color: Color) -> Self {
+        let tint = Vec4::from(color.to_linear_f32());
+        Self { tint }
+    }
+}
+
 /// GPU-ready debug draw vertex data.
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -69,9 +87,187 @@ impl Vertex {
     };
 }
 
+/// The number of line segments in each circle of a debug sphere.
+const SPHERE_SEGMENTS: u32 = 32;
+
+/// A transient shape added to a debug draw.
+struct Shape {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+
+    /// When this shape should be removed, if ever.
+    expires: Option<Instant>,
+}
+
+impl Shape {
+    /// Creates the shape for an `Add*` update. Returns `None` for other updates.
+    fn from_update(update: DebugDrawUpdate, now: Instant) -> Option<Self> {
+        use DebugDrawUpdate::*;
+        let (vertices, indices, ttl) = match update {
+            AddLine {
+                start,
+                end,
+                color,
+                ttl,
+            } => {
+                let vertices = [start, end].map(|position| Vertex {
+                    position,
+                    color: color.0,
+                });
+
+                (vertices.to_vec(), vec![0, 1], ttl)
+            }
+            AddBox {
+                center,
+                half_extents,
+                color,
+                ttl,
+            } => {
+                // corner index bits select the sign of each axis
+                let vertices = (0..8)
+                    .map(|corner| {
+                        let sign = |bit: u32| if corner & bit == 0 { -1.0 } else { 1.0 };
+                        let offset = vec3(sign(1), sign(2), sign(4));
+                        Vertex {
+                            position: center + offset * half_extents,
+                            color: color.0,
+                        }
+                    })
+                    .collect();
+
+                // connect each corner to the corners differing by one axis
+                let indices = (0..8u32)
+                    .flat_map(|corner| [1, 2, 4].map(|bit| (corner, corner ^ bit)))
+                    .filter(|(a, b)| a < b)
+                    .flat_map(|(a, b)| [a, b])
+                    .collect();
+
+                (vertices, indices, ttl)
+            }
+            AddSphere {
+                center,
+                radius,
+                color,
+                ttl,
+            } => {
+                // one circle around each axis
+                let mut vertices = Vec::new();
+                let mut indices = Vec::new();
+                for circle in 0..3 {
+                    let base = circle * SPHERE_SEGMENTS;
+                    for segment in 0..SPHERE_SEGMENTS {
+                        let angle = segment as f32 / SPHERE_SEGMENTS as f32 * TAU;
+                        let (sin, cos) = angle.sin_cos();
+                        let offset = match circle {
+                            0 => vec3(0.0, cos, sin),
+                            1 => vec3(cos, 0.0, sin),
+                            _ => vec3(cos, sin, 0.0),
+                        };
+
+                        vertices.push(Vertex {
+                            position: center + offset * radius,
+                            color: color.0,
+                        });
+
+                        let next = (segment + 1) % SPHERE_SEGMENTS;
+                        indices.extend([base + segment, base + next]);
+                    }
+                }
+
+                (vertices, indices, ttl)
+            }
+            _ => return None,
+        };
+
+        let expires = ttl.and_then(|ttl| {
+            // treat unrepresentable TTLs as permanent
+            let ttl = Duration::try_from_secs_f32(ttl.max(0.0)).ok()?;
+            now.checked_add(ttl)
+        });
+
+        Some(Self {
+            vertices,
+            indices,
+            expires,
+        })
+    }
+}
+
 struct DebugDraw {
     mesh: DynamicMesh<Vertex>,
     hide: bool,
+
+    /// The topology of [Self::mesh].
+    topology: DebugDrawTopology,
+
+    /// The uniform buffer containing this draw's [TintUniform].
+    tint_buffer: Buffer,
+
+    /// The bind group for [Self::tint_buffer].
+    tint_bind_group: BindGroup,
+
+    /// The transient shapes in this draw.
+    shapes: Vec<Shape>,
+
+    /// A mesh containing all of [Self::shapes].
+    shapes_mesh: DynamicMesh<Vertex>,
+
+    /// Whether [Self::shapes_mesh] needs to be rebuilt.
+    shapes_dirty: bool,
+}
+
+impl DebugDraw {
+    fn new(device: &Device, tint_bgl: &BindGroupLayout, id: usize) -> Self {
+        let tint_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some(&format!("debug draw #{id} tint buffer")),
+            contents: bytemuck::bytes_of(&TintUniform::from(Color(0xffffffff))),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let tint_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(&format!("debug draw #{id} tint bind group")),
+            layout: tint_bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: tint_buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            mesh: DynamicMesh::new(device, Some(format!("debug draw #{id}"))),
+            hide: false,
+            topology: DebugDrawTopology::Lines,
+            tint_buffer,
+            tint_bind_group,
+            shapes: Vec::new(),
+            shapes_mesh: DynamicMesh::new(device, Some(format!("debug draw #{id} shapes"))),
+            shapes_dirty: false,
+        }
+    }
+
+    /// Removes expired shapes and uploads the shapes mesh if it has changed.
+    fn update_shapes(&mut self, device: &Device, queue: &Queue, now: Instant) {
+        let old_len = self.shapes.len();
+        self.shapes
+            .retain(|shape| shape.expires.map_or(true, |expires| expires > now));
+        self.shapes_dirty |= self.shapes.len() != old_len;
+
+        if !self.shapes_dirty {
+            return;
+        }
+
+        self.shapes_dirty = false;
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for shape in self.shapes.iter() {
+            let base = vertices.len() as u32;
+            vertices.extend_from_slice(&shape.vertices);
+            indices.extend(shape.indices.iter().map(|index| index + base));
+        }
+
+        self.shapes_mesh.update(device, queue, &vertices, &indices);
+    }
 }
 
 pub struct DebugDrawRoutine {
@@ -79,7 +275,9 @@ pub struct DebugDrawRoutine {
     queue: Arc<Queue>,
     camera_bind_group: BindGroup,
     camera_buffer: Buffer,
-    pipeline: RenderPipeline,
+    tint_bgl: BindGroupLayout,
+    line_pipeline: RenderPipeline,
+    triangle_pipeline: RenderPipeline,
     draws: HashMap<usize, DebugDraw>,
     update_rx: Receiver<(usize, DebugDrawUpdate)>,
 }
@@ -88,11 +286,17 @@ impl Routine for DebugDrawRoutine {
     fn build_node(&mut self) -> Box<dyn Node + '_> {
         // vec of updates received in order by each ID
         let updates = self.update_rx.drain().into_group_map();
+        let now = Instant::now();
 
         for (id, mut updates) in updates {
             // only write the latest property from the update queue
             let mut new_contents = None;
             let mut new_hide = None;
+            let mut new_tint = None;
+
+            // shapes added since the last clear, in reverse order
+            let mut new_shapes = Vec::new();
+            let mut clear_shapes = false;
 
             // whether a destroy message has been received
             let mut destroy = false;
@@ -107,10 +311,19 @@ impl Routine for DebugDrawRoutine {
                     Hide(hide) if new_hide.is_none() => {
                         new_hide = Some(hide);
                     }
+                    SetTint(tint) if new_tint.is_none() => {
+                        new_tint = Some(tint);
+                    }
                     Destroy => {
                         destroy = true;
                         break;
                     }
+                    ClearShapes => {
+                        clear_shapes = true;
+                    }
+                    AddLine { .. } | AddBox { .. } | AddSphere { .. } if !clear_shapes => {
+                        new_shapes.extend(Shape::from_update(update, now));
+                    }
                     _ => {}
                 }
             }
@@ -122,10 +335,10 @@ impl Routine for DebugDrawRoutine {
             }
 
             // retrieve the draw by ID or init it if it doesn't exist yet
-            let draw = self.draws.entry(id).or_insert_with(|| DebugDraw {
-                mesh: DynamicMesh::new(self.device.as_ref(), Some(format!("debug draw #{id}"))),
-                hide: false,
-            });
+            let draw = self
+                .draws
+                .entry(id)
+                .or_insert_with(|| DebugDraw::new(self.device.as_ref(), &self.tint_bgl, id));
 
             if let Some(mesh) = new_contents {
                 let vertices: Vec<_> = mesh
@@ -143,11 +356,37 @@ impl Routine for DebugDrawRoutine {
                     &vertices,
                     &mesh.indices,
                 );
+
+                draw.topology = mesh.topology;
             }
 
             if let Some(hide) = new_hide {
                 draw.hide = hide;
             }
+
+            if let Some(tint) = new_tint {
+                self.queue.write_buffer(
+                    &draw.tint_buffer,
+                    0,
+                    bytemuck::bytes_of(&TintUniform::from(tint)),
+                );
+            }
+
+            if clear_shapes {
+                draw.shapes.clear();
+                draw.shapes_dirty = true;
+            }
+
+            if !new_shapes.is_empty() {
+                new_shapes.reverse();
+                draw.shapes.extend(new_shapes);
+                draw.shapes_dirty = true;
+            }
+        }
+
+        // expire old shapes, including in draws that weren't updated
+        for draw in self.draws.values_mut() {
+            draw.update_shapes(self.device.as_ref(), self.queue.as_ref(), now);
         }
 
         Box::new(DebugDrawNode { routine: self })
@@ -178,49 +417,78 @@ impl DebugDrawRoutine {
                 }],
             });
 
+        let tint_bgl = rend3
+            .iad
+            .device
+            .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("debug draw tint bind group layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
         let layout = rend3
             .iad
             .device
             .create_pipeline_layout(&PipelineLayoutDescriptor {
                 label: Some("debug draw pipeline layout"),
-                bind_group_layouts: &[&bgl],
+                bind_group_layouts: &[&bgl, &tint_bgl],
                 push_constant_ranges: &[],
             });
 
-        let pipeline = rend3
-            .iad
-            .device
-            .create_render_pipeline(&RenderPipelineDescriptor {
-                label: Some("debug draw pipeline"),
-                layout: Some(&layout),
-                vertex: VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[Vertex::LAYOUT],
-                },
-                primitive: PrimitiveState {
-                    topology: PrimitiveTopology::LineList,
-                    ..Default::default()
-                },
-                depth_stencil: Some(DepthStencilState {
-                    format: TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: CompareFunction::GreaterEqual,
-                    stencil: Default::default(),
-                    bias: Default::default(),
-                }),
-                multisample: MultisampleState::default(),
-                fragment: Some(FragmentState {
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[ColorTargetState {
-                        format: rend3.surface_format,
-                        blend: None,
-                        write_mask: ColorWrites::COLOR,
-                    }],
-                }),
-                multiview: None,
-            });
+        let create_pipeline = |label, topology| {
+            rend3
+                .iad
+                .device
+                .create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&layout),
+                    vertex: VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[Vertex::LAYOUT],
+                    },
+                    primitive: PrimitiveState {
+                        topology,
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(DepthStencilState {
+                        format: TextureFormat::Depth32Float,
+                        depth_write_enabled: true,
+                        depth_compare: CompareFunction::GreaterEqual,
+                        stencil: Default::default(),
+                        bias: Default::default(),
+                    }),
+                    multisample: MultisampleState {
+                        count: rend3.sample_count as u32,
+                        ..Default::default()
+                    },
+                    fragment: Some(FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[ColorTargetState {
+                            format: rend3.surface_format,
+                            blend: Some(BlendState::ALPHA_BLENDING),
+                            write_mask: ColorWrites::COLOR,
+                        }],
+                    }),
+                    multiview: None,
+                })
+        };
+
+        let line_pipeline =
+            create_pipeline("debug draw line pipeline", PrimitiveTopology::LineList);
+        let triangle_pipeline = create_pipeline(
+            "debug draw triangle pipeline",
+            PrimitiveTopology::TriangleList,
+        );
 
         let camera_buffer = rend3.iad.device.create_buffer(&BufferDescriptor {
             label: Some("debug draw camera buffer"),
@@ -243,7 +511,9 @@ impl DebugDrawRoutine {
             queue: rend3.iad.queue.to_owned(),
             camera_buffer,
             camera_bind_group,
-            pipeline,
+            tint_bgl,
+            line_pipeline,
+            triangle_pipeline,
             draws: HashMap::new(),
             update_rx,
         }
@@ -256,7 +526,7 @@ struct DebugDrawNode<'a> {
 
 impl<'a> Node<'a> for DebugDrawNode<'a> {
     fn draw<'graph>(&'graph self, info: &mut RoutineInfo<'_, 'graph>) {
-        let output = info.graph.add_surface_texture();
+        let output = info.output;
         let depth = info.state.depth;
 
         let mut builder = info.graph.add_node("debug draw");
@@ -266,7 +536,7 @@ impl<'a> Node<'a> for DebugDrawNode<'a> {
         let rpass_handle = builder.add_renderpass(RenderPassTargets {
             targets: vec![RenderPassTarget {
                 color: output_handle,
-                clear: Color::BLACK,
+                clear: info.clear_color,
                 resolve: None,
             }],
             depth_stencil: Some(RenderPassDepthTarget {
@@ -290,7 +560,7 @@ impl<'a> Node<'a> for DebugDrawNode<'a> {
                     bytemuck::bytes_of(&CameraUniform { mvp }),
                 );
 
-                rpass.set_pipeline(&routine.pipeline);
+                rpass.set_pipeline(&routine.line_pipeline);
                 rpass.set_bind_group(0, &routine.camera_bind_group, &[]);
 
                 for draw in routine.draws.values() {
@@ -298,7 +568,25 @@ impl<'a> Node<'a> for DebugDrawNode<'a> {
                         continue;
                     }
 
-                    draw.mesh.draw(rpass);
+                    rpass.set_bind_group(1, &draw.tint_bind_group, &[]);
+
+                    if draw.topology == DebugDrawTopology::Lines {
+                        draw.mesh.draw(rpass);
+                    }
+
+                    // shapes are always wireframe
+                    if !draw.shapes.is_empty() {
+                        draw.shapes_mesh.draw(rpass);
+                    }
+                }
+
+                rpass.set_pipeline(&routine.triangle_pipeline);
+
+                for draw in routine.draws.values() {
+                    if !draw.hide && draw.topology == DebugDrawTopology::Triangles {
+                        rpass.set_bind_group(1, &draw.tint_bind_group, &[]);
+                        draw.mesh.draw(rpass);
+                    }
                 }
             },
         );
@@ -384,7 +672,8 @@ impl Plugin for DebugDrawPlugin {
 
         let (update_tx, update_rx) = unbounded();
 
-        rend3.add_routine(DebugDrawRoutine::new(rend3, update_rx));
+        // draw debug geometry beneath canvases and terminals
+        rend3.add_routine_ordered(100, DebugDrawRoutine::new(rend3, update_rx));
 
         builder.add_plugin(DebugDrawFactory {
             next_id: 0,
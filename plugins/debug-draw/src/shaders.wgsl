@@ -30,7 +30,12 @@ struct CameraUniform {
     mvp: mat4x4<f32>;
 };
 
+struct TintUniform {
+    tint: vec4<f32>;
+};
+
 [[group(0), binding(0)]] var<uniform> camera: CameraUniform;
+[[group(1), binding(0)]] var<uniform> tint: TintUniform;
 
 fn srgb_to_linear(l: vec3<f32>) -> vec3<f32> {
     let cutoff = l > vec3<f32>(0.0405);
@@ -43,7 +48,8 @@ fn srgb_to_linear(l: vec3<f32>) -> vec3<f32> {
 fn vs_main(in: VertexIn) -> VertexOut {
     var out: VertexOut;
     out.clip_position = camera.mvp * vec4<f32>(in.position, 1.0);
-    out.color = vec4<f32>(srgb_to_linear(in.color.bgr), 1.0);
+    let color = srgb_to_linear(in.color.bgr) * tint.tint.rgb;
+    out.color = vec4<f32>(color, tint.tint.a);
     return out;
 }
 
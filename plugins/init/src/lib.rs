@@ -93,6 +93,9 @@ impl Plugin for InitPlugin {
                 let spawn_info = WasmSpawnInfo {
                     lump: wasm_lump,
                     entrypoint: None,
+                    ready_timeout: None,
+                    fuel_limit: None,
+                    priority: 0,
                 };
 
                 debug!("Running init system");
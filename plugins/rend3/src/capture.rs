@@ -0,0 +1,135 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Readback of rendered frames into CPU memory.
+
+use std::sync::Arc;
+
+use glam::UVec2;
+use wgpu::*;
+
+/// The texture format that frames are captured in.
+pub const CAPTURE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// A frame captured by [crate::Rend3Command::Capture].
+#[derive(Clone, Debug)]
+pub struct CapturedFrame {
+    /// The dimensions of the frame.
+    pub size: UVec2,
+
+    /// The frame's pixels as tightly-packed, row-major RGBA in sRGB.
+    pub data: Vec<u8>,
+}
+
+/// An offscreen texture that a frame is rendered into to be read back.
+pub struct CaptureTarget {
+    size: UVec2,
+    texture: Texture,
+    buffer: Buffer,
+    padded_bytes_per_row: u32,
+}
+
+impl CaptureTarget {
+    /// Creates a new capture target of the given size.
+    pub fn new(device: &Device, size: UVec2) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("capture texture"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: CAPTURE_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        });
+
+        // buffer copies need each row to be aligned
+        let unpadded_bytes_per_row = size.x * 4;
+        let align = COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("capture buffer"),
+            size: (padded_bytes_per_row * size.y) as BufferAddress,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            size,
+            texture,
+            buffer,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Creates a view of this target's texture to render into.
+    pub fn create_view(&self) -> TextureView {
+        self.texture.create_view(&TextureViewDescriptor::default())
+    }
+
+    /// Encodes a copy of this target's texture into its readback buffer.
+    ///
+    /// Must be encoded after the frame has been rendered into the texture.
+    pub fn encode_copy(&self, encoder: &mut CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: self.size.x,
+                height: self.size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Waits for the copy to finish and reads the captured frame.
+    ///
+    /// Must only be called after the commands encoded by [Self::encode_copy]
+    /// have been submitted. The render loop waits on this before submitting
+    /// another frame, so the device is polled until the copy finishes on a
+    /// blocking thread instead of a runtime worker.
+    pub async fn read(self, device: Arc<Device>) -> Result<CapturedFrame, BufferAsyncError> {
+        let slice = self.buffer.slice(..);
+        let mapping = slice.map_async(MapMode::Read);
+        let _ = tokio::task::spawn_blocking(move || device.poll(Maintain::Wait)).await;
+        mapping.await?;
+
+        let row_len = self.size.x as usize * 4;
+        let mut data = Vec::with_capacity(row_len * self.size.y as usize);
+        let mapped = slice.get_mapped_range();
+        for row in mapped.chunks(self.padded_bytes_per_row as usize) {
+            data.extend_from_slice(&row[..row_len]);
+        }
+
+        Ok(CapturedFrame {
+            size: self.size,
+            data,
+        })
+    }
+}
@@ -18,23 +18,31 @@
 
 use std::sync::Arc;
 
-use glam::{UVec2, Vec4};
+use capture::{CaptureTarget, CapturedFrame};
+use glam::{UVec2, Vec3, Vec4};
+use hearth_runtime::hearth_schema::renderer::ToneMapOperator;
 use hearth_runtime::runtime::{Plugin, RuntimeBuilder};
-use rend3::graph::{ReadyData, RenderGraph};
+use hearth_runtime::tracing::warn;
+use rend3::graph::{
+    ReadyData, RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetDescriptor,
+    RenderTargetHandle,
+};
 use rend3::types::{Camera, SampleCount, TextureHandle};
 use rend3::util::output::OutputFrame;
 use rend3::{InstanceAdapterDevice, Renderer};
 use rend3_routine::base::{BaseRenderGraph, BaseRenderGraphIntermediateState};
 use rend3_routine::pbr::PbrRoutine;
 use rend3_routine::skybox::SkyboxRoutine;
-use rend3_routine::tonemapping::TonemappingRoutine;
 use tokio::sync::{mpsc, oneshot};
-use wgpu::TextureFormat;
+use tonemapping::ToneMappingRoutine;
+use wgpu::{Color, TextureFormat, TextureUsages};
 
 pub use rend3;
 pub use rend3_routine;
 pub use wgpu;
 
+pub mod capture;
+pub mod tonemapping;
 pub mod utils;
 
 /// The info about a frame passed to [Routine::draw].
@@ -44,10 +52,34 @@ pub struct RoutineInfo<'a, 'graph> {
     pub resolution: UVec2,
     pub ready_data: &'a ReadyData,
     pub graph: &'a mut RenderGraph<'graph>,
+
+    /// The color that the frame is cleared to before anything is drawn.
+    ///
+    /// The frame is cleared once before the scene is rendered, so nodes
+    /// should pass this as the clear color of their render pass targets
+    /// instead of clearing to a color of their own.
+    pub clear_color: Color,
+
+    /// The world-space position of the camera for this frame.
+    pub camera_position: Vec3,
+
+    /// The render target that nodes draw their output into.
+    ///
+    /// This target is in the surface's format and has `sample_count` samples,
+    /// matching the scene's depth target. It's resolved onto the surface after
+    /// all nodes have been drawn.
+    pub output: RenderTargetHandle,
 }
 
 pub trait Routine: Send + Sync + 'static {
     fn build_node(&mut self) -> Box<dyn Node<'_> + '_>;
+
+    /// The name of this routine, used to look it up in a [Rend3Plugin].
+    ///
+    /// Defaults to the routine's type name.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
 }
 
 pub trait Node<'a> {
@@ -62,8 +94,9 @@ pub struct FrameRequest {
     /// The dimensions of the frame.
     pub resolution: glam::UVec2,
 
-    /// The camera to use for this frame.
-    pub camera: Camera,
+    /// The camera to use for this frame, overriding the camera set by
+    /// [Rend3Command::SetCamera] if present.
+    pub camera: Option<Camera>,
 
     /// This oneshot message is sent when the frame is done rendering.
     pub on_complete: oneshot::Sender<()>,
@@ -76,6 +109,29 @@ pub enum Rend3Command {
 
     /// Updates the ambient lighting.
     SetAmbient(Vec4),
+
+    /// Updates the color that frames are cleared to.
+    ///
+    /// The clear color's alpha is only transparent on surfaces whose format
+    /// has an alpha channel. On other surfaces it is forced to be opaque.
+    SetClearColor(Color),
+
+    /// Updates the tone mapping operator and exposure.
+    SetToneMapping {
+        operator: ToneMapOperator,
+        exposure: f32,
+    },
+
+    /// Updates the camera used by frames that don't supply their own.
+    SetCamera(Camera),
+
+    /// Captures the next frame that is drawn.
+    ///
+    /// `on_complete` is sent the frame once it has been read back from the
+    /// GPU. It is dropped without a response if the readback fails.
+    Capture {
+        on_complete: oneshot::Sender<CapturedFrame>,
+    },
 }
 
 /// A rend3 Hearth plugin for adding 3D rendering to a Hearth runtime.
@@ -88,15 +144,20 @@ pub struct Rend3Plugin {
     pub renderer: Arc<Renderer>,
     pub base_render_graph: BaseRenderGraph,
     pub pbr_routine: PbrRoutine,
-    pub tonemapping_routine: TonemappingRoutine,
+    pub tonemapping_routine: ToneMappingRoutine,
     pub skybox_routine: SkyboxRoutine,
     pub ambient: Vec4,
+    pub clear_color: Color,
+    pub camera: Camera,
+    pub sample_count: SampleCount,
     pub frame_request_tx: mpsc::UnboundedSender<FrameRequest>,
     pub command_tx: mpsc::UnboundedSender<Rend3Command>,
     new_skybox: Option<TextureHandle>,
+    pending_captures: Vec<oneshot::Sender<CapturedFrame>>,
+    capture: Option<(CaptureTarget, Vec<oneshot::Sender<CapturedFrame>>)>,
     frame_request_rx: mpsc::UnboundedReceiver<FrameRequest>,
     command_rx: mpsc::UnboundedReceiver<Rend3Command>,
-    routines: Vec<Box<dyn Routine>>,
+    routines: Vec<(i32, Box<dyn Routine>)>,
 }
 
 impl Plugin for Rend3Plugin {
@@ -105,6 +166,7 @@ impl Plugin for Rend3Plugin {
             while let Some(frame) = self.frame_request_rx.recv().await {
                 self.flush_commands();
                 self.draw(frame);
+                self.finish_capture().await;
             }
         });
     }
@@ -120,10 +182,12 @@ impl Rend3Plugin {
         let mut data_core = renderer.data_core.lock();
         let interfaces = &base_render_graph.interfaces;
         let pbr_routine = PbrRoutine::new(&renderer, &mut data_core, interfaces);
-        let tonemapping_routine = TonemappingRoutine::new(&renderer, interfaces, surface_format);
         let skybox_routine = SkyboxRoutine::new(&renderer, interfaces);
         drop(data_core);
 
+        let tonemapping_routine =
+            ToneMappingRoutine::new(&iad.device, iad.queue.to_owned(), surface_format);
+
         let (frame_request_tx, frame_request_rx) = mpsc::unbounded_channel();
         let (command_tx, command_rx) = mpsc::unbounded_channel();
 
@@ -140,14 +204,62 @@ impl Rend3Plugin {
             command_tx,
             command_rx,
             new_skybox: None,
+            pending_captures: Vec::new(),
+            capture: None,
             ambient: Vec4::ZERO,
+            clear_color: Color::BLACK,
+            camera: Camera::default(),
+            sample_count: SampleCount::One,
             routines: Vec::new(),
         }
     }
 
-    /// Adds a new [Routine] to this plugin.
+    /// Sets the number of samples used to anti-alias each frame.
+    ///
+    /// Routines create their pipelines for the sample count that is set when
+    /// they are added, so this must be called before any routines are added.
+    pub fn set_sample_count(&mut self, sample_count: SampleCount) {
+        if !self.routines.is_empty() {
+            warn!("sample count set after routines were added");
+        }
+
+        self.sample_count = sample_count;
+        self.tonemapping_routine
+            .set_sample_count(&self.iad.device, sample_count as u32);
+    }
+
+    /// Adds a new [Routine] to this plugin with a priority of 0.
     pub fn add_routine(&mut self, routine: impl Routine) {
-        self.routines.push(Box::new(routine));
+        self.add_routine_ordered(0, routine);
+    }
+
+    /// Adds a new [Routine] to this plugin with the given priority.
+    ///
+    /// The nodes of routines with lower priorities are drawn first. Routines
+    /// with the same priority are drawn in the order they were added.
+    pub fn add_routine_ordered(&mut self, priority: i32, routine: impl Routine) {
+        let index = self
+            .routines
+            .partition_point(|(other, _)| *other <= priority);
+
+        self.routines.insert(index, (priority, Box::new(routine)));
+    }
+
+    /// Returns true if a [Routine] with the given name has been added.
+    pub fn has_routine(&self, name: &str) -> bool {
+        self.routines
+            .iter()
+            .any(|(_, routine)| routine.name() == name)
+    }
+
+    /// Removes the first [Routine] with the given name, if any.
+    pub fn remove_routine(&mut self, name: &str) -> Option<Box<dyn Routine>> {
+        let index = self
+            .routines
+            .iter()
+            .position(|(_, routine)| routine.name() == name)?;
+
+        Some(self.routines.remove(index).1)
     }
 
     /// Flushes and applies all [Rend3Command] messages.
@@ -161,14 +273,40 @@ impl Rend3Plugin {
                 SetAmbient(ambient) => {
                     self.ambient = ambient;
                 }
+                SetClearColor(mut color) => {
+                    if color.a < 1.0 && self.surface_format.describe().components < 4 {
+                        warn!("surface format has no alpha channel; clear color will be opaque");
+                        color.a = 1.0;
+                    }
+
+                    self.clear_color = color;
+                }
+                SetToneMapping { operator, exposure } => {
+                    self.tonemapping_routine.set(operator, exposure);
+                }
+                SetCamera(camera) => {
+                    self.camera = camera;
+                }
+                Capture { on_complete } => {
+                    self.pending_captures.push(on_complete);
+                }
             }
         }
     }
 
     /// Draws a frame in response to a [FrameRequest].
+    ///
+    /// If any captures are pending, the frame is also rendered into a capture
+    /// target to be read back by [Self::finish_capture].
     pub fn draw(&mut self, request: FrameRequest) {
         let (cmd_bufs, ready) = self.renderer.ready();
 
+        let capture = if self.pending_captures.is_empty() {
+            None
+        } else {
+            Some(CaptureTarget::new(&self.iad.device, request.resolution))
+        };
+
         if let Some(skybox) = self.new_skybox.take() {
             self.skybox_routine.set_background_texture(Some(skybox));
             self.skybox_routine.ready(&self.renderer);
@@ -177,17 +315,19 @@ impl Rend3Plugin {
         let aspect = request.resolution.as_vec2();
         let aspect = aspect.x / aspect.y;
         self.renderer.set_aspect_ratio(aspect);
-        self.renderer.set_camera_data(request.camera);
+        let camera = request.camera.unwrap_or(self.camera);
+        let camera_position = camera.view.inverse().w_axis.truncate();
+        self.renderer.set_camera_data(camera);
 
         let nodes: Vec<_> = self
             .routines
             .iter_mut()
-            .map(|routine| routine.build_node())
+            .map(|(_, routine)| routine.build_node())
             .collect();
 
         let mut graph_data = RenderGraph::new();
         let graph = &mut graph_data;
-        let samples = SampleCount::One;
+        let samples = self.sample_count;
         let base = &self.base_render_graph;
         let ambient = self.ambient;
         let pbr = &self.pbr_routine;
@@ -217,6 +357,9 @@ impl Rend3Plugin {
         state.pbr_shadow_rendering(graph, pbr);
         state.pbr_prepass_rendering(graph, pbr, samples);
 
+        // Clear the scene's color target before anything else writes to it
+        self.add_clear_node(graph, state.color);
+
         // Skybox
         state.skybox(graph, skybox, samples);
 
@@ -225,22 +368,123 @@ impl Rend3Plugin {
 
         // Make the reference to the surface
         let surface = graph.add_surface_texture();
-        state.tonemapping(graph, &self.tonemapping_routine, surface);
+
+        // Multisampled nodes can't draw onto the surface directly
+        let multisampled = !matches!(samples, SampleCount::One);
+        let output = if !multisampled {
+            surface
+        } else {
+            graph.add_render_target(RenderTargetDescriptor {
+                label: Some("multisampled output".into()),
+                resolution: request.resolution,
+                samples,
+                format: self.surface_format,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+            })
+        };
+
+        let hdr_color = state.resolve.unwrap_or(state.color);
+        self.tonemapping_routine
+            .add_to_graph(graph, hdr_color, output, self.clear_color);
+
+        if let Some(capture) = capture.as_ref() {
+            self.tonemapping_routine.add_capture_to_graph(
+                graph,
+                hdr_color,
+                capture,
+                self.clear_color,
+            );
+        }
 
         let mut info = RoutineInfo {
             state: &state,
-            sample_count: SampleCount::One,
+            sample_count: samples,
             resolution: request.resolution,
             ready_data: &ready,
             graph,
+            clear_color: self.clear_color,
+            camera_position,
+            output,
         };
 
         for node in nodes.iter() {
             node.draw(&mut info);
         }
 
+        if multisampled {
+            self.add_resolve_node(info.graph, output, surface);
+        }
+
         graph_data.execute(&self.renderer, request.output_frame, cmd_bufs, &ready);
 
+        if let Some(capture) = capture {
+            let senders = std::mem::take(&mut self.pending_captures);
+            self.capture = Some((capture, senders));
+        }
+
         let _ = request.on_complete.send(()); // ignore hangup
     }
+
+    /// Reads back the frame captured by the last call to [Self::draw], if
+    /// any, and sends it to all of the pending capture requests.
+    pub async fn finish_capture(&mut self) {
+        let Some((capture, senders)) = self.capture.take() else {
+            return;
+        };
+
+        let frame = match capture.read(self.iad.device.clone()).await {
+            Ok(frame) => frame,
+            Err(err) => {
+                warn!("failed to read back captured frame: {err:?}");
+                return;
+            }
+        };
+
+        for sender in senders {
+            let _ = sender.send(frame.clone()); // ignore hangup
+        }
+    }
+
+    /// Adds a node that resolves a multisampled render target onto another.
+    fn add_resolve_node(
+        &self,
+        graph: &mut RenderGraph,
+        src: RenderTargetHandle,
+        dst: RenderTargetHandle,
+    ) {
+        let mut builder = graph.add_node("resolve");
+        let src_handle = builder.add_render_target_output(src);
+        let dst_handle = builder.add_render_target_output(dst);
+
+        let _ = builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: src_handle,
+                clear: self.clear_color,
+                resolve: Some(dst_handle),
+            }],
+            depth_stencil: None,
+        });
+
+        builder.build(|_pt, _renderer, _encoder_or_pass, _temps, _ready, _graph_data| {});
+    }
+
+    /// Adds a node that clears a render target to the clear color.
+    ///
+    /// rend3 only clears a render target in the first pass that uses it in a
+    /// frame, so this must be added before any other nodes write to it.
+    fn add_clear_node(&self, graph: &mut RenderGraph, target: RenderTargetHandle) {
+        let mut builder = graph.add_node("clear");
+        let target_handle = builder.add_render_target_output(target);
+
+        let _ = builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: target_handle,
+                clear: self.clear_color,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        builder.build(|_pt, _renderer, _encoder_or_pass, _temps, _ready, _graph_data| {});
+    }
 }
@@ -0,0 +1,289 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tone mapping of the HDR scene onto the output surface.
+//!
+//! rend3 0.3's own tonemapping routine is a plain blit that has no notion of
+//! exposure or tone mapping operators, so this module replaces it with a
+//! pass that supports both.
+
+use std::sync::Arc;
+
+use crate::capture::{CaptureTarget, CAPTURE_FORMAT};
+use bytemuck::{Pod, Zeroable};
+use hearth_runtime::hearth_schema::renderer::ToneMapOperator;
+use rend3::graph::{RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetHandle};
+use wgpu::*;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct ToneMappingUniform {
+    exposure: f32,
+    operator: u32,
+    _padding: [u32; 2],
+}
+
+/// A render routine that maps the HDR scene color onto the output surface.
+pub struct ToneMappingRoutine {
+    queue: Arc<Queue>,
+    shader: ShaderModule,
+    bgl: BindGroupLayout,
+    layout: PipelineLayout,
+    surface_format: TextureFormat,
+    pipeline: RenderPipeline,
+    capture_pipeline: RenderPipeline,
+    uniform_buffer: Buffer,
+}
+
+impl ToneMappingRoutine {
+    /// Creates a new tone mapping routine rendering to the given format.
+    ///
+    /// The routine starts with no operator and an exposure of 1.0, which
+    /// matches the output of rend3's default tonemapping routine.
+    pub fn new(device: &Device, queue: Arc<Queue>, surface_format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(&include_wgsl!("tonemapping.wgsl"));
+
+        let bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("tone mapping bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("tone mapping pipeline layout"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = create_pipeline(device, &shader, &layout, surface_format, 1);
+        let capture_pipeline = create_pipeline(device, &shader, &layout, CAPTURE_FORMAT, 1);
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("tone mapping uniform buffer"),
+            size: std::mem::size_of::<ToneMappingUniform>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let routine = Self {
+            queue,
+            shader,
+            bgl,
+            layout,
+            surface_format,
+            pipeline,
+            capture_pipeline,
+            uniform_buffer,
+        };
+
+        routine.set(ToneMapOperator::None, 1.0);
+        routine
+    }
+
+    /// Recreates the output pipeline to render to targets with the given
+    /// number of samples.
+    pub fn set_sample_count(&mut self, device: &Device, samples: u32) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.shader,
+            &self.layout,
+            self.surface_format,
+            samples,
+        );
+    }
+
+    /// Updates the tone mapping operator and exposure.
+    pub fn set(&self, operator: ToneMapOperator, exposure: f32) {
+        let uniform = ToneMappingUniform {
+            exposure,
+            // must match the operator IDs in tonemapping.wgsl
+            operator: match operator {
+                ToneMapOperator::None => 0,
+                ToneMapOperator::Reinhard => 1,
+                ToneMapOperator::Aces => 2,
+            },
+            _padding: [0; 2],
+        };
+
+        self.queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Adds a node to the graph that tone maps `src` into `dst`.
+    ///
+    /// `dst` must have the sample count last passed to
+    /// [Self::set_sample_count], or one sample if it was never called.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        src: RenderTargetHandle,
+        dst: RenderTargetHandle,
+        clear: Color,
+    ) {
+        let mut builder = graph.add_node("tone mapping");
+        let input_handle = builder.add_render_target_input(src);
+        let output_handle = builder.add_render_target_output(dst);
+
+        let rpass_handle = builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: output_handle,
+                clear,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        let routine = builder.passthrough_ref(self);
+
+        builder.build(
+            move |pt, renderer, encoder_or_pass, temps, _ready, graph_data| {
+                let routine = pt.get(routine);
+                let rpass = encoder_or_pass.get_rpass(rpass_handle);
+                let hdr_color = graph_data.get_render_target(input_handle);
+                let bind_group = temps.add(routine.create_bind_group(&renderer.device, hdr_color));
+
+                rpass.set_pipeline(&routine.pipeline);
+                rpass.set_bind_group(0, bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+            },
+        );
+    }
+
+    /// Adds a node to the graph that tone maps `src` into a capture target
+    /// and copies the result into the target's readback buffer.
+    pub fn add_capture_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        src: RenderTargetHandle,
+        capture: &'node CaptureTarget,
+        clear: Color,
+    ) {
+        let mut builder = graph.add_node("capture");
+        let input_handle = builder.add_render_target_input(src);
+
+        // the capture target lives outside of the graph
+        builder.add_external_output();
+
+        let routine = builder.passthrough_ref(self);
+        let capture = builder.passthrough_ref(capture);
+
+        builder.build(
+            move |pt, renderer, encoder_or_pass, _temps, _ready, graph_data| {
+                let routine = pt.get(routine);
+                let capture = pt.get(capture);
+                let encoder = encoder_or_pass.get_encoder();
+                let hdr_color = graph_data.get_render_target(input_handle);
+                let bind_group = routine.create_bind_group(&renderer.device, hdr_color);
+                let view = capture.create_view();
+
+                let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("capture pass"),
+                    color_attachments: &[RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(clear),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+
+                rpass.set_pipeline(&routine.capture_pipeline);
+                rpass.set_bind_group(0, &bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+                drop(rpass);
+
+                capture.encode_copy(encoder);
+            },
+        );
+    }
+
+    /// Creates a bind group for tone mapping the given HDR color target.
+    fn create_bind_group(&self, device: &Device, hdr_color: &TextureView) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("tone mapping bind group"),
+            layout: &self.bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(hdr_color),
+                },
+            ],
+        })
+    }
+}
+
+/// Creates a tone mapping pipeline that renders to the given format.
+fn create_pipeline(
+    device: &Device,
+    shader: &ShaderModule,
+    layout: &PipelineLayout,
+    format: TextureFormat,
+    samples: u32,
+) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("tone mapping pipeline"),
+        layout: Some(layout),
+        vertex: VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState {
+            count: samples,
+            ..Default::default()
+        },
+        fragment: Some(FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[ColorTargetState {
+                format,
+                blend: None,
+                write_mask: ColorWrites::all(),
+            }],
+        }),
+        multiview: None,
+    })
+}
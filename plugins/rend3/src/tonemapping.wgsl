@@ -0,0 +1,64 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+struct ToneMappingUniform {
+    exposure: f32;
+    operator: u32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: ToneMappingUniform;
+[[group(0), binding(1)]] var hdr_t: texture_2d<f32>;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> [[builtin(position)]] vec4<f32> {
+    // a single triangle covering the whole screen
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index & 2u) * 2 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+fn reinhard(c: vec3<f32>) -> vec3<f32> {
+    return c / (c + vec3<f32>(1.0));
+}
+
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn aces(c: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let d = 2.43;
+    let e = 0.59;
+    let f = 0.14;
+    let mapped = (c * (a * c + b)) / (c * (d * c + e) + f);
+    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let hdr = textureLoad(hdr_t, vec2<i32>(position.xy), 0);
+    var color = hdr.rgb * params.exposure;
+
+    if (params.operator == 1u) {
+        color = reinhard(color);
+    } else {
+        if (params.operator == 2u) {
+            color = aces(color);
+        }
+    }
+
+    return vec4<f32>(color, hdr.a);
+}
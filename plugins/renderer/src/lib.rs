@@ -16,24 +16,38 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
+use std::io::Cursor;
 use std::sync::Arc;
 
 use hearth_rend3::{
     rend3::{types::*, *},
-    rend3_routine::pbr::{AlbedoComponent, PbrMaterial},
+    rend3_routine::pbr::{
+        AlbedoComponent, MaterialComponent, NormalTexture, NormalTextureYDirection, PbrMaterial,
+        SampleType, Transparency,
+    },
+    wgpu::Color,
     Rend3Command, Rend3Plugin,
 };
 use hearth_runtime::{
-    anyhow::{self, bail},
-    asset::{AssetLoader, AssetStore, JsonAssetLoader},
+    anyhow::{self, anyhow, bail, Context},
+    asset::{AssetError, AssetLoader, AssetStore, JsonAssetLoader},
     async_trait,
     hearth_macros::GetProcessMetadata,
-    hearth_schema::{renderer::*, LumpId},
+    hearth_schema::{
+        renderer::{self, *},
+        ByteVec, LumpId,
+    },
+    lump::LumpStoreImpl,
     runtime::{Plugin, RuntimeBuilder},
-    tokio::sync::mpsc::UnboundedSender,
+    tokio::sync::{mpsc::UnboundedSender, oneshot},
     tracing::{error, warn},
     utils::*,
 };
+use image::{ImageOutputFormat, RgbaImage};
+
+use lod::{LodObjectInstance, LodObjects, LodRoutine};
+
+pub mod lod;
 
 pub struct MeshLoader(Arc<Renderer>);
 
@@ -81,8 +95,36 @@ impl JsonAssetLoader for MaterialLoader {
     ) -> anyhow::Result<Self::Asset> {
         let albedo = store.load_asset::<TextureLoader>(&data.albedo).await?;
 
+        let normal = match data.normal_map {
+            Some(lump) => {
+                let texture = store.load_asset::<TextureLoader>(&lump).await?;
+                NormalTexture::Tricomponent(texture.handle.to_owned(), NormalTextureYDirection::Up)
+            }
+            None => NormalTexture::None,
+        };
+
+        let emissive = match data.emissive {
+            Some(color) => MaterialComponent::Value(color),
+            None => MaterialComponent::None,
+        };
+
+        let transparency = match data.alpha_mode {
+            AlphaMode::Opaque => Transparency::Opaque,
+            AlphaMode::Mask { cutoff } => Transparency::Cutout { cutout: cutoff },
+            AlphaMode::Blend => Transparency::Blend,
+        };
+
         let material = PbrMaterial {
-            albedo: AlbedoComponent::Texture(albedo.as_ref().to_owned()),
+            albedo: AlbedoComponent::Texture(albedo.handle.to_owned()),
+            metallic_factor: data.metallic,
+            roughness_factor: data.roughness,
+            normal,
+            emissive,
+            transparency,
+            sample_type: match albedo.sampling {
+                TextureSampling::Linear => SampleType::Linear,
+                TextureSampling::Nearest => SampleType::Nearest,
+            },
             ..Default::default()
         };
 
@@ -91,11 +133,17 @@ impl JsonAssetLoader for MaterialLoader {
     }
 }
 
+/// A loaded 2D texture and how it should be sampled.
+pub struct LoadedTexture {
+    pub handle: TextureHandle,
+    pub sampling: TextureSampling,
+}
+
 pub struct TextureLoader(Arc<Renderer>);
 
 #[async_trait]
 impl JsonAssetLoader for TextureLoader {
-    type Asset = TextureHandle;
+    type Asset = LoadedTexture;
     type Data = TextureData;
 
     async fn load_asset(
@@ -103,23 +151,35 @@ impl JsonAssetLoader for TextureLoader {
         _store: &AssetStore,
         data: Self::Data,
     ) -> anyhow::Result<Self::Asset> {
-        let expected_len = (data.size.x * data.size.y * 4) as usize;
+        let expected_len = data.format.data_len(data.size);
+        let bytes = ByteVec::<u8>::from_bytes_checked(data.data, expected_len)
+            .context("invalid texture data")?;
 
-        if data.data.len() != expected_len {
-            bail!("invalid texture data length");
+        if data.generate_mips && data.format.block_dimensions() != 1 {
+            bail!("mipmaps cannot be generated for block-compressed textures");
         }
 
+        let (mip_count, mip_source) = if data.generate_mips {
+            (MipmapCount::Maximum, MipmapSource::Generated)
+        } else {
+            (MipmapCount::ONE, MipmapSource::Uploaded)
+        };
+
         let texture = Texture {
             label: data.label,
-            data: data.data,
-            format: TextureFormat::Rgba8UnormSrgb,
+            data: bytes.0,
+            format: convert_texture_format(data.format),
             size: data.size,
-            mip_count: MipmapCount::ONE,
-            mip_source: MipmapSource::Uploaded,
+            mip_count,
+            mip_source,
         };
 
         let handle = self.0.add_texture_2d(texture);
-        Ok(handle)
+
+        Ok(LoadedTexture {
+            handle,
+            sampling: data.sampling,
+        })
     }
 }
 
@@ -135,18 +195,27 @@ impl JsonAssetLoader for CubeTextureLoader {
         _store: &AssetStore,
         data: Self::Data,
     ) -> anyhow::Result<Self::Asset> {
-        let expected_len = (data.size.x * data.size.y * 24) as usize;
-
-        if data.data.len() != expected_len {
-            bail!("invalid texture data length");
+        // mipmaps can't be generated for block-compressed textures
+        if data.format.block_dimensions() != 1 {
+            bail!("cube textures cannot be block-compressed");
         }
 
+        let expected_len = data.format.data_len(data.size) * 6;
+        let bytes = ByteVec::<u8>::from_bytes_checked(data.data, expected_len)
+            .context("invalid cube texture data")?;
+
+        let mip_count = if data.generate_mips {
+            MipmapCount::Maximum
+        } else {
+            MipmapCount::ONE
+        };
+
         let texture = Texture {
             label: data.label,
-            data: data.data,
-            format: TextureFormat::Rgba8UnormSrgb,
+            data: bytes.0,
+            format: convert_texture_format(data.format),
             size: data.size,
-            mip_count: MipmapCount::ONE,
+            mip_count,
             mip_source: MipmapSource::Generated,
         };
 
@@ -157,6 +226,9 @@ impl JsonAssetLoader for CubeTextureLoader {
 }
 
 /// An instance of a renderer directional light. Accepts DirectionalLightUpdate.
+///
+/// rend3 removes a resource when its last handle is dropped, so the light is
+/// removed from the scene when this process is killed.
 #[derive(GetProcessMetadata)]
 pub struct DirectionalLightInstance {
     renderer: Arc<Renderer>,
@@ -182,11 +254,46 @@ impl SinkProcess for DirectionalLightInstance {
     }
 }
 
+/// An instance of a renderer point light. Accepts PointLightUpdate.
+#[derive(GetProcessMetadata)]
+pub struct PointLightInstance {
+    renderer: Arc<Renderer>,
+    handle: ResourceHandle<PointLight>,
+}
+
+#[async_trait]
+impl SinkProcess for PointLightInstance {
+    type Message = PointLightUpdate;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        let mut change = PointLightChange::default();
+
+        use PointLightUpdate::*;
+        match message.data {
+            Position(position) => change.position = Some(position),
+            Color(color) => change.color = Some(color),
+            Intensity(intensity) => change.intensity = Some(intensity),
+            Range(range) => change.radius = Some(range),
+        }
+
+        self.renderer.update_point_light(&self.handle, change);
+    }
+}
+
 /// An instance of a renderer object. Accepts ObjectUpdate.
+///
+/// rend3 removes a resource when its last handle is dropped, so the object is
+/// removed from the scene when this process is killed.
 #[derive(GetProcessMetadata)]
 pub struct ObjectInstance {
     renderer: Arc<Renderer>,
-    handle: ObjectHandle,
+
+    /// The object's description, kept to re-add the object when it's shown.
+    object: Object,
+
+    /// The handle to the object in the scene, or `None` if it's hidden.
+    handle: Option<ObjectHandle>,
+
     skeleton: Option<SkeletonHandle>,
 }
 
@@ -198,8 +305,19 @@ impl SinkProcess for ObjectInstance {
         use ObjectUpdate::*;
         match &message.data {
             Transform(transform) => {
-                self.renderer.set_object_transform(&self.handle, *transform);
+                self.object.transform = *transform;
+
+                if let Some(handle) = self.handle.as_ref() {
+                    self.renderer.set_object_transform(handle, *transform);
+                }
             }
+            SetVisible(visible) => match (*visible, self.handle.is_some()) {
+                (true, false) => {
+                    self.handle = Some(self.renderer.add_object(self.object.clone()));
+                }
+                (false, true) => self.handle = None,
+                _ => {}
+            },
             JointMatrices(matrices) => {
                 let Some(skeleton) = self.skeleton.as_ref() else {
                     warn!("tried to update joint matrices on static object");
@@ -230,6 +348,7 @@ impl SinkProcess for ObjectInstance {
 pub struct RendererService {
     renderer: Arc<Renderer>,
     command_tx: UnboundedSender<Rend3Command>,
+    lod_objects: LodObjects,
 }
 
 #[async_trait]
@@ -263,6 +382,26 @@ impl RequestResponseProcess for RendererService {
                     caps: vec![child],
                 };
             }
+            AddPointLight { initial_state } => {
+                let light = PointLight {
+                    position: initial_state.position,
+                    color: initial_state.color,
+                    intensity: initial_state.intensity,
+                    radius: initial_state.range,
+                };
+
+                let handle = self.renderer.add_point_light(light);
+
+                let child = request.spawn(PointLightInstance {
+                    renderer: self.renderer.clone(),
+                    handle,
+                });
+
+                return ResponseInfo {
+                    data: Ok(RendererSuccess::Ok),
+                    caps: vec![child],
+                };
+            }
             AddObject {
                 mesh,
                 skeleton,
@@ -297,11 +436,12 @@ impl RequestResponseProcess for RendererService {
                     transform: *transform,
                 };
 
-                let handle = self.renderer.add_object(object);
+                let handle = self.renderer.add_object(object.clone());
 
                 let child = request.spawn(ObjectInstance {
                     renderer: self.renderer.clone(),
-                    handle,
+                    object,
+                    handle: Some(handle),
                     skeleton,
                 });
 
@@ -310,6 +450,51 @@ impl RequestResponseProcess for RendererService {
                     caps: vec![child],
                 };
             }
+            AddLodObject {
+                lods,
+                material,
+                transform,
+            } => {
+                let valid = |distance: &f32| distance.is_finite() && *distance >= 0.0;
+                if lods.is_empty() || !lods.iter().all(|(_, distance)| valid(distance)) {
+                    return ResponseInfo {
+                        data: Err(RendererError::InvalidRequest),
+                        caps: vec![],
+                    };
+                }
+
+                let mut levels = Vec::with_capacity(lods.len());
+                for (mesh, distance) in lods.iter() {
+                    match Self::try_load_asset::<MeshLoader>(&request, mesh).await {
+                        Ok(mesh) => levels.push((mesh.as_ref().to_owned(), *distance)),
+                        Err(err) => return err.into(),
+                    }
+                }
+
+                let material =
+                    match Self::try_load_asset::<MaterialLoader>(&request, material).await {
+                        Ok(material) => material,
+                        Err(err) => return err.into(),
+                    };
+
+                let id = self.lod_objects.add(
+                    &self.renderer,
+                    levels,
+                    material.as_ref().to_owned(),
+                    *transform,
+                );
+
+                let child = request.spawn(LodObjectInstance::new(
+                    self.renderer.clone(),
+                    self.lod_objects.clone(),
+                    id,
+                ));
+
+                return ResponseInfo {
+                    data: Ok(RendererSuccess::Ok),
+                    caps: vec![child],
+                };
+            }
             SetSkybox { texture } => {
                 let texture =
                     match Self::try_load_asset::<CubeTextureLoader>(&request, texture).await {
@@ -324,6 +509,62 @@ impl RequestResponseProcess for RendererService {
             SetAmbientLighting { ambient } => {
                 let _ = self.command_tx.send(Rend3Command::SetAmbient(*ambient));
             }
+            SetClearColor { color } => {
+                let [r, g, b, a] = color.to_linear_f32().map(f64::from);
+                let color = Color { r, g, b, a };
+
+                let _ = self.command_tx.send(Rend3Command::SetClearColor(color));
+            }
+            SetToneMapping { operator, exposure } => {
+                if !(exposure.is_finite() && *exposure > 0.0) {
+                    return ResponseInfo {
+                        data: Err(RendererError::InvalidRequest),
+                        caps: vec![],
+                    };
+                }
+
+                let _ = self.command_tx.send(Rend3Command::SetToneMapping {
+                    operator: *operator,
+                    exposure: *exposure,
+                });
+            }
+            SetCamera {
+                view,
+                projection_mode,
+                fov,
+            } => {
+                let projection = match *projection_mode {
+                    ProjectionMode::Perspective { near } => {
+                        if !(fov.is_finite() && *fov > 0.0) {
+                            return ResponseInfo {
+                                data: Err(RendererError::InvalidRequest),
+                                caps: vec![],
+                            };
+                        }
+
+                        CameraProjection::Perspective { vfov: *fov, near }
+                    }
+                    ProjectionMode::Orthographic { size } => {
+                        CameraProjection::Orthographic { size: size.into() }
+                    }
+                };
+
+                let _ = self.command_tx.send(Rend3Command::SetCamera(Camera {
+                    projection,
+                    view: *view,
+                }));
+            }
+            Capture => {
+                let data = match self.capture(&request.runtime.lump_store).await {
+                    Ok(lump) => Ok(RendererSuccess::Captured(lump)),
+                    Err(err) => {
+                        error!("failed to capture frame: {err:?}");
+                        Err(RendererError::CaptureFailed)
+                    }
+                };
+
+                return ResponseInfo { data, caps: vec![] };
+            }
         }
 
         ResponseInfo {
@@ -338,15 +579,37 @@ impl ServiceRunner for RendererService {
 }
 
 impl RendererService {
-    pub fn new(renderer: Arc<Renderer>, command_tx: UnboundedSender<Rend3Command>) -> Self {
+    pub fn new(
+        renderer: Arc<Renderer>,
+        command_tx: UnboundedSender<Rend3Command>,
+        lod_objects: LodObjects,
+    ) -> Self {
         Self {
             renderer,
             command_tx,
+            lod_objects,
         }
     }
 
+    /// Captures the next frame and uploads it as a PNG image to the lump store.
+    async fn capture(&self, lump_store: &LumpStoreImpl) -> anyhow::Result<LumpId> {
+        let (on_complete, on_complete_rx) = oneshot::channel();
+        let _ = self.command_tx.send(Rend3Command::Capture { on_complete });
+        let frame = on_complete_rx
+            .await
+            .map_err(|_| anyhow!("frame readback failed"))?;
+
+        let image = RgbaImage::from_raw(frame.size.x, frame.size.y, frame.data)
+            .context("captured frame has invalid size")?;
+
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+
+        Ok(lump_store.add_lump(png.into()).await)
+    }
+
     /// Helper function to attempt to load an asset but log a warning and return
-    /// a `RendererError::LumpError` if unsuccessful.
+    /// a [RendererError] for the stage that failed if unsuccessful.
     async fn try_load_asset<T: AssetLoader>(
         request: &RequestInfo<'_, RendererRequest>,
         lump: &LumpId,
@@ -362,11 +625,30 @@ impl RendererService {
                     std::any::type_name::<T::Asset>(),
                 );
 
-                RendererError::LumpError
+                // errors from asset loaders themselves are data validation
+                // failures, so anything else is an invalid format
+                match err.downcast_ref::<AssetError>() {
+                    Some(AssetError::LumpNotFound(_)) => RendererError::NotFound,
+                    Some(AssetError::Decode(_)) => RendererError::DecodeError,
+                    _ => RendererError::InvalidFormat,
+                }
             })
     }
 }
 
+/// Converts a [TextureData] format to its wgpu equivalent.
+fn convert_texture_format(format: renderer::TextureFormat) -> types::TextureFormat {
+    use renderer::TextureFormat::*;
+    match format {
+        Rgba8UnormSrgb => types::TextureFormat::Rgba8UnormSrgb,
+        Rgba8Unorm => types::TextureFormat::Rgba8Unorm,
+        Rg8Unorm => types::TextureFormat::Rg8Unorm,
+        R8Unorm => types::TextureFormat::R8Unorm,
+        Bc7RgbaUnormSrgb => types::TextureFormat::Bc7RgbaUnormSrgb,
+        Bc7RgbaUnorm => types::TextureFormat::Bc7RgbaUnorm,
+    }
+}
+
 /// Initializes guest-available rendering code.
 #[derive(Default)]
 pub struct RendererPlugin {}
@@ -374,17 +656,19 @@ pub struct RendererPlugin {}
 impl Plugin for RendererPlugin {
     fn build(&mut self, builder: &mut RuntimeBuilder) {
         let rend3 = builder
-            .get_plugin::<Rend3Plugin>()
+            .get_plugin_mut::<Rend3Plugin>()
             .expect("rend3 plugin was not found");
 
         let renderer = rend3.renderer.clone();
         let command_tx = rend3.command_tx.clone();
+        let lod_objects = LodObjects::default();
+        rend3.add_routine(LodRoutine::new(renderer.clone(), lod_objects.clone()));
 
         builder
             .add_asset_loader(MeshLoader(renderer.clone()))
             .add_asset_loader(MaterialLoader(renderer.clone()))
             .add_asset_loader(TextureLoader(renderer.clone()))
             .add_asset_loader(CubeTextureLoader(renderer.clone()))
-            .add_plugin(RendererService::new(renderer, command_tx));
+            .add_plugin(RendererService::new(renderer, command_tx, lod_objects));
     }
 }
@@ -0,0 +1,260 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Objects that switch between meshes of varying detail with camera distance.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use glam::{Mat4, Vec3};
+use hearth_rend3::{
+    rend3::{types::*, Renderer},
+    Node, Routine, RoutineInfo,
+};
+use hearth_runtime::{
+    async_trait, hearth_macros::GetProcessMetadata, hearth_schema::renderer::ObjectUpdate,
+    tracing::warn, utils::*,
+};
+
+/// The fraction of a level's switching distance that the camera needs to move
+/// past before the level is switched.
+///
+/// Without this, a camera hovering around a switching distance would swap an
+/// object's mesh back and forth every frame.
+const LOD_HYSTERESIS: f32 = 0.1;
+
+/// Selects the level of detail to use at the given camera distance.
+///
+/// `levels` are the switching distances of each level in ascending order and
+/// `current` is the level that is currently in use.
+fn select_level(levels: &[f32], current: usize, distance: f32) -> usize {
+    let mut level = current;
+
+    while level + 1 < levels.len() && distance > levels[level + 1] * (1.0 + LOD_HYSTERESIS) {
+        level += 1;
+    }
+
+    while level > 0 && distance < levels[level] * (1.0 - LOD_HYSTERESIS) {
+        level -= 1;
+    }
+
+    level
+}
+
+/// A single object with multiple levels of detail.
+struct LodObject {
+    /// The handle to the current level, or `None` if this object is hidden.
+    handle: Option<ObjectHandle>,
+    meshes: Vec<MeshHandle>,
+    distances: Vec<f32>,
+    material: MaterialHandle,
+    transform: Mat4,
+    current: usize,
+}
+
+impl LodObject {
+    /// Adds the current level of this object to the renderer.
+    ///
+    /// The previous level is removed when its handle is replaced.
+    fn add_current(&self, renderer: &Renderer) -> ObjectHandle {
+        renderer.add_object(Object {
+            mesh_kind: ObjectMeshKind::Static(self.meshes[self.current].clone()),
+            material: self.material.clone(),
+            transform: self.transform,
+        })
+    }
+}
+
+#[derive(Default)]
+struct LodObjectsInner {
+    next_id: usize,
+    objects: HashMap<usize, LodObject>,
+}
+
+/// The set of all LOD objects in the scene.
+///
+/// Shared between the [LodRoutine], which updates the objects' levels every
+/// frame, and each object's [LodObjectInstance].
+#[derive(Clone, Default)]
+pub struct LodObjects(Arc<Mutex<LodObjectsInner>>);
+
+impl LodObjects {
+    /// Adds a new LOD object to the scene and returns its ID.
+    ///
+    /// `levels` must not be empty. Level selection begins at the most detailed
+    /// level and catches up to the camera on the next frame.
+    pub fn add(
+        &self,
+        renderer: &Renderer,
+        mut levels: Vec<(MeshHandle, f32)>,
+        material: MaterialHandle,
+        transform: Mat4,
+    ) -> usize {
+        levels.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (meshes, distances): (Vec<_>, Vec<_>) = levels.into_iter().unzip();
+
+        let handle = renderer.add_object(Object {
+            mesh_kind: ObjectMeshKind::Static(meshes[0].clone()),
+            material: material.clone(),
+            transform,
+        });
+
+        let object = LodObject {
+            handle: Some(handle),
+            meshes,
+            distances,
+            material,
+            transform,
+            current: 0,
+        };
+
+        let mut inner = self.0.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.objects.insert(id, object);
+        id
+    }
+
+    /// Removes a LOD object from the scene.
+    pub fn remove(&self, id: usize) {
+        self.0.lock().unwrap().objects.remove(&id);
+    }
+
+    /// Updates the transform of a LOD object.
+    pub fn set_transform(&self, renderer: &Renderer, id: usize, transform: Mat4) {
+        let mut inner = self.0.lock().unwrap();
+        let Some(object) = inner.objects.get_mut(&id) else {
+            return;
+        };
+
+        object.transform = transform;
+
+        if let Some(handle) = object.handle.as_ref() {
+            renderer.set_object_transform(handle, transform);
+        }
+    }
+
+    /// Shows or hides a LOD object.
+    pub fn set_visible(&self, renderer: &Renderer, id: usize, visible: bool) {
+        let mut inner = self.0.lock().unwrap();
+        let Some(object) = inner.objects.get_mut(&id) else {
+            return;
+        };
+
+        match (visible, object.handle.is_some()) {
+            (true, false) => object.handle = Some(object.add_current(renderer)),
+            (false, true) => object.handle = None,
+            _ => {}
+        }
+    }
+
+    /// Switches every object to the level of detail for the given camera.
+    fn update(&self, renderer: &Renderer, camera_position: Vec3) {
+        let mut inner = self.0.lock().unwrap();
+        for object in inner.objects.values_mut() {
+            let origin = object.transform.w_axis.truncate();
+            let distance = origin.distance(camera_position);
+            let level = select_level(&object.distances, object.current, distance);
+
+            if level != object.current {
+                object.current = level;
+
+                if object.handle.is_some() {
+                    object.handle = Some(object.add_current(renderer));
+                }
+            }
+        }
+    }
+}
+
+/// A [Routine] that updates the levels of all [LodObjects] every frame.
+///
+/// Levels are switched while the frame's render graph is being built, so a
+/// switch is only visible starting on the following frame.
+pub struct LodRoutine {
+    renderer: Arc<Renderer>,
+    objects: LodObjects,
+}
+
+impl LodRoutine {
+    pub fn new(renderer: Arc<Renderer>, objects: LodObjects) -> Self {
+        Self { renderer, objects }
+    }
+}
+
+impl Routine for LodRoutine {
+    fn build_node(&mut self) -> Box<dyn Node<'_> + '_> {
+        Box::new(LodNode { routine: self })
+    }
+}
+
+struct LodNode<'a> {
+    routine: &'a LodRoutine,
+}
+
+impl<'a> Node<'a> for LodNode<'a> {
+    fn draw<'graph>(&'graph self, info: &mut RoutineInfo<'_, 'graph>) {
+        self.routine
+            .objects
+            .update(&self.routine.renderer, info.camera_position);
+    }
+}
+
+/// An instance of a renderer LOD object. Accepts ObjectUpdate.
+#[derive(GetProcessMetadata)]
+pub struct LodObjectInstance {
+    renderer: Arc<Renderer>,
+    objects: LodObjects,
+    id: usize,
+}
+
+impl Drop for LodObjectInstance {
+    fn drop(&mut self) {
+        self.objects.remove(self.id);
+    }
+}
+
+#[async_trait]
+impl SinkProcess for LodObjectInstance {
+    type Message = ObjectUpdate;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        match &message.data {
+            ObjectUpdate::Transform(transform) => {
+                self.objects
+                    .set_transform(&self.renderer, self.id, *transform);
+            }
+            ObjectUpdate::SetVisible(visible) => {
+                self.objects.set_visible(&self.renderer, self.id, *visible);
+            }
+            ObjectUpdate::JointMatrices(_) | ObjectUpdate::JointTransforms { .. } => {
+                warn!("tried to update joints on LOD object");
+            }
+        }
+    }
+}
+
+impl LodObjectInstance {
+    pub fn new(renderer: Arc<Renderer>, objects: LodObjects, id: usize) -> Self {
+        Self {
+            renderer,
+            objects,
+            id,
+        }
+    }
+}
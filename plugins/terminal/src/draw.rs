@@ -119,8 +119,13 @@ pub struct TerminalPipelines {
 
 impl TerminalPipelines {
     /// Initialize a device and queue's GPU state targeting the given output
-    /// surface format.
-    pub fn new(device: Arc<Device>, queue: Arc<Queue>, format: TextureFormat) -> Self {
+    /// surface format and sample count.
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        format: TextureFormat,
+        samples: u32,
+    ) -> Self {
         let shader = device.create_shader_module(&include_wgsl!("shaders.wgsl"));
 
         let camera_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -222,7 +227,10 @@ impl TerminalPipelines {
                     polygon_mode: PolygonMode::Fill,
                     conservative: false,
                 },
-                multisample: MultisampleState::default(),
+                multisample: MultisampleState {
+                    count: samples,
+                    ..Default::default()
+                },
                 fragment: Some(FragmentState {
                     module: &shader,
                     entry_point: fs,
@@ -282,7 +290,10 @@ impl TerminalPipelines {
                 polygon_mode: PolygonMode::Fill,
                 conservative: false,
             },
-            multisample: MultisampleState::default(),
+            multisample: MultisampleState {
+                count: samples,
+                ..Default::default()
+            },
             fragment: Some(FragmentState {
                 module: &grid_shader,
                 entry_point: "grid_fs",
@@ -326,6 +337,7 @@ impl TerminalPipelines {
         graph: &mut RenderGraph<'a>,
         output: RenderTargetHandle,
         depth: RenderTargetHandle,
+        clear: Color,
     ) {
         let mut builder = graph.add_node("terminal");
         let output_handle = builder.add_render_target_output(output);
@@ -333,7 +345,7 @@ impl TerminalPipelines {
         let rpass_handle = builder.add_renderpass(RenderPassTargets {
             targets: vec![RenderPassTarget {
                 color: output_handle,
-                clear: Color::BLACK,
+                clear,
                 resolve: None,
             }],
             depth_stencil: Some(RenderPassDepthTarget {
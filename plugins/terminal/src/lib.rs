@@ -22,13 +22,18 @@ use draw::{TerminalDrawState, TerminalPipelines};
 use hearth_rend3::*;
 use hearth_runtime::{
     async_trait,
+    flue::Table,
     hearth_macros::GetProcessMetadata,
     runtime::{Plugin, RuntimeBuilder},
-    tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    tokio::{
+        self,
+        sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    },
+    tracing::debug,
     utils::*,
 };
 use hearth_schema::terminal::*;
-use terminal::{Terminal, TerminalConfig};
+use terminal::{Terminal, TerminalConfig, TerminalStatus};
 use text::{FaceAtlas, FontSet};
 
 /// Terminal rendering code.
@@ -73,6 +78,7 @@ impl TerminalRoutine {
                 rend3.renderer.device.to_owned(),
                 rend3.renderer.queue.to_owned(),
                 rend3.surface_format,
+                rend3.sample_count as u32,
             ),
             terminals: vec![],
             new_terminals,
@@ -106,10 +112,11 @@ pub struct TerminalNode<'a> {
 
 impl<'a> Node<'a> for TerminalNode<'a> {
     fn draw<'graph>(&'graph self, info: &mut RoutineInfo<'_, 'graph>) {
-        let output = info.graph.add_surface_texture();
+        let output = info.output;
         let depth = info.state.depth;
+        let clear = info.clear_color;
         self.pipelines
-            .add_to_graph(self.draws.as_slice(), info.graph, output, depth);
+            .add_to_graph(self.draws.as_slice(), info.graph, output, depth, clear);
     }
 }
 
@@ -140,6 +147,12 @@ impl SinkProcess for TerminalSink {
             TerminalUpdate::State(state) => {
                 self.inner.update(state);
             }
+            TerminalUpdate::Scroll(lines) => {
+                self.inner.scroll(lines);
+            }
+            TerminalUpdate::SetScrollbackLines(lines) => {
+                self.inner.set_scrollback_lines(lines);
+            }
         }
     }
 }
@@ -160,16 +173,61 @@ impl RequestResponseProcess for TerminalFactory {
         &'a mut self,
         request: &mut RequestInfo<'a, Self::Request>,
     ) -> ResponseInfo<'a, Self::Response> {
-        let FactoryRequest::CreateTerminal(state) = &request.data;
+        let FactoryRequest::CreateTerminal { state, command } = &request.data;
+
+        let (command, args) = match command.as_deref() {
+            None => (None, vec![]),
+            Some([program, args @ ..]) if !program.is_empty() => {
+                (Some(program.to_owned()), args.to_vec())
+            }
+            Some(_) => {
+                return ResponseInfo {
+                    data: Err(FactoryError::InvalidCommand),
+                    caps: vec![],
+                }
+            }
+        };
 
         let config = TerminalConfig {
             fonts: self.fonts.to_owned(),
-            command: None,
+            palette: Default::default(),
+            command,
+            args,
         };
 
         let terminal = Terminal::new(config, state.clone());
         let _ = self.new_terminals_tx.send(terminal.clone());
 
+        // notify the exit listener, if any, when the command exits
+        if let Some(listener) = request.cap_args.first() {
+            let listener = listener.to_owned();
+            let post = request.runtime.post.to_owned();
+            let mut status = terminal.subscribe_status();
+
+            tokio::spawn(async move {
+                while *status.borrow_and_update() == TerminalStatus::Running {
+                    if status.changed().await.is_err() {
+                        return;
+                    }
+                }
+
+                if *status.borrow() != TerminalStatus::Exited {
+                    return;
+                }
+
+                // alacritty_terminal does not expose the child's exit code
+                let exit = TerminalExit { code: None };
+                let data = serde_json::to_vec(&exit).unwrap();
+
+                let table = Table::new(post);
+                let listener = table.import_owned(listener).unwrap();
+                let listener = table.wrap_handle(listener).unwrap();
+                if let Err(err) = listener.send(&data, &[]).await {
+                    debug!("terminal exit listener error: {err:?}");
+                }
+            });
+        }
+
         let child = request.spawn(TerminalSink { inner: terminal });
 
         ResponseInfo {
@@ -183,8 +241,34 @@ impl ServiceRunner for TerminalFactory {
     const NAME: &'static str = "hearth.terminal.TerminalFactory";
 }
 
-#[derive(Default)]
-pub struct TerminalPlugin {}
+/// A plugin that provides the native terminal emulator factory.
+pub struct TerminalPlugin {
+    /// The TTF sources of the terminal font faces.
+    fonts: FontSet<Vec<u8>>,
+}
+
+impl Default for TerminalPlugin {
+    /// Creates a terminal plugin using the bundled mononoki fonts.
+    fn default() -> Self {
+        Self::with_fonts(FontSet {
+            regular: include_bytes!("../../../resources/mononoki/mononoki-Regular.ttf").to_vec(),
+            italic: include_bytes!("../../../resources/mononoki/mononoki-Italic.ttf").to_vec(),
+            bold: include_bytes!("../../../resources/mononoki/mononoki-Bold.ttf").to_vec(),
+            bold_italic: include_bytes!("../../../resources/mononoki/mononoki-BoldItalic.ttf")
+                .to_vec(),
+        })
+    }
+}
+
+impl TerminalPlugin {
+    /// Creates a terminal plugin using the given TTF sources for each face.
+    ///
+    /// The faces should be monospace. Faces that fail to parse will panic
+    /// when the plugin is built.
+    pub fn with_fonts(fonts: FontSet<Vec<u8>>) -> Self {
+        Self { fonts }
+    }
+}
 
 impl Plugin for TerminalPlugin {
     fn build(&mut self, builder: &mut RuntimeBuilder) {
@@ -192,13 +276,7 @@ impl Plugin for TerminalPlugin {
             .get_plugin_mut::<Rend3Plugin>()
             .expect("rend3 plugin was not found");
 
-        let ttf_srcs = FontSet {
-            regular: include_bytes!("../../../resources/mononoki/mononoki-Regular.ttf").to_vec(),
-            italic: include_bytes!("../../../resources/mononoki/mononoki-Italic.ttf").to_vec(),
-            bold: include_bytes!("../../../resources/mononoki/mononoki-Bold.ttf").to_vec(),
-            bold_italic: include_bytes!("../../../resources/mononoki/mononoki-BoldItalic.ttf")
-                .to_vec(),
-        };
+        let ttf_srcs = std::mem::take(&mut self.fonts);
 
         let fonts = ttf_srcs.map(|src| {
             let face = owned_ttf_parser::OwnedFace::from_vec(src, 0).unwrap();
@@ -214,7 +292,8 @@ impl Plugin for TerminalPlugin {
 
         let (new_terminals_tx, new_terminals) = unbounded_channel();
 
-        rend3.add_routine(TerminalRoutine::new(rend3, new_terminals));
+        // draw terminals on top of canvases
+        rend3.add_routine_ordered(300, TerminalRoutine::new(rend3, new_terminals));
 
         builder.add_plugin(TerminalFactory {
             fonts,
@@ -17,6 +17,7 @@
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
+    collections::HashMap,
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::{channel, Sender},
@@ -30,7 +31,7 @@ use alacritty_terminal::{
     config::PtyConfig,
     event::{Event, EventListener},
     event_loop::{EventLoop, Msg, State},
-    grid::Indexed,
+    grid::{Indexed, Scroll},
     sync::FairMutex,
     term::{
         cell::{Cell, Flags},
@@ -42,7 +43,8 @@ use alacritty_terminal::{
 };
 use glam::{vec2, IVec2, Mat4, UVec2, Vec2};
 use hearth_rend3::wgpu::{Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect};
-use hearth_schema::terminal::TerminalState;
+use hearth_runtime::tokio::sync::watch;
+use hearth_schema::terminal::{TerminalPalette, TerminalState};
 use mio_extras::channel::Sender as MioSender;
 use owned_ttf_parser::AsFaceRef;
 
@@ -72,10 +74,17 @@ impl EventListener for Listener {
 pub struct TerminalConfig {
     pub fonts: FontSet<Arc<FaceAtlas>>,
 
+    /// The palette this terminal starts with, unless the initial
+    /// [TerminalState] provides its own.
+    pub palette: TerminalPalette,
+
     /// The command that this terminal will run.
     ///
     /// Defaults to a platform-specific shell.
     pub command: Option<String>,
+
+    /// The arguments passed to [Self::command].
+    pub args: Vec<String>,
 }
 
 impl TerminalConfig {
@@ -146,10 +155,60 @@ impl From<Arc<FaceAtlas>> for FaceWithMetrics {
     }
 }
 
+/// The maximum number of lines that a terminal's scrollback can hold.
+///
+/// Matches the limit of Alacritty's own configuration.
+pub const MAX_SCROLLBACK_LINES: u32 = 100_000;
+
 /// Private terminal mutable state.
 struct TerminalInner {
     grid_size: UVec2,
     state: TerminalState,
+    palette: TerminalPalette,
+}
+
+/// Converts a Hearth color to an `alacritty_terminal` color, dropping alpha.
+fn to_rgb(color: hearth_schema::Color) -> Rgb {
+    let (_a, r, g, b) = color.to_argb();
+    Rgb { r, g, b }
+}
+
+/// Resolves a palette and its per-index overrides into a color list.
+fn resolve_colors(
+    palette: &TerminalPalette,
+    overrides: &HashMap<usize, hearth_schema::Color>,
+) -> Colors {
+    let mut colors = Colors::default();
+
+    for (index, color) in palette.ansi.iter().enumerate() {
+        colors[index] = Some(to_rgb(*color));
+    }
+
+    let cursor = palette.cursor.unwrap_or(palette.foreground);
+    colors[NamedColor::Foreground] = Some(to_rgb(palette.foreground));
+    colors[NamedColor::Background] = Some(to_rgb(palette.background));
+    colors[NamedColor::Cursor] = Some(to_rgb(cursor));
+
+    for (index, color) in overrides.iter() {
+        if *index < COUNT {
+            colors[*index] = Some(to_rgb(*color));
+        }
+    }
+
+    colors
+}
+
+/// Whether a [Terminal] is running and how it stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminalStatus {
+    /// The terminal's command is running.
+    Running,
+
+    /// The terminal was quit with [Terminal::quit].
+    Quit,
+
+    /// The terminal's command exited on its own.
+    Exited,
 }
 
 /// A CPU-side wrapper around terminal functionality.
@@ -158,6 +217,7 @@ pub struct Terminal {
     _term_loop: JoinHandle<(EventLoop<Pty, Listener>, State)>,
     term_channel: FairMutex<MioSender<Msg>>,
     should_quit: AtomicBool,
+    status: watch::Sender<TerminalStatus>,
     inner: FairMutex<TerminalInner>,
     fonts: FontSet<FaceWithMetrics>,
     font_baselines: FontSet<f32>,
@@ -165,7 +225,7 @@ pub struct Terminal {
 }
 
 impl Terminal {
-    pub fn new(config: TerminalConfig, initial_state: TerminalState) -> Arc<Self> {
+    pub fn new(config: TerminalConfig, mut initial_state: TerminalState) -> Arc<Self> {
         let fonts = config.fonts.clone().map(FaceWithMetrics::from);
         let cell_size = Vec2::new(fonts.regular.width, fonts.regular.height);
         let font_baselines = fonts
@@ -189,8 +249,10 @@ impl Terminal {
 
         let (sender, term_events) = channel();
 
-        let command = config.unwrap_command();
-        let shell = alacritty_terminal::config::Program::Just(command);
+        let shell = alacritty_terminal::config::Program::WithArgs {
+            program: config.unwrap_command(),
+            args: config.args.clone(),
+        };
 
         let term_config = alacritty_terminal::config::Config {
             pty_config: PtyConfig {
@@ -216,9 +278,12 @@ impl Terminal {
         let term_loop = EventLoop::new(term.clone(), term_listener, pty, false, false);
         let term_channel = term_loop.channel();
 
+        let palette = initial_state.palette.take().unwrap_or(config.palette);
+
         let inner = TerminalInner {
             grid_size,
             state: initial_state,
+            palette,
         };
 
         let term = Self {
@@ -227,6 +292,7 @@ impl Terminal {
             _term_loop: term_loop.spawn(),
             term_channel: FairMutex::new(term_channel),
             should_quit: AtomicBool::new(false),
+            status: watch::channel(TerminalStatus::Running).0,
             inner: FairMutex::new(inner),
             cell_size,
             font_baselines,
@@ -248,9 +314,13 @@ impl Terminal {
         self.fonts.as_ref().map(|font| font.atlas.to_owned())
     }
 
-    pub fn update(&self, state: TerminalState) {
+    pub fn update(&self, mut state: TerminalState) {
         let mut inner = self.inner.lock();
 
+        if let Some(palette) = state.palette.take() {
+            inner.palette = palette;
+        }
+
         let available = (state.half_size - state.padding) * 2.0;
         let grid_size = (available / self.cell_size / state.units_per_em)
             .floor()
@@ -284,12 +354,14 @@ impl Terminal {
         let inner = self.inner.lock();
         let grid_size = inner.grid_size;
         let state = inner.state.clone();
+        let colors = resolve_colors(&inner.palette, &state.colors);
         drop(inner); // get off the mutex
 
         let font_baselines = self.font_baselines.clone();
         let mut canvas = TerminalCanvas::new(
             self.fonts.clone(),
             state,
+            colors,
             grid_size,
             self.cell_size,
             font_baselines,
@@ -303,8 +375,43 @@ impl Terminal {
         canvas.apply_to_state(pipelines, draw);
     }
 
+    /// Scrolls the viewport by a number of lines, clamped to the scrollback.
+    ///
+    /// Positive values scroll up into the scrollback.
+    pub fn scroll(&self, lines: i32) {
+        self.term.lock().scroll_display(Scroll::Delta(lines));
+    }
+
+    /// Sets the maximum number of scrollback lines, up to
+    /// [MAX_SCROLLBACK_LINES].
+    pub fn set_scrollback_lines(&self, lines: u32) {
+        let lines = lines.min(MAX_SCROLLBACK_LINES) as usize;
+        self.term.lock().grid_mut().update_history(lines);
+    }
+
+    /// Renders the visible rows of this terminal's grid as text.
+    ///
+    /// See [term_text] for the format. Does not require a GPU, so it can be
+    /// used to check a terminal's output in tests.
+    pub fn dump_text(&self) -> String {
+        term_text(&self.term.lock())
+    }
+
     pub fn quit(&self) {
         self.should_quit.store(true, Ordering::Relaxed);
+        self.stop(TerminalStatus::Quit);
+    }
+
+    /// Subscribes to changes in this terminal's [TerminalStatus].
+    pub fn subscribe_status(&self) -> watch::Receiver<TerminalStatus> {
+        self.status.subscribe()
+    }
+
+    /// Sets the status of this terminal if it hasn't already stopped.
+    fn stop(&self, status: TerminalStatus) {
+        if *self.status.borrow() == TerminalStatus::Running {
+            self.status.send_replace(status);
+        }
     }
 
     pub fn should_quit(&self) -> bool {
@@ -320,26 +427,23 @@ impl Terminal {
     fn on_event(&self, event: Event) {
         match event {
             Event::ColorRequest(index, format) => {
-                let color = self
-                    .inner
-                    .lock()
-                    .state
-                    .colors
-                    .get(&index)
-                    .map(|color| {
-                        let (_, r, g, b) = color.to_argb();
-                        Rgb { r, g, b }
-                    })
-                    .unwrap_or(Rgb {
-                        r: 0xff,
-                        g: 0xff,
-                        b: 0xff,
-                    });
+                let inner = self.inner.lock();
+                let colors = resolve_colors(&inner.palette, &inner.state.colors);
+                drop(inner); // get off the mutex
+
+                let color = colors[index].unwrap_or(Rgb {
+                    r: 0xff,
+                    g: 0xff,
+                    b: 0xff,
+                });
 
                 self.send_input(&format(color));
             }
             Event::PtyWrite(text) => self.send_input(&text),
-            Event::Exit => self.should_quit.store(true, Ordering::Relaxed),
+            Event::Exit => {
+                self.should_quit.store(true, Ordering::Relaxed);
+                self.stop(TerminalStatus::Exited);
+            }
             _ => {}
         }
     }
@@ -363,17 +467,11 @@ impl TerminalCanvas {
     pub fn new(
         fonts: FontSet<FaceWithMetrics>,
         state: TerminalState,
+        colors: Colors,
         grid_size: UVec2,
         cell_size: Vec2,
         font_baselines: FontSet<f32>,
     ) -> Self {
-        let mut colors = Colors::default();
-
-        for (index, color) in state.colors.iter() {
-            let (_a, r, g, b) = color.to_argb();
-            colors[*index] = Some(Rgb { r, g, b });
-        }
-
         Self {
             fonts,
             bg_texture: vec![0; (grid_size.x * grid_size.y) as usize],
@@ -563,7 +661,7 @@ impl TerminalCanvas {
     }
 
     pub fn draw_cursor(&mut self, cursor: RenderableCursor) {
-        let cursor_color = Color::Named(NamedColor::Foreground);
+        let cursor_color = Color::Named(NamedColor::Cursor);
         let cursor_color = self.color_to_u32(cursor_color);
         let col = cursor.point.column.0 as i32;
         let row = cursor.point.line.0;
@@ -704,3 +802,84 @@ impl TerminalCanvas {
         ((alpha as u32) << 24) | (base & 0x00ffffff)
     }
 }
+
+/// Renders the visible rows of a terminal's grid as text.
+///
+/// Rows are joined with newlines and have their trailing whitespace trimmed.
+/// Hidden cells are rendered as spaces and the spacers after wide characters
+/// are skipped.
+fn term_text<T: EventListener>(term: &Term<T>) -> String {
+    let content = term.renderable_content();
+
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut current_line = None;
+    for cell in content.display_iter {
+        if current_line
+            .replace(cell.point.line)
+            .is_some_and(|line| line != cell.point.line)
+        {
+            rows.push(row.trim_end().to_string());
+            row.clear();
+        }
+
+        if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+            continue;
+        }
+
+        if cell.flags.contains(Flags::HIDDEN) {
+            row.push(' ');
+            continue;
+        }
+
+        row.push(cell.c);
+
+        if let Some(zerowidth) = cell.zerowidth() {
+            row.extend(zerowidth);
+        }
+    }
+
+    rows.push(row.trim_end().to_string());
+    rows.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::{ansi::Processor, config::Config, term::SizeInfo};
+
+    use super::*;
+
+    /// Ignores all terminal events.
+    struct NoopListener;
+
+    impl EventListener for NoopListener {}
+
+    /// Writes input to a headless terminal and renders its text.
+    fn render(columns: usize, lines: usize, input: &str) -> String {
+        let size = SizeInfo::new(columns as f32, lines as f32, 1.0, 1.0, 0.0, 0.0, false);
+        let mut term = Term::new(&Config::default(), size, NoopListener);
+        let mut processor = Processor::new();
+
+        for byte in input.bytes() {
+            processor.advance(&mut term, byte);
+        }
+
+        term_text(&term)
+    }
+
+    #[test]
+    fn trims_trailing_spaces() {
+        assert_eq!(render(10, 3, "hi   \r\n there "), "hi\n there\n");
+    }
+
+    #[test]
+    fn skips_wide_char_spacers() {
+        assert_eq!(render(10, 1, "a\u{4e2d}\u{6587}b"), "a\u{4e2d}\u{6587}b");
+    }
+
+    #[test]
+    fn blanks_hidden_cells() {
+        assert_eq!(render(10, 1, "\x1b[8msecret\x1b[0m!"), "      !");
+        assert_eq!(render(20, 1, "shown\x1b[8m hidden"), "shown");
+    }
+}
@@ -16,21 +16,23 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::time::SystemTime;
+use std::{sync::Arc, time::SystemTime};
 
 use hearth_runtime::{
     async_trait,
-    flue::Table,
+    flue::{CapabilityRef, Permissions, PostOffice, Table, TableSignal},
     hearth_macros::GetProcessMetadata,
-    runtime::{Plugin, RuntimeBuilder},
+    hearth_schema::time::{AlarmRequest, IntervalRequest, SleepRequest, TimerRequest},
+    process::Process,
+    runtime::{Plugin, Runtime, RuntimeBuilder},
     tokio::{
         self,
-        time::{Duration, Instant},
+        time::{Duration, Instant, MissedTickBehavior},
     },
-    tracing::debug,
+    tracing::{debug, warn},
     utils::{
-        MessageInfo, RequestInfo, RequestResponseProcess, ResponseInfo, RunnerContext,
-        ServiceRunner, SinkProcess,
+        MessageInfo, ProcessRunToken, ProcessRunner, RequestInfo, RequestResponseProcess,
+        ResponseInfo, RunnerContext, ServiceRunner, SinkProcess,
     },
 };
 
@@ -38,8 +40,10 @@ use hearth_runtime::{
 ///
 /// Adds the following services:
 /// - [SleepService]
+/// - [AlarmService]
 /// - [TimerFactory]
 /// - [StopwatchFactory]
+/// - [IntervalFactory]
 /// - [UnixTimeService]
 #[derive(Default)]
 pub struct TimePlugin;
@@ -48,12 +52,51 @@ impl Plugin for TimePlugin {
     fn build(&mut self, builder: &mut RuntimeBuilder) {
         builder
             .add_plugin(SleepService)
+            .add_plugin(AlarmService)
             .add_plugin(TimerFactory)
             .add_plugin(StopwatchFactory)
+            .add_plugin(IntervalFactory)
             .add_plugin(UnixTimeService);
     }
 }
 
+/// Gets the reply capability of a request from the first of its
+/// capabilities.
+///
+/// Logs a warning and returns `None` if the request has no reply capability or
+/// if the reply capability does not permit sending.
+fn get_reply<'a>(service: &str, caps: &'a [CapabilityRef<'a>]) -> Option<&'a CapabilityRef<'a>> {
+    let Some(reply) = caps.first() else {
+        warn!("{} request has no reply address", service);
+        return None;
+    };
+
+    if !reply.get_permissions().contains(Permissions::SEND) {
+        warn!("{} request's reply address doesn't permit send", service);
+        return None;
+    }
+
+    Some(reply)
+}
+
+/// Spawns a task that sends an empty message to a reply capability once a
+/// duration has elapsed, so that services can keep receiving requests.
+fn reply_after(post: Arc<PostOffice>, reply: &CapabilityRef<'_>, duration: Duration) {
+    let reply = reply.to_owned();
+
+    tokio::spawn(async move {
+        tokio::time::sleep(duration).await;
+
+        let table = Table::new(post);
+        let reply_handle = table.import_owned(reply).unwrap();
+        let reply_cap = table.wrap_handle(reply_handle).unwrap();
+
+        if let Err(err) = reply_cap.send(&[], &[]).await {
+            debug!("Failed to send time reply: {:?}", err);
+        }
+    });
+}
+
 /// Receives a single floating-point number as a request, waits the value of
 /// the number in seconds, then responds with an empty message.
 #[derive(GetProcessMetadata)]
@@ -63,27 +106,19 @@ pub struct SleepService;
 // asynchronously.
 #[async_trait]
 impl SinkProcess for SleepService {
-    type Message = f32;
+    type Message = SleepRequest;
 
     async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
-        let Some(reply) = message.caps.first() else {
-            debug!("Sleep request has no reply address");
+        let Some(reply) = get_reply("Sleep", message.caps) else {
             return;
         };
 
-        let duration = Duration::from_secs_f32(message.data);
-        let reply = reply.to_owned();
-        let post = message.runtime.post.to_owned();
-
-        // spawn thread to reply so that we can receive more sleep requests
-        tokio::spawn(async move {
-            tokio::time::sleep(duration).await;
+        let Ok(duration) = Duration::try_from_secs_f32(message.data.0) else {
+            warn!("Sleep request has invalid duration {}", message.data.0);
+            return;
+        };
 
-            let table = Table::new(post);
-            let reply_handle = table.import_owned(reply).unwrap();
-            let reply_cap = table.wrap_handle(reply_handle).unwrap();
-            reply_cap.send(&[], &[]).await.unwrap();
-        });
+        reply_after(message.runtime.post.to_owned(), reply, duration);
     }
 }
 
@@ -91,6 +126,40 @@ impl ServiceRunner for SleepService {
     const NAME: &'static str = "hearth.Sleep";
 }
 
+/// Receives a deadline as a 128-bit count of nanoseconds since the UNIX epoch,
+/// waits until the system clock reaches it, then responds with an empty
+/// message.
+///
+/// Deadlines in the past are responded to immediately.
+#[derive(GetProcessMetadata)]
+pub struct AlarmService;
+
+#[async_trait]
+impl SinkProcess for AlarmService {
+    type Message = AlarmRequest;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        let Some(reply) = get_reply("Alarm", message.caps) else {
+            return;
+        };
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("system time before UNIX epoch")
+            .as_nanos();
+
+        // saturate deadlines too far in the future to fit in a Duration
+        let remaining = message.data.0.saturating_sub(now);
+        let remaining = u64::try_from(remaining).unwrap_or(u64::MAX);
+        let duration = Duration::from_nanos(remaining);
+        reply_after(message.runtime.post.to_owned(), reply, duration);
+    }
+}
+
+impl ServiceRunner for AlarmService {
+    const NAME: &'static str = "hearth.Alarm";
+}
+
 /// Responds to empty request messages with a capability to a new instance of
 /// a [Timer].
 #[derive(GetProcessMetadata)]
@@ -137,16 +206,25 @@ pub struct Timer {
 
 #[async_trait]
 impl RequestResponseProcess for Timer {
-    type Request = f32;
+    type Request = TimerRequest;
     type Response = ();
 
     async fn on_request<'a>(
         &'a mut self,
         request: &mut RequestInfo<'a, Self::Request>,
     ) -> ResponseInfo<'a, Self::Response> {
-        let duration = Duration::from_secs_f32(request.data);
-        self.last_request += duration;
-        tokio::time::sleep_until(self.last_request).await;
+        // respond immediately to invalid durations without moving the clock
+        let deadline = Duration::try_from_secs_f32(request.data.0)
+            .ok()
+            .and_then(|duration| self.last_request.checked_add(duration));
+
+        match deadline {
+            Some(deadline) => {
+                self.last_request = deadline;
+                tokio::time::sleep_until(deadline).await;
+            }
+            None => warn!("Timer request has invalid duration {}", request.data.0),
+        }
 
         ResponseInfo {
             data: (),
@@ -211,6 +289,99 @@ impl RequestResponseProcess for Stopwatch {
     }
 }
 
+/// Receives an [IntervalRequest] and spawns an [Interval] that sends ticks to
+/// the request's reply capability.
+#[derive(GetProcessMetadata)]
+pub struct IntervalFactory;
+
+#[async_trait]
+impl SinkProcess for IntervalFactory {
+    type Message = IntervalRequest;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        let Some(reply) = get_reply("Interval", message.caps) else {
+            return;
+        };
+
+        // reject negative, non-finite, overflowing, and zero periods
+        let period = match Duration::try_from_secs_f32(message.data.0) {
+            Ok(period) if !period.is_zero() => period,
+            _ => {
+                warn!("Interval request has invalid period {}", message.data.0);
+                return;
+            }
+        };
+
+        let child = message.spawn(Interval { period });
+
+        // pass the reply capability on to the new interval
+        if let Err(err) = child.send(&[], &[reply]).await {
+            debug!("Failed to start interval: {:?}", err);
+        }
+    }
+}
+
+impl ServiceRunner for IntervalFactory {
+    const NAME: &'static str = "hearth.IntervalFactory";
+}
+
+/// Sends an empty message to a reply capability every period until the
+/// capability's route is closed.
+///
+/// Receives the reply capability as its first message. Ticks are scheduled
+/// on a fixed cadence, so a slow receiver delays later ticks instead of
+/// causing a burst of them to catch up.
+#[derive(GetProcessMetadata)]
+pub struct Interval {
+    period: Duration,
+}
+
+#[async_trait]
+impl ProcessRunner for Interval {
+    async fn run(self, label: String, _runtime: Arc<Runtime>, ctx: &Process, _: ProcessRunToken) {
+        let on_recv = |signal: TableSignal<'_>| match signal {
+            TableSignal::Message { caps, .. } => caps.first().copied(),
+            _ => None,
+        };
+
+        let Some(Some(reply)) = ctx.borrow_parent().recv(on_recv).await else {
+            debug!("{} received no reply address", label);
+            return;
+        };
+
+        let reply = ctx.borrow_table().wrap_handle(reply).unwrap();
+
+        // stop early when the reply route closes, if we're allowed to watch it
+        if reply.monitor(ctx.borrow_parent()).is_err() {
+            debug!("{} can't monitor its reply address", label);
+        }
+
+        let mut interval = tokio::time::interval(self.period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // skip the first tick, which completes immediately
+        interval.tick().await;
+
+        let is_down = |signal: TableSignal<'_>| matches!(signal, TableSignal::Down { .. });
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if reply.send(&[], &[]).await.is_err() {
+                        break;
+                    }
+                }
+                signal = ctx.borrow_parent().recv(is_down) => {
+                    match signal {
+                        Some(true) | None => break, // reply route closed or killed
+                        Some(false) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
 /// Native service that returns time since the UNIX epoch in nanoseconds as an
 /// unsigned 128-bit integer.
 #[derive(GetProcessMetadata)]
@@ -239,3 +410,91 @@ impl RequestResponseProcess for UnixTimeService {
 impl ServiceRunner for UnixTimeService {
     const NAME: &'static str = "hearth.UnixTime";
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hearth_runtime::{
+        process::{ProcessFactory, ProcessMetadata},
+        runtime::RuntimeConfig,
+    };
+
+    #[test]
+    fn reply_validation() {
+        let factory = ProcessFactory::new(PostOffice::new());
+        let process = factory.spawn(ProcessMetadata::default());
+        let send = process.borrow_parent().export(Permissions::SEND).unwrap();
+        let monitor = process
+            .borrow_parent()
+            .export(Permissions::MONITOR)
+            .unwrap();
+
+        assert!(get_reply("Sleep", &[]).is_none());
+        assert!(get_reply("Sleep", &[monitor]).is_none());
+        assert!(get_reply("Sleep", &[send]).is_some());
+    }
+
+    #[tokio::test]
+    async fn sleep_invalid_reply() {
+        let runtime = RuntimeBuilder::new().run(RuntimeConfig::default()).await;
+        let parent = runtime.process_factory.spawn(ProcessMetadata::default());
+        let service = runtime.process_factory.spawn(ProcessMetadata::default());
+
+        let service_cap = service
+            .borrow_parent()
+            .export_to(Permissions::SEND, parent.borrow_table())
+            .unwrap();
+
+        SleepService.spawn("sleep".to_string(), runtime.clone(), service);
+
+        let reply = parent.borrow_group().create_mailbox().unwrap();
+        let monitor = reply.export(Permissions::MONITOR).unwrap();
+        let send = reply.export(Permissions::SEND).unwrap();
+        let request = serde_json::to_vec(&SleepRequest(0.0)).unwrap();
+
+        // requests without a usable reply address are dropped
+        service_cap.send(&request, &[]).await.unwrap();
+        service_cap.send(&request, &[&monitor]).await.unwrap();
+
+        // so are requests with durations that can't be represented
+        for duration in [-1.0, 1e20] {
+            let invalid = serde_json::to_vec(&SleepRequest(duration)).unwrap();
+            service_cap.send(&invalid, &[&send]).await.unwrap();
+        }
+
+        // the service keeps handling valid requests afterwards
+        service_cap.send(&request, &[&send]).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(1), reply.recv(|_| ())).await;
+        assert_eq!(received, Ok(Some(())));
+    }
+
+    #[tokio::test]
+    async fn interval_invalid_period() {
+        let runtime = RuntimeBuilder::new().run(RuntimeConfig::default()).await;
+        let parent = runtime.process_factory.spawn(ProcessMetadata::default());
+        let service = runtime.process_factory.spawn(ProcessMetadata::default());
+
+        let service_cap = service
+            .borrow_parent()
+            .export_to(Permissions::SEND, parent.borrow_table())
+            .unwrap();
+
+        IntervalFactory.spawn("interval".to_string(), runtime.clone(), service);
+
+        let reply = parent.borrow_group().create_mailbox().unwrap();
+        let send = reply.export(Permissions::SEND).unwrap();
+
+        // periods that can't be represented as a duration are dropped
+        for period in [1e20, -1.0, 0.0] {
+            let request = serde_json::to_vec(&IntervalRequest(period)).unwrap();
+            service_cap.send(&request, &[&send]).await.unwrap();
+        }
+
+        // the factory keeps handling valid requests afterwards
+        let request = serde_json::to_vec(&IntervalRequest(0.01)).unwrap();
+        service_cap.send(&request, &[&send]).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(1), reply.recv(|_| ())).await;
+        assert_eq!(received, Ok(Some(())));
+    }
+}
@@ -16,7 +16,7 @@ async fn main() {
         .expect("expected path to .wasm file");
     let wasm_data = std::fs::read(wasm_path).unwrap();
 
-    let config = RuntimeConfig {};
+    let config = RuntimeConfig::default();
 
     let mut builder = RuntimeBuilder::new();
     builder.add_plugin(hearth_wasm::WasmPlugin::default());
@@ -26,6 +26,9 @@ async fn main() {
     let spawn_info = WasmSpawnInfo {
         lump: wasm_lump,
         entrypoint: None,
+        ready_timeout: None,
+        fuel_limit: None,
+        priority: 0,
     };
 
     let meta = cargo_process_metadata!();
@@ -16,24 +16,40 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
+use std::future::{poll_fn, Future};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+
+use futures_util::task::AtomicWaker;
 
 use hearth_runtime::anyhow::{anyhow, bail, Context, Result};
 use hearth_runtime::asset::{AssetLoader, AssetStore};
 use hearth_runtime::flue::{
-    CapabilityHandle, CapabilityRef, Mailbox, MailboxGroup, Permissions, Table, TableSignal,
+    CapabilityHandle, CapabilityRef, Mailbox, MailboxGroup, OwnedCapability, Permissions,
+    PostOffice, Table, TableSignal,
 };
 use hearth_runtime::hearth_macros::{impl_wasm_linker, GetProcessMetadata};
-use hearth_runtime::lump::{bytes::Bytes, LumpStoreImpl};
+use hearth_runtime::lump::{
+    bytes::{Bytes, BytesMut},
+    LumpPin, LumpStoreImpl,
+};
 use hearth_runtime::process::{Process, ProcessMetadata};
 use hearth_runtime::runtime::{Plugin, Runtime, RuntimeBuilder};
 use hearth_runtime::{async_trait, hearth_schema};
-use hearth_runtime::{tokio, utils::*};
-use hearth_schema::wasm::WasmSpawnInfo;
+use hearth_runtime::{cargo_process_metadata, tokio, utils::*};
+use hearth_schema::wasm::*;
 use hearth_schema::{LumpId, ProcessLogLevel, SignalKind};
 use slab::Slab;
-use tracing::{error, warn};
-use wasmtime::{Caller, Config, Engine, Instance, Linker, Module, Store, UpdateDeadline};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
+use wasmtime::{
+    Caller, Config, Engine, Instance, Linker, Memory, Module, Store, StoreContextMut, StoreLimits,
+    StoreLimitsBuilder, Trap, UpdateDeadline, WasmBacktrace,
+};
 
 /// An interface to attempt to acquire a Wasm ABI by type.
 pub trait GetAbi<T>
@@ -44,6 +60,19 @@ where
     fn get_abi(&mut self) -> Result<&mut T>;
 }
 
+/// The version of the host ABI provided by this plugin.
+///
+/// This is incremented whenever the ABI changes incompatibly, and must match
+/// the guest's `hearth_guest::ABI_VERSION` for it to be spawned.
+pub const ABI_VERSION: u32 = 1;
+
+/// The highest process priority that lengthens a process's time slice.
+///
+/// A process with priority `p` runs for `p + 1` times as many epochs as a
+/// process with the default priority of zero before it yields. Higher
+/// priorities in [WasmSpawnInfo::priority] are capped to this.
+pub const MAX_PRIORITY: u32 = 7;
+
 /// An interface for Wasm ABIs: host-side data exposed to WebAssembly through a
 /// set of linked host functions.
 ///
@@ -83,13 +112,45 @@ impl<'a> GuestMemory<'a> {
             .with_context(|| format!("GuestMemory::get_str({}, {})", ptr, len))
     }
 
+    /// Copies a string out of guest memory.
+    ///
+    /// Fails if out-of-bounds or if the string is not valid UTF-8.
+    pub fn get_str_owned(&self, ptr: u32, len: u32) -> Result<String> {
+        self.get_str(ptr, len).map(|str| str.to_string())
+    }
+
+    /// Copies a byte slice into guest memory at the given pointer.
+    ///
+    /// Fails if out-of-bounds.
+    pub fn write_slice(&self, ptr: u32, data: &[u8]) -> Result<()> {
+        let len = data
+            .len()
+            .try_into()
+            .with_context(|| format!("GuestMemory::write_slice({}, ..) is too long", ptr))?;
+
+        self.get_slice(ptr, len)?.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Copies a string's bytes into guest memory at the given pointer.
+    ///
+    /// Fails if out-of-bounds.
+    pub fn write_str(&self, ptr: u32, str: &str) -> Result<()> {
+        self.write_slice(ptr, str.as_bytes())
+    }
+
     /// Retrieves a byte slice of guest memory by its pointer and length.
     ///
     /// Fails if out-of-bounds.
     pub fn get_slice(&self, ptr: u32, len: u32) -> Result<&'a mut [u8]> {
         let ptr = ptr as usize;
         let len = len as usize;
-        if ptr + len > self.bytes.len() {
+        let in_bounds = ptr
+            .checked_add(len)
+            .map(|end| end <= self.bytes.len())
+            .unwrap_or(false);
+
+        if !in_bounds {
             Err(anyhow!(
                 "GuestMemory::get_slice({}, {}) is out-of-bounds",
                 ptr,
@@ -147,6 +208,10 @@ impl LogAbi {
     /// Logs an event for this process.
     ///
     /// Each argument corresponds to a field in [ProcessLogEvent].
+    /// `fields_ptr` and `fields_len` optionally point to a JSON object of
+    /// structured fields to attach to the event. If `fields_len` is zero, the
+    /// event has no structured fields.
+    #[allow(clippy::too_many_arguments)]
     async fn log(
         &self,
         memory: GuestMemory<'_>,
@@ -155,33 +220,150 @@ impl LogAbi {
         module_len: u32,
         content_ptr: u32,
         content_len: u32,
+        fields_ptr: u32,
+        fields_len: u32,
     ) -> Result<()> {
         let level = level
             .try_into()
             .map_err(|_| anyhow!("invalid log level constant {}", level))?;
 
-        let module = memory.get_str(module_ptr, module_len)?.to_string();
-        let content = memory.get_str(content_ptr, content_len)?.to_string();
+        let module = memory.get_str_owned(module_ptr, module_len)?;
+        let content = memory.get_str_owned(content_ptr, content_len)?;
+
+        let fields = if fields_len == 0 {
+            None
+        } else {
+            let fields = memory.get_str(fields_ptr, fields_len)?;
+
+            serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(fields)
+                .context("parsing log fields")?;
+
+            Some(fields.to_string())
+        };
 
+        let fields = fields.as_deref();
         let info = self.process.borrow_info();
         info.process_span.in_scope(|| match level {
-            ProcessLogLevel::Trace => tracing::trace!(module, "{content}"),
-
-            ProcessLogLevel::Debug => tracing::debug!(module, "{content}"),
-            ProcessLogLevel::Info => tracing::info!(module, "{content}"),
-            ProcessLogLevel::Warning => tracing::warn!(module, "{content}"),
-            ProcessLogLevel::Error => tracing::error!(module, "{content}"),
+            ProcessLogLevel::Trace => tracing::trace!(module, fields, "{content}"),
+            ProcessLogLevel::Debug => tracing::debug!(module, fields, "{content}"),
+            ProcessLogLevel::Info => tracing::info!(module, fields, "{content}"),
+            ProcessLogLevel::Warning => tracing::warn!(module, fields, "{content}"),
+            ProcessLogLevel::Error => tracing::error!(module, fields, "{content}"),
         });
 
         Ok(())
     }
 }
 
+/// Implements the `hearth::random` ABI module.
+pub struct RandomAbi;
+
+#[impl_wasm_linker(module = "hearth::random")]
+impl RandomAbi {
+    /// Fills a region of guest memory with cryptographically secure random
+    /// bytes from the host's entropy source.
+    async fn fill(&self, memory: GuestMemory<'_>, ptr: u32, len: u32) -> Result<()> {
+        let dst = memory.get_slice(ptr, len)?;
+        getrandom::getrandom(dst).context("generating random bytes")?;
+        Ok(())
+    }
+}
+
+/// The maximum total size in bytes of the keys and values in a process's
+/// [KvAbi] store.
+pub const MAX_KV_BYTES: usize = 1 << 20;
+
+/// Implements the `hearth::kv` ABI module.
+///
+/// A host-side key-value store private to each process, for small values
+/// that a guest wants to keep outside of its own memory. The store is cleared
+/// when the process exits.
+#[derive(Debug, Default)]
+pub struct KvAbi {
+    values: HashMap<String, Vec<u8>>,
+
+    /// The total size in bytes of all keys and values.
+    size: usize,
+}
+
+#[impl_wasm_linker(module = "hearth::kv")]
+impl KvAbi {
+    /// Sets the value of a key, replacing any previous value.
+    ///
+    /// Returns 1 if the value was set, or 0 if it would grow the store past
+    /// [MAX_KV_BYTES].
+    fn set(
+        &mut self,
+        memory: GuestMemory<'_>,
+        key_ptr: u32,
+        key_len: u32,
+        val_ptr: u32,
+        val_len: u32,
+    ) -> Result<u32> {
+        let key = memory.get_str_owned(key_ptr, key_len)?;
+        let value = memory.get_slice(val_ptr, val_len)?.to_vec();
+
+        let old_size = match self.values.get(&key) {
+            Some(old) => key.len() + old.len(),
+            None => 0,
+        };
+
+        let size = self.size - old_size + key.len() + value.len();
+        if size > MAX_KV_BYTES {
+            return Ok(0);
+        }
+
+        self.size = size;
+        self.values.insert(key, value);
+        Ok(1)
+    }
+
+    /// Copies the value of a key into guest memory, writing at most `val_cap`
+    /// bytes.
+    ///
+    /// Returns the full length of the value, or `u32::MAX` if the key is not
+    /// set. A capacity of zero retrieves the length without copying anything.
+    fn get(
+        &self,
+        memory: GuestMemory<'_>,
+        key_ptr: u32,
+        key_len: u32,
+        val_ptr: u32,
+        val_cap: u32,
+    ) -> Result<u32> {
+        let key = memory.get_str_owned(key_ptr, key_len)?;
+
+        let Some(value) = self.values.get(&key) else {
+            return Ok(u32::MAX);
+        };
+
+        let len = value.len().min(val_cap as usize);
+        memory.write_slice(val_ptr, &value[..len])?;
+        Ok(value.len() as u32)
+    }
+
+    /// Removes a key and its value. Returns 1 if the key was set and 0
+    /// otherwise.
+    fn delete(&mut self, memory: GuestMemory<'_>, key_ptr: u32, key_len: u32) -> Result<u32> {
+        let key = memory.get_str(key_ptr, key_len)?;
+
+        let Some(value) = self.values.remove(&*key) else {
+            return Ok(0);
+        };
+
+        self.size -= key.len() + value.len();
+        Ok(1)
+    }
+}
+
 /// A script-local lump stored in [LumpAbi].
 #[derive(Debug)]
 pub struct LocalLump {
     pub id: LumpId,
     pub bytes: Bytes,
+
+    /// Keeps the lump in the store while this process has it loaded.
+    pub pin: LumpPin,
 }
 
 /// Implements the `hearth::lump` ABI module.
@@ -194,7 +376,11 @@ pub struct LocalLump {
 pub struct LumpAbi {
     pub lump_store: Arc<LumpStoreImpl>,
     pub lump_handles: Slab<LocalLump>,
+    pub lump_streams: Slab<BytesMut>,
     pub this_lump: LumpId,
+
+    /// Keeps this process's module in the store while it runs.
+    pub this_pin: LumpPin,
 }
 
 #[impl_wasm_linker(module = "hearth::lump")]
@@ -216,18 +402,71 @@ impl LumpAbi {
         let bytes = self
             .lump_store
             .get_lump(&id)
-            .await
+            .await?
             .ok_or_else(|| anyhow!("couldn't find {:?} in lump store", id))?;
-        Ok(self.lump_handles.insert(LocalLump { id, bytes }) as u32)
+        Ok(self.insert_lump(id, bytes))
+    }
+
+    /// Returns 1 if a lump with the [LumpId] at the given pointer is in the
+    /// lump store and 0 otherwise.
+    ///
+    /// Unlike [LumpAbi::load_by_id], this never fails on a missing lump and
+    /// does not allocate a handle.
+    async fn exists(&self, memory: GuestMemory<'_>, id_ptr: u32) -> Result<u32> {
+        let id: LumpId = *memory.get_memory_ref(id_ptr)?;
+        Ok(self.lump_store.has_lump(&id).await as u32)
     }
 
     /// Loads a lump from guest memory.
     async fn load(&mut self, memory: GuestMemory<'_>, data_ptr: u32, data_len: u32) -> Result<u32> {
         let bytes: Bytes = memory.get_slice(data_ptr, data_len)?.to_vec().into();
         let id = self.lump_store.add_lump(bytes.clone()).await;
-        let lump = LocalLump { id, bytes };
-        let handle = self.lump_handles.insert(lump) as u32;
-        Ok(handle)
+        Ok(self.insert_lump(id, bytes))
+    }
+
+    /// Begins streaming a new lump from guest memory in chunks and returns a
+    /// handle to the stream.
+    fn begin_stream(&mut self) -> Result<u32> {
+        Ok(self.lump_streams.insert(BytesMut::new()) as u32)
+    }
+
+    /// Appends data from guest memory to a lump stream by handle.
+    fn write_stream(
+        &mut self,
+        memory: GuestMemory<'_>,
+        handle: u32,
+        data_ptr: u32,
+        data_len: u32,
+    ) -> Result<()> {
+        let data = memory.get_slice(data_ptr, data_len)?;
+        self.lump_streams
+            .get_mut(handle as usize)
+            .ok_or_else(|| anyhow!("lump stream handle {} is invalid", handle))?
+            .extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Finishes a lump stream by handle, loading its data as a lump.
+    ///
+    /// The lump is identical to one loaded with [Self::load] from the stream's
+    /// complete data. Returns a handle to the loaded lump.
+    async fn finish_stream(&mut self, handle: u32) -> Result<u32> {
+        let bytes = self
+            .lump_streams
+            .try_remove(handle as usize)
+            .ok_or_else(|| anyhow!("lump stream handle {} is invalid", handle))?
+            .freeze();
+
+        let id = self.lump_store.add_lump(bytes.clone()).await;
+        Ok(self.insert_lump(id, bytes))
+    }
+
+    /// Discards an unfinished lump stream by handle.
+    fn abort_stream(&mut self, handle: u32) -> Result<()> {
+        self.lump_streams
+            .try_remove(handle as usize)
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("lump stream handle {} is invalid", handle))
     }
 
     /// Writes the [LumpId] of a loaded lump to guest memory via pointer.
@@ -249,10 +488,17 @@ impl LumpAbi {
     /// using [Self::get_len].
     fn get_data(&self, memory: GuestMemory<'_>, handle: u32, data_ptr: u32) -> Result<()> {
         let lump = self.get_lump(handle)?;
-        let data_len = lump.bytes.len() as u32;
-        let dst = memory.get_slice(data_ptr, data_len)?;
-        dst.copy_from_slice(&lump.bytes);
-        Ok(())
+        memory.write_slice(data_ptr, &lump.bytes)
+    }
+
+    /// Removes the lump with the [LumpId] at the given pointer from the lump
+    /// store to free its memory.
+    ///
+    /// Does nothing if any process still has the lump loaded or is running it
+    /// as its module. Returns 1 if the lump was removed and 0 otherwise.
+    async fn unload_store(&self, memory: GuestMemory<'_>, id_ptr: u32) -> Result<u32> {
+        let id: LumpId = *memory.get_memory_ref(id_ptr)?;
+        Ok(self.lump_store.remove_lump(&id).await as u32)
     }
 
     /// Unloads a lump by handle.
@@ -266,13 +512,25 @@ impl LumpAbi {
 
 impl LumpAbi {
     pub fn new(runtime: &Runtime, this_lump: LumpId) -> Self {
+        Self::with_store(runtime.lump_store.clone(), this_lump)
+    }
+
+    fn with_store(lump_store: Arc<LumpStoreImpl>, this_lump: LumpId) -> Self {
         Self {
-            lump_store: runtime.lump_store.clone(),
+            this_pin: lump_store.pin(this_lump),
+            lump_store,
             lump_handles: Default::default(),
+            lump_streams: Default::default(),
             this_lump,
         }
     }
 
+    /// Helper function to pin a lump and insert it as a new handle.
+    fn insert_lump(&mut self, id: LumpId, bytes: Bytes) -> u32 {
+        let pin = self.lump_store.pin(id);
+        self.lump_handles.insert(LocalLump { id, bytes, pin }) as u32
+    }
+
     /// Helper function to get a lump reference from a handle.
     fn get_lump(&self, handle: u32) -> Result<&LocalLump> {
         self.lump_handles
@@ -284,6 +542,12 @@ impl LumpAbi {
 /// Implements the `hearth::table` ABI module.
 pub struct TableAbi {
     process: Arc<Process>,
+
+    /// The maximum number of capabilities allowed in a sent message.
+    max_caps: usize,
+
+    /// The controls of every running Wasm process, to send shutdown requests.
+    controls: Arc<ProcessControls>,
 }
 
 impl AsRef<Table> for TableAbi {
@@ -292,6 +556,21 @@ impl AsRef<Table> for TableAbi {
     }
 }
 
+impl TableAbi {
+    /// Helper function to fail if a message has too many capabilities.
+    fn check_caps_len(&self, caps_len: u32) -> Result<()> {
+        if caps_len as usize > self.max_caps {
+            bail!(
+                "message has {} capabilities attached but at most {} are allowed",
+                caps_len,
+                self.max_caps
+            );
+        }
+
+        Ok(())
+    }
+}
+
 #[impl_wasm_linker(module = "hearth::table")]
 impl TableAbi {
     /// Increments the reference count of this capability.
@@ -303,9 +582,7 @@ impl TableAbi {
     /// and inform the guest that incoming capabilities from messages or down
     /// signals are identical because of their shared handle.
     fn inc_ref(&self, handle: u32) -> Result<()> {
-        self.as_ref()
-            .inc_ref(CapabilityHandle(handle as usize))
-            .with_context(|| format!("inc_ref({handle})"))?;
+        self.as_ref().inc_ref(CapabilityHandle(handle as usize))?;
 
         Ok(())
     }
@@ -315,9 +592,7 @@ impl TableAbi {
     /// If the reference count of this handle drops to 0, it will be removed
     /// from the table.
     fn dec_ref(&self, handle: u32) -> Result<()> {
-        self.as_ref()
-            .dec_ref(CapabilityHandle(handle as usize))
-            .with_context(|| format!("dec_ref({handle})"))?;
+        self.as_ref().dec_ref(CapabilityHandle(handle as usize))?;
 
         Ok(())
     }
@@ -326,8 +601,7 @@ impl TableAbi {
     fn get_permissions(&self, handle: u32) -> Result<u32> {
         let perms = self
             .as_ref()
-            .get_permissions(CapabilityHandle(handle as usize))
-            .with_context(|| format!("get_permissions({handle})"))?;
+            .get_permissions(CapabilityHandle(handle as usize))?;
 
         Ok(perms.bits())
     }
@@ -335,25 +609,60 @@ impl TableAbi {
     /// Create a new capability from an existing one with a subset of the
     /// original's permissions.
     ///
+    /// If the desired permissions are the same as the original's, the
+    /// original handle's reference count is incremented and it is returned
+    /// as-is instead of creating a new table entry.
+    ///
     /// Fails if the desired permissions are not a subset of the original's.
     fn demote(&self, handle: u32, perms: u32) -> Result<u32> {
         let perms = Permissions::from_bits(perms).context("unknown permission bits set")?;
+        let table = self.as_ref();
+        let handle = CapabilityHandle(handle as usize);
 
-        let handle = self
-            .as_ref()
-            .demote(CapabilityHandle(handle as usize), perms)
-            .with_context(|| format!("demote({handle})"))?;
+        if table.get_permissions(handle)? == perms {
+            table.inc_ref(handle)?;
+            return Ok(handle.0.try_into().unwrap());
+        }
+
+        let handle = table.demote(handle, perms)?;
 
         Ok(handle.0.try_into().unwrap())
     }
 
+    /// Returns 1 if two capabilities are to the same route, regardless of
+    /// their permissions, and 0 otherwise.
+    ///
+    /// Demotes both capabilities to no permissions, which the table resolves
+    /// to the same handle if and only if they share a route.
+    fn same_route(&self, a: u32, b: u32) -> Result<u32> {
+        let table = self.as_ref();
+        let demote =
+            |handle: u32| table.demote(CapabilityHandle(handle as usize), Permissions::empty());
+
+        let a_route = demote(a)?;
+        let b_route = demote(b)?;
+        let same = a_route.0 == b_route.0;
+
+        table
+            .dec_ref(a_route)
+            .context("freeing demoted capability")?;
+        table
+            .dec_ref(b_route)
+            .context("freeing demoted capability")?;
+
+        Ok(same as u32)
+    }
+
     /// Sends a message to a capability's route.
     ///
     /// `data_ptr` and `data_len` comprise a byte vector that is sent in the
     /// data payload of the message. `caps_ptr` and `caps_len` point to an
     /// array of `u32`-sized capability handles to be sent in the message.
     ///
-    /// Fails if the capability does not have the send permission.
+    /// Fails if the capability does not have the send permission or if more
+    /// capabilities are attached than the runtime's
+    /// [max_message_caps][hearth_runtime::runtime::RuntimeConfig::max_message_caps]
+    /// allows.
     async fn send(
         &self,
         memory: GuestMemory<'_>,
@@ -363,6 +672,7 @@ impl TableAbi {
         caps_ptr: u32,
         caps_len: u32,
     ) -> Result<()> {
+        self.check_caps_len(caps_len)?;
         let data = memory.get_slice(data_ptr, data_len)?;
         let caps = memory.get_memory_slice::<u32>(caps_ptr, caps_len)?;
         let caps: Vec<_> = caps
@@ -372,8 +682,99 @@ impl TableAbi {
         self.process
             .borrow_table()
             .send(CapabilityHandle(handle as usize), data, &caps)
-            .await
-            .with_context(|| format!("send({handle})"))?;
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sends the same message to multiple capabilities' routes.
+    ///
+    /// `handles_ptr` and `handles_len` point to an array of `u32`-sized
+    /// capability handles to send to. The message is formed like in
+    /// [TableAbi::send] and is delivered to each target in array order.
+    ///
+    /// Fails without sending anything if any target does not have the send
+    /// permission or if too many capabilities are attached, like in
+    /// [TableAbi::send].
+    #[allow(clippy::too_many_arguments)]
+    async fn send_many(
+        &self,
+        memory: GuestMemory<'_>,
+        handles_ptr: u32,
+        handles_len: u32,
+        data_ptr: u32,
+        data_len: u32,
+        caps_ptr: u32,
+        caps_len: u32,
+    ) -> Result<()> {
+        self.check_caps_len(caps_len)?;
+        let handles = memory.get_memory_slice::<u32>(handles_ptr, handles_len)?;
+        let data = memory.get_slice(data_ptr, data_len)?;
+        let caps = memory.get_memory_slice::<u32>(caps_ptr, caps_len)?;
+        let caps: Vec<_> = caps
+            .iter()
+            .map(|cap| CapabilityHandle(*cap as usize))
+            .collect();
+
+        let table = self.process.borrow_table();
+
+        for handle in handles.iter() {
+            let perms = table
+                .get_permissions(CapabilityHandle(*handle as usize))
+                .with_context(|| format!("send_many({handle})"))?;
+
+            if !perms.contains(Permissions::SEND) {
+                bail!("send_many({handle}): capability does not have the send permission");
+            }
+        }
+
+        for handle in handles.iter() {
+            table
+                .send(CapabilityHandle(*handle as usize), data, &caps)
+                .await
+                .with_context(|| format!("send_many({handle})"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Asks a capability's process to shut down gracefully.
+    ///
+    /// Wasm processes receive a shutdown signal on their parent mailbox, and
+    /// if the process is still alive after a timeout, it is killed. Other
+    /// processes can't receive shutdown signals, so they're killed right away.
+    ///
+    /// Fails if the capability does not have the kill permission.
+    fn shutdown(&self, handle: u32) -> Result<()> {
+        let table = self.process.borrow_table();
+        let cap = CapabilityHandle(handle as usize);
+
+        let perms = table.get_permissions(cap)?;
+
+        if !perms.contains(Permissions::KILL) {
+            bail!("capability does not have the kill permission");
+        }
+
+        // the wrapped capability releases this reference when it's dropped
+        table.inc_ref(cap)?;
+        let target = table.wrap_handle(cap).context("invalid handle")?;
+
+        let Some(control) = self.controls.get(target) else {
+            table.kill(cap)?;
+            return Ok(());
+        };
+
+        control.request_shutdown();
+
+        // hold a reference to the capability until the timeout has passed
+        table.inc_ref(cap).unwrap();
+        let process = self.process.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(SHUTDOWN_TIMEOUT).await;
+            let table = process.borrow_table();
+            let _ = table.kill(cap);
+            let _ = table.dec_ref(cap);
+        });
 
         Ok(())
     }
@@ -382,18 +783,21 @@ impl TableAbi {
     ///
     /// Fails if the capability does not have the kill permission.
     fn kill(&self, handle: u32) -> Result<()> {
-        self.as_ref()
-            .kill(CapabilityHandle(handle as usize))
-            .with_context(|| format!("kill({handle})"))?;
+        self.as_ref().kill(CapabilityHandle(handle as usize))?;
 
         Ok(())
     }
 }
 
+/// How long a process has to exit after [TableAbi::shutdown] before it's
+/// killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// A form of signal mapped to a process's table.
 enum Signal {
-    Down { handle: u32 },
+    Down { handle: u32, tag: u64 },
     Message { data: Vec<u8>, caps: Vec<u32> },
+    Shutdown,
 }
 
 impl<'a> From<TableSignal<'a>> for Signal {
@@ -402,6 +806,8 @@ impl<'a> From<TableSignal<'a>> for Signal {
             TableSignal::Down { handle } => Signal::Down {
                 // TODO impl into for handles?
                 handle: handle.0 as u32,
+                // tags are resolved by the mailbox ABI on receipt
+                tag: 0,
             },
             TableSignal::Message { data, caps } => Signal::Message {
                 data: data.to_vec(),
@@ -411,6 +817,16 @@ impl<'a> From<TableSignal<'a>> for Signal {
     }
 }
 
+impl From<&Signal> for SignalSnapshot {
+    fn from(signal: &Signal) -> Self {
+        match signal {
+            Signal::Down { .. } => SignalSnapshot::Down,
+            Signal::Message { data, .. } => SignalSnapshot::Message { data: data.clone() },
+            Signal::Shutdown => SignalSnapshot::Shutdown,
+        }
+    }
+}
+
 /// A data structure to contain a dynamically-allocated slab of mailboxes.
 struct MailboxArena<'a> {
     group: &'a MailboxGroup<'a>,
@@ -432,12 +848,38 @@ impl<'a> MailboxArena<'a> {
     }
 }
 
+/// Returned by the mailbox ABI in place of a signal handle when the process
+/// has been killed and its mailboxes can no longer receive signals.
+const KILLED_SIGNAL: u32 = u32::MAX - 1;
+
 /// Implements the `hearth::mailbox` ABI module.
 #[ouroboros::self_referencing]
 pub struct MailboxAbi {
     process: Arc<Process>,
+
+    /// This process's control, which receives its shutdown requests.
+    control: Arc<ProcessControl>,
+
     signals: Slab<Signal>,
 
+    /// Tags of monitored capabilities, keyed by the mailbox monitoring them
+    /// and the handle of the capability demoted to no permissions.
+    ///
+    /// Each entry holds a reference to its demoted capability so that the
+    /// handle isn't reused until the down signal is received.
+    tags: HashMap<(u32, u32), u64>,
+
+    /// Linked capabilities, keyed like `tags`.
+    ///
+    /// Each entry holds a reference to both its demoted capability and the
+    /// original capability, which is killed when this process exits.
+    links: HashMap<(u32, u32), u32>,
+
+    /// Signals drained from each mailbox's queue for a snapshot, by mailbox
+    /// handle. They're received by the guest before any signals still queued
+    /// in the mailbox.
+    queued: HashMap<u32, VecDeque<Signal>>,
+
     #[borrows(process)]
     #[covariant]
     arena: MailboxArena<'this>,
@@ -463,6 +905,41 @@ impl MailboxAbi {
                 .context("invalid handle")
         })?;
 
+        // free the capabilities of messages drained from this mailbox
+        let process = self.borrow_process().clone();
+        let table = process.borrow_table();
+        let queued = self.with_queued_mut(|queued| queued.remove(&handle));
+        for signal in queued.into_iter().flatten() {
+            if let Signal::Message { caps, .. } = signal {
+                for cap in caps {
+                    let _ = table.dec_ref(CapabilityHandle(cap as usize));
+                }
+            }
+        }
+
+        // free the tags of capabilities that this mailbox was monitoring
+        self.with_tags_mut(|tags| {
+            tags.retain(|(mailbox, route), _| {
+                if *mailbox == handle {
+                    let _ = table.dec_ref(CapabilityHandle(*route as usize));
+                }
+
+                *mailbox != handle
+            })
+        });
+
+        // unlink the capabilities that this mailbox was linked to
+        self.with_links_mut(|links| {
+            links.retain(|(mailbox, route), cap| {
+                if *mailbox == handle {
+                    let _ = table.dec_ref(CapabilityHandle(*route as usize));
+                    let _ = table.dec_ref(CapabilityHandle(*cap as usize));
+                }
+
+                *mailbox != handle
+            })
+        });
+
         Ok(())
     }
 
@@ -475,54 +952,219 @@ impl MailboxAbi {
         Ok(cap.into_handle().0.try_into().unwrap())
     }
 
+    /// Make a capability in this process's table to this process's parent
+    /// mailbox with the given permissions.
+    fn make_self_capability(&self, perms: u32) -> Result<u32> {
+        let perms = Permissions::from_bits(perms).context("unknown permission bits set")?;
+        let cap = self.borrow_process().borrow_parent().export(perms).unwrap();
+        Ok(cap.into_handle().0.try_into().unwrap())
+    }
+
     /// Monitors a capability by its handle in this process's table. When the
     /// capability is closed, the mailbox will receive a down signal.
     fn monitor(&self, mailbox: u32, cap: u32) -> Result<()> {
         let cap = CapabilityHandle(cap as usize);
         let mb = self.get_mb(mailbox)?;
 
-        self.borrow_process()
-            .borrow_table()
-            .monitor(cap, mb)
-            .with_context(|| format!("monitor(mailbox = {}, cap = {})", mailbox, cap.0))?;
+        self.borrow_process().borrow_table().monitor(cap, mb)?;
+
+        Ok(())
+    }
+
+    /// Monitors a capability like [MailboxAbi::monitor], but attaches a tag
+    /// to the down signal that the mailbox receives when it's closed.
+    ///
+    /// The tag can be retrieved with [MailboxAbi::get_down_tag]. Monitoring
+    /// the same route with the same mailbox again replaces its tag.
+    fn monitor_tagged(&mut self, mailbox: u32, cap: u32, tag: u64) -> Result<()> {
+        self.monitor(mailbox, cap)?;
+
+        let process = self.borrow_process().clone();
+        let table = process.borrow_table();
+        let route = table.demote(CapabilityHandle(cap as usize), Permissions::empty())?;
+
+        let route: u32 = route.0.try_into().unwrap();
+        let old = self.with_tags_mut(|tags| tags.insert((mailbox, route), tag));
+
+        // only hold one reference per entry
+        if old.is_some() {
+            table
+                .dec_ref(CapabilityHandle(route as usize))
+                .context("freeing demoted capability")?;
+        }
+
+        Ok(())
+    }
+
+    /// Links this process to a capability's route group, so that when either
+    /// one exits, the other is killed.
+    ///
+    /// The mailbox monitors the capability like [MailboxAbi::monitor]. When
+    /// this process exits, the capability is killed, and when the mailbox
+    /// receives the capability's down signal, receiving it fails and this
+    /// process is killed. If both processes exit at the same time, killing
+    /// the other fails harmlessly because it's already dead, so links never
+    /// cascade in a cycle.
+    ///
+    /// Fails if the capability does not have both the monitor and kill
+    /// permissions.
+    fn link(&mut self, mailbox: u32, cap: u32) -> Result<()> {
+        let process = self.borrow_process().clone();
+        let table = process.borrow_table();
+        let handle = CapabilityHandle(cap as usize);
+
+        let perms = table.get_permissions(handle)?;
+        let required = Permissions::MONITOR | Permissions::KILL;
+        if !perms.contains(required) {
+            bail!("capability needs the monitor and kill permissions");
+        }
+
+        self.monitor(mailbox, cap)?;
+
+        let route = table.demote(handle, Permissions::empty())?;
+        table.inc_ref(handle)?;
+
+        let route: u32 = route.0.try_into().unwrap();
+        let old = self.with_links_mut(|links| links.insert((mailbox, route), cap));
+
+        // only hold one reference per entry
+        if let Some(old) = old {
+            table
+                .dec_ref(CapabilityHandle(route as usize))
+                .context("freeing demoted capability")?;
+            table
+                .dec_ref(CapabilityHandle(old as usize))
+                .context("freeing linked capability")?;
+        }
 
         Ok(())
     }
 
     /// Waits for a signal to be received by a mailbox.
+    ///
+    /// Returns `u32::MAX - 1` if this process has been killed. Otherwise,
+    /// returns the handle to the received signal.
     async fn recv(&mut self, handle: u32) -> Result<u32> {
-        let mb = self.get_mb(handle)?;
+        if let Some(signal) = self.take_queued(handle)? {
+            return self.insert_signal(handle, signal);
+        }
 
-        let signal = mb
-            .recv(|signal| Signal::from(signal))
-            .await
-            .context("process has been killed")?;
+        let Some(signal) = self.recv_signal(handle).await? else {
+            return Ok(KILLED_SIGNAL);
+        };
 
-        let handle = self.with_signals_mut(|signals| signals.insert(signal));
+        self.insert_signal(handle, signal)
+    }
 
-        Ok(handle.try_into().unwrap())
+    /// Waits for a signal to be received by a mailbox for at most
+    /// `timeout_us` microseconds.
+    ///
+    /// Returns `u32::MAX` (or `0xFFFFFFFF`) if no signal was received before
+    /// the timeout and `u32::MAX - 1` if this process has been killed.
+    /// Otherwise, returns the handle to the received signal.
+    async fn recv_timeout(&mut self, handle: u32, timeout_us: u64) -> Result<u32> {
+        if let Some(signal) = self.take_queued(handle)? {
+            return self.insert_signal(handle, signal);
+        }
+
+        let timeout = Duration::from_micros(timeout_us);
+        let recv = self.recv_signal(handle);
+
+        match tokio::time::timeout(timeout, recv).await {
+            Ok(signal) => match signal? {
+                Some(signal) => self.insert_signal(handle, signal),
+                None => Ok(KILLED_SIGNAL),
+            },
+            Err(_) => Ok(u32::MAX),
+        }
     }
 
     /// Checks if a mailbox has received any signals without waiting.
     ///
-    /// Returns `u32::MAX` (or `0xFFFFFFFF`) if the mailbox's queue is empty.
-    /// Otherwise, returns the handle to the received signal.
+    /// Returns `u32::MAX` (or `0xFFFFFFFF`) if the mailbox's queue is empty
+    /// and `u32::MAX - 1` if this process has been killed. Otherwise, returns
+    /// the handle to the received signal.
     fn try_recv(&mut self, handle: u32) -> Result<u32> {
+        if let Some(signal) = self.take_queued(handle)? {
+            return self.insert_signal(handle, signal);
+        }
+
         let mb = self.get_mb(handle)?;
 
-        let signal = mb
+        if self.take_shutdown(handle) {
+            return self.insert_signal(handle, Signal::Shutdown);
+        }
+
+        let Ok(signal) = mb
             .try_recv(|signal| Signal::from(signal))
-            .context("process has been killed")?;
+            .context("process has been killed")
+        else {
+            return Ok(KILLED_SIGNAL);
+        };
 
         match signal {
-            Some(signal) => {
-                let handle = self.with_signals_mut(|signals| signals.insert(signal));
-                Ok(handle.try_into().unwrap())
-            }
+            Some(signal) => self.insert_signal(handle, signal),
             None => Ok(u32::MAX),
         }
     }
 
+    /// Receives up to `max` signals that a mailbox has already received
+    /// without waiting.
+    ///
+    /// `dst_ptr` points to an array of `max` `u32`-sized signal handles in
+    /// guest memory, which is filled with the handles of the received signals
+    /// in the order that they were received.
+    ///
+    /// Returns the number of received signals, or `u32::MAX - 1` if this
+    /// process has been killed before any signals were received.
+    fn recv_many(
+        &mut self,
+        memory: GuestMemory<'_>,
+        mailbox: u32,
+        dst_ptr: u32,
+        max: u32,
+    ) -> Result<u32> {
+        let dst = memory.get_memory_slice::<u32>(dst_ptr, max)?;
+        let mut count = 0;
+
+        while count < dst.len() {
+            if let Some(signal) = self.take_queued(mailbox)? {
+                dst[count] = self.insert_signal(mailbox, signal)?;
+                count += 1;
+                continue;
+            }
+
+            let mb = self.get_mb(mailbox)?;
+
+            if self.take_shutdown(mailbox) {
+                dst[count] = self.insert_signal(mailbox, Signal::Shutdown)?;
+                count += 1;
+                continue;
+            }
+
+            let Ok(signal) = mb
+                .try_recv(|signal| Signal::from(signal))
+                .context("process has been killed")
+            else {
+                // report the signals that were received before being killed
+                if count == 0 {
+                    return Ok(KILLED_SIGNAL);
+                }
+
+                break;
+            };
+
+            let Some(signal) = signal else {
+                break;
+            };
+
+            dst[count] = self.insert_signal(mailbox, signal)?;
+            count += 1;
+        }
+
+        Ok(count.try_into().unwrap())
+    }
+
     /// Waits for one of multiple mailboxes to receive a signal.
     ///
     /// `handles_ptr` and `handles_len` point to an array of `u32`-sized
@@ -540,17 +1182,24 @@ impl MailboxAbi {
     ) -> Result<u64> {
         let handles = memory.get_memory_slice(handles_ptr, handles_len)?;
 
+        for (index, mailbox) in handles.iter().enumerate() {
+            if let Some(signal) = self.take_queued(*mailbox)? {
+                let handle = self.insert_signal(*mailbox, signal)?;
+                return Ok(((index as u64) << 32) | (handle as u64));
+            }
+        }
+
         let mbs = handles
             .iter()
-            .map(|handle| self.get_mb(*handle))
+            .map(|handle| self.get_mb(*handle).map(|_| *handle))
             .collect::<Result<Vec<_>>>()?
             .into_iter()
-            .map(|mb| mb.recv(|signal| Signal::from(signal)))
+            .map(|handle| self.recv_signal(handle))
             .map(Box::pin);
 
         let (signal, index, _) = futures_util::future::select_all(mbs).await;
-        let signal = signal.context("process has been killed")?;
-        let handle = self.with_signals_mut(|signals| signals.insert(signal));
+        let signal = signal?.context("process has been killed")?;
+        let handle = self.insert_signal(handles[index], signal)?;
         let result = ((index as u64) << 32) | (handle as u64);
         Ok(result)
     }
@@ -571,6 +1220,7 @@ impl MailboxAbi {
         let kind = match signal {
             Signal::Down { .. } => SignalKind::Down,
             Signal::Message { .. } => SignalKind::Message,
+            Signal::Shutdown => SignalKind::Shutdown,
         };
 
         Ok(kind.into())
@@ -582,13 +1232,41 @@ impl MailboxAbi {
     fn get_down_capability(&self, handle: u32) -> Result<u32> {
         let signal = self.get_signal(handle)?;
 
-        let Signal::Down { handle } = signal else {
+        let Signal::Down { handle, .. } = signal else {
             bail!("invalid signal kind");
         };
 
         Ok(*handle)
     }
 
+    /// Gets the tag of a down signal.
+    ///
+    /// The tag is zero if the capability was monitored without a tag. Fails
+    /// if the given signal is not a down signal.
+    fn get_down_tag(&self, handle: u32) -> Result<u64> {
+        let signal = self.get_signal(handle)?;
+
+        let Signal::Down { tag, .. } = signal else {
+            bail!("invalid signal kind");
+        };
+
+        Ok(*tag)
+    }
+
+    /// Gets both the length of the data and the length of the capability
+    /// list in a message signal in a single call.
+    ///
+    /// The data length is packed in the high 32 bits of the result and the
+    /// capability count is packed in the low 32 bits.
+    ///
+    /// Fails if the given signal is not a message signal.
+    fn get_message_header(&self, handle: u32) -> Result<u64> {
+        let (data, caps) = self.get_message(handle)?;
+        let data_len: u32 = data.len().try_into().unwrap();
+        let caps_num: u32 = caps.len().try_into().unwrap();
+        Ok(((data_len as u64) << 32) | caps_num as u64)
+    }
+
     /// Gets the length of the data in a message signal.
     ///
     /// Fails if the given signal is not a message signal.
@@ -605,10 +1283,7 @@ impl MailboxAbi {
     /// Fails if the given signal is not a message signal.
     fn get_message_data(&self, memory: GuestMemory<'_>, handle: u32, dst_ptr: u32) -> Result<()> {
         let (data, _caps) = self.get_message(handle)?;
-        let dst_len = data.len().try_into().unwrap();
-        let dst = memory.get_memory_slice(dst_ptr, dst_len)?;
-        dst.copy_from_slice(data);
-        Ok(())
+        memory.write_slice(dst_ptr, data)
     }
 
     /// Gets the length of the capability list in a message signal.
@@ -647,6 +1322,93 @@ impl MailboxAbi {
         }
     }
 
+    /// Helper function to wait for a mailbox to receive a signal. Returns
+    /// `None` if this process has been killed.
+    ///
+    /// The parent mailbox also receives this process's shutdown requests.
+    /// They're set in its [ProcessControl] instead of being sent as messages
+    /// so that other processes can't forge them.
+    async fn recv_signal(&self, handle: u32) -> Result<Option<Signal>> {
+        let recv = self.get_mb(handle)?.recv(|signal| Signal::from(signal));
+
+        if handle != 0 {
+            return Ok(recv.await);
+        }
+
+        let control = self.borrow_control();
+        Ok(tokio::select! {
+            biased;
+            _ = control.wait_shutdown() => Some(Signal::Shutdown),
+            signal = recv => signal,
+        })
+    }
+
+    /// Helper function to take the oldest signal drained from a mailbox for a
+    /// snapshot, if any.
+    ///
+    /// Fails if the handle is invalid.
+    fn take_queued(&mut self, handle: u32) -> Result<Option<Signal>> {
+        self.get_mb(handle)?;
+
+        Ok(self.with_queued_mut(|queued| {
+            let queue = queued.get_mut(&handle)?;
+            let signal = queue.pop_front();
+
+            if queue.is_empty() {
+                queued.remove(&handle);
+            }
+
+            signal
+        }))
+    }
+
+    /// Helper function to take a pending shutdown request if a mailbox is the
+    /// parent mailbox.
+    fn take_shutdown(&self, handle: u32) -> bool {
+        handle == 0 && self.borrow_control().take_shutdown()
+    }
+
+    /// Helper function to store a signal received by a mailbox and return its
+    /// handle, resolving the tag of down signals.
+    fn insert_signal(&mut self, mailbox: u32, mut signal: Signal) -> Result<u32> {
+        if let Signal::Down { handle, tag } = &mut signal {
+            if let Some(found) = self.with_tags_mut(|tags| tags.remove(&(mailbox, *handle))) {
+                *tag = found;
+
+                self.borrow_process()
+                    .borrow_table()
+                    .dec_ref(CapabilityHandle(*handle as usize))
+                    .context("freeing demoted capability")?;
+            }
+
+            if let Some(cap) = self.with_links_mut(|links| links.remove(&(mailbox, *handle))) {
+                let process = self.borrow_process().clone();
+                let table = process.borrow_table();
+
+                table
+                    .dec_ref(CapabilityHandle(*handle as usize))
+                    .context("freeing demoted capability")?;
+                table
+                    .dec_ref(CapabilityHandle(cap as usize))
+                    .context("freeing linked capability")?;
+
+                bail!("linked process exited");
+            }
+        }
+
+        let handle = self.with_signals_mut(|signals| signals.insert(signal));
+        Ok(handle.try_into().unwrap())
+    }
+
+    /// Helper function to kill every capability linked to this process.
+    fn kill_links(&self) {
+        let table = self.borrow_process().borrow_table();
+        for cap in self.borrow_links().values() {
+            // the linked process may have already exited
+            let _ = table.kill(CapabilityHandle(*cap as usize));
+        }
+    }
+
     /// Helper function to get a reference to a signal by its handle.
     ///
     /// Fails if the handle is invalid.
@@ -655,6 +1417,62 @@ impl MailboxAbi {
             .context("invalid handle")
     }
 
+    /// Helper function to capture all received signals by handle.
+    fn snapshot_signals(&self) -> Vec<(u32, SignalSnapshot)> {
+        self.with_signals(|signals| {
+            signals
+                .iter()
+                .map(|(handle, signal)| (handle.try_into().unwrap(), signal.into()))
+                .collect()
+        })
+    }
+
+    /// Helper function to capture the signals queued in each mailbox that
+    /// the guest hasn't received yet, by mailbox handle.
+    ///
+    /// Flue can't peek at a mailbox's queue, so this drains each queue into
+    /// [MailboxAbi::queued], which the guest receives from first. A pending
+    /// shutdown request is captured after them without being taken.
+    fn snapshot_queued(&mut self) -> Vec<(u32, Vec<SignalSnapshot>)> {
+        let mut handles = vec![0];
+        self.with_arena(|arena| {
+            handles.extend(arena.mbs.iter().map(|(index, _)| index as u32 + 1));
+        });
+
+        for handle in handles.iter().copied() {
+            let mb = self.get_mb(handle).unwrap();
+
+            // a killed process's mailboxes have nothing left to drain
+            let mut drained = Vec::new();
+            while let Ok(Some(signal)) = mb.try_recv(|signal| Signal::from(signal)) {
+                drained.push(signal);
+            }
+
+            if !drained.is_empty() {
+                self.with_queued_mut(|queued| queued.entry(handle).or_default().extend(drained));
+            }
+        }
+
+        let shutdown = self.borrow_control().shutdown.load(Ordering::SeqCst);
+
+        handles
+            .into_iter()
+            .filter_map(|handle| {
+                let mut signals: Vec<_> = self
+                    .borrow_queued()
+                    .get(&handle)
+                    .map(|queue| queue.iter().map(SignalSnapshot::from).collect())
+                    .unwrap_or_default();
+
+                if handle == 0 && shutdown {
+                    signals.push(SignalSnapshot::Shutdown);
+                }
+
+                (!signals.is_empty()).then_some((handle, signals))
+            })
+            .collect()
+    }
+
     /// Helper function to get a message signal by its handle.
     ///
     /// Fails if the handle is invalid or if the signal is not a message.
@@ -722,6 +1540,21 @@ impl MetadataAbi {
         self.meta.license = Some(str.to_string());
         Ok(())
     }
+
+    fn set_version(&mut self, memory: GuestMemory<'_>, ptr: u32, len: u32) -> Result<()> {
+        let str = memory.get_str(ptr, len)?;
+        self.meta.version = Some(str.to_string());
+        Ok(())
+    }
+
+    /// Declares the version of the host ABI that the guest was built against.
+    ///
+    /// Processes that declare an ABI version other than [ABI_VERSION] are
+    /// refused by the spawner.
+    fn set_abi_version(&mut self, version: u32) -> Result<()> {
+        self.meta.abi_version = Some(version);
+        Ok(())
+    }
 }
 
 /// Encapsulates an instance of each guest ABI data structure.
@@ -734,16 +1567,22 @@ pub enum ProcessData {
     ///
     /// Before the process is spawned into the runtime, it may export
     /// user-facing metadata through [MetadataAbi].
-    Metadata { metadata: MetadataAbi },
+    Metadata {
+        metadata: MetadataAbi,
+        limits: StoreLimits,
+    },
 
     /// The **running phase** of process execution.
     ///
     /// Provides full access to a process's ABIs post-spawn.
     Running {
         log: LogAbi,
+        random: RandomAbi,
+        kv: KvAbi,
         lump: LumpAbi,
         table: TableAbi,
         mailbox: MailboxAbi,
+        limits: StoreLimits,
     },
 }
 
@@ -751,7 +1590,7 @@ impl GetAbi<MetadataAbi> for ProcessData {
     fn get_abi(&mut self) -> Result<&mut MetadataAbi> {
         match self {
             Self::Running { .. } => bail!("process is running"),
-            Self::Metadata { metadata } => Ok(metadata),
+            Self::Metadata { metadata, .. } => Ok(metadata),
         }
     }
 }
@@ -770,38 +1609,71 @@ macro_rules! impl_running_get_abi {
 }
 
 impl_running_get_abi!(ProcessData, LogAbi, log);
+impl_running_get_abi!(ProcessData, RandomAbi, random);
+impl_running_get_abi!(ProcessData, KvAbi, kv);
 impl_running_get_abi!(ProcessData, LumpAbi, lump);
 impl_running_get_abi!(ProcessData, TableAbi, table);
 impl_running_get_abi!(ProcessData, MailboxAbi, mailbox);
 
 impl ProcessData {
-    pub fn new_metadata() -> Self {
+    pub fn new_metadata(limits: StoreLimits) -> Self {
         Self::Metadata {
             metadata: Default::default(),
+            limits,
         }
     }
 
-    pub fn new_running(runtime: &Runtime, process: Process, this_lump: LumpId) -> Self {
+    fn new_running(
+        runtime: &Runtime,
+        process: Process,
+        this_lump: LumpId,
+        limits: StoreLimits,
+        control: Arc<ProcessControl>,
+        controls: Arc<ProcessControls>,
+    ) -> Self {
         let process = Arc::new(process);
 
         Self::Running {
+            limits,
             log: LogAbi {
                 process: process.clone(),
             },
+            random: RandomAbi,
+            kv: KvAbi::default(),
             lump: LumpAbi::new(runtime, this_lump),
             table: TableAbi {
                 process: process.clone(),
+                max_caps: runtime.config.max_message_caps,
+                controls,
             },
-            mailbox: MailboxAbi::new(process, Slab::new(), |process| MailboxArena {
-                group: process.borrow_group(),
-                mbs: Slab::new(),
-            }),
+            mailbox: MailboxAbi::new(
+                process,
+                control,
+                Slab::new(),
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+                |process| MailboxArena {
+                    group: process.borrow_group(),
+                    mbs: Slab::new(),
+                },
+            ),
+        }
+    }
+
+    /// Gets the resource limits of this process's store.
+    pub fn limits_mut(&mut self) -> &mut StoreLimits {
+        match self {
+            Self::Metadata { limits, .. } => limits,
+            Self::Running { limits, .. } => limits,
         }
     }
 
     /// Adds all module ABIs to the given linker.
     pub fn add_to_linker(linker: &mut Linker<Self>) {
         LogAbi::add_to_linker(linker);
+        RandomAbi::add_to_linker(linker);
+        KvAbi::add_to_linker(linker);
         LumpAbi::add_to_linker(linker);
         TableAbi::add_to_linker(linker);
         MailboxAbi::add_to_linker(linker);
@@ -809,11 +1681,168 @@ impl ProcessData {
     }
 }
 
+/// State shared between a [WasmProcess] and the host services that control
+/// it, like the [WasmProcessController] and [TableAbi::shutdown].
+///
+/// A process is suspended from its epoch callback, which is the only place
+/// outside of the process's own execution that has access to its [Store]. The
+/// callback takes a snapshot of the process and yields, and from then on the
+/// process's execution future is not polled again until it is resumed.
+#[derive(Default)]
+struct ProcessControl {
+    /// Set while a suspension is requested.
+    suspend: AtomicBool,
+
+    /// The snapshot taken when the suspension took effect, if it has.
+    snapshot: Mutex<Option<WasmProcessSnapshot>>,
+
+    /// Wakes the process's execution future when it is resumed.
+    waker: AtomicWaker,
+
+    /// Set while a shutdown request has yet to be received by the process.
+    shutdown: AtomicBool,
+
+    /// Wakes the process's parent mailbox when a shutdown is requested.
+    shutdown_notify: Notify,
+}
+
+impl ProcessControl {
+    /// Requests that the process be suspended at its next epoch.
+    fn suspend(&self) {
+        self.suspend.store(true, Ordering::SeqCst);
+    }
+
+    /// Cancels a suspension and continues executing the process.
+    fn resume(&self) {
+        self.suspend.store(false, Ordering::SeqCst);
+        self.snapshot.lock().unwrap().take();
+        self.waker.wake();
+    }
+
+    /// Returns true if the suspension has taken effect.
+    fn is_suspended(&self) -> bool {
+        self.snapshot.lock().unwrap().is_some()
+    }
+
+    /// Gets the snapshot taken when this process was suspended.
+    fn get_snapshot(&self) -> Option<WasmProcessSnapshot> {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    /// Requests that the process shut down gracefully.
+    fn request_shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.shutdown_notify.notify_one();
+    }
+
+    /// Takes a pending shutdown request. Returns true if there was one.
+    fn take_shutdown(&self) -> bool {
+        self.shutdown.swap(false, Ordering::SeqCst)
+    }
+
+    /// Waits for and takes a shutdown request.
+    async fn wait_shutdown(&self) {
+        while !self.take_shutdown() {
+            self.shutdown_notify.notified().await;
+        }
+    }
+}
+
+/// The [ProcessControl] of each running Wasm process, looked up by
+/// capabilities to the processes.
+///
+/// Like [PubSub], each process is keyed by a capability to it with no
+/// permissions in this struct's own table, which is the same for every
+/// capability to the same process.
+struct ProcessControls {
+    table: Table,
+    controls: Mutex<HashMap<CapabilityHandle, Arc<ProcessControl>>>,
+}
+
+impl ProcessControls {
+    fn new(post: Arc<PostOffice>) -> Self {
+        Self {
+            table: Table::new(post),
+            controls: Default::default(),
+        }
+    }
+
+    /// Makes a process controllable by capabilities to it. Returns the key
+    /// to remove it with.
+    fn insert(&self, cap: CapabilityRef, control: Arc<ProcessControl>) -> CapabilityHandle {
+        let cap = self.table.import_ref(cap).unwrap();
+        let key = cap.demote(Permissions::empty()).unwrap().into_handle();
+        self.controls.lock().unwrap().insert(key, control);
+        key
+    }
+
+    /// Removes a process by the key returned from [ProcessControls::insert].
+    fn remove(&self, key: CapabilityHandle) {
+        if self.controls.lock().unwrap().remove(&key).is_some() {
+            self.table.dec_ref(key).unwrap();
+        }
+    }
+
+    /// Looks up a running process's control by a capability to it.
+    ///
+    /// Returns `None` if the capability does not have the kill permission,
+    /// so that only processes that may kill the target may control it.
+    fn get(&self, cap: CapabilityRef) -> Option<Arc<ProcessControl>> {
+        if !cap.get_permissions().contains(Permissions::KILL) {
+            return None;
+        }
+
+        let cap = self.table.import_ref(cap).unwrap();
+        let key = cap.demote(Permissions::empty()).unwrap().into_handle();
+        let control = self.controls.lock().unwrap().get(&key).cloned();
+        self.table.dec_ref(key).unwrap();
+        control
+    }
+}
+
+/// Resource limits applied to each Wasm process.
+///
+/// A process that exceeds its limits traps and is killed.
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessLimits {
+    /// The maximum size of each of the process's linear memories in bytes.
+    pub max_memory_bytes: usize,
+
+    /// The maximum number of elements in each of the process's tables.
+    pub max_table_elements: u32,
+}
+
+/// Per-process execution settings shared by all processes of a plugin.
+#[derive(Clone, Copy, Debug)]
+struct ProcessConfig {
+    /// The resource limits of each process, if any.
+    limits: Option<ProcessLimits>,
+
+    /// The number of epochs that a process runs for before it yields.
+    yield_epochs: u64,
+
+    /// If the engine consumes fuel, the amount of fuel that a process runs
+    /// for before it yields and is refilled.
+    fuel_slice: Option<u64>,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            limits: None,
+            yield_epochs: 1,
+            fuel_slice: None,
+        }
+    }
+}
+
 struct WasmProcess {
     store: Store<ProcessData>,
     exports_metadata: bool,
     instance: Instance,
     this_lump: LumpId,
+    control: Arc<ProcessControl>,
+    yield_epochs: u64,
 }
 
 impl WasmProcess {
@@ -822,9 +1851,27 @@ impl WasmProcess {
         linker: &Linker<ProcessData>,
         module: &Module,
         this_lump: LumpId,
+        config: ProcessConfig,
+        fuel_limit: Option<u64>,
     ) -> Result<Self> {
-        let data = ProcessData::new_metadata();
+        let limits = match config.limits {
+            Some(limits) => StoreLimitsBuilder::new()
+                .memory_size(limits.max_memory_bytes)
+                .table_elements(limits.max_table_elements)
+                .trap_on_grow_failure(true)
+                .build(),
+            None => StoreLimits::default(),
+        };
+
+        let data = ProcessData::new_metadata(limits);
         let mut store = Store::new(engine, data);
+        store.limiter(|data| data.limits_mut());
+
+        if let Some(slice) = config.fuel_slice {
+            Self::budget_fuel(&mut store, slice, fuel_limit)?;
+        } else if fuel_limit.is_some() {
+            bail!("fuel limits require a fuel-metered Wasm plugin");
+        }
 
         let instance = linker
             .instantiate_async(&mut store, module)
@@ -836,14 +1883,39 @@ impl WasmProcess {
             exports_metadata: false,
             instance,
             this_lump,
+            control: Default::default(),
+            yield_epochs: config.yield_epochs,
         })
     }
 
+    /// Sets up a store to yield and refill its fuel every `slice` units of
+    /// fuel, trapping once it has consumed `limit` units in total.
+    fn budget_fuel(store: &mut Store<ProcessData>, slice: u64, limit: Option<u64>) -> Result<()> {
+        let Some(limit) = limit else {
+            store.add_fuel(slice)?;
+            store.out_of_fuel_async_yield(u64::MAX, slice);
+            return Ok(());
+        };
+
+        if limit == 0 {
+            bail!("fuel limit must be non-zero");
+        }
+
+        // split the budget so that the initial fuel plus every refill adds
+        // up to exactly the limit
+        let refills = (limit - 1) / slice;
+        store.add_fuel(limit - refills * slice)?;
+        store.out_of_fuel_async_yield(refills, slice);
+
+        Ok(())
+    }
+
     /// Executes the process's `_hearth_metadata` function and returns the
     /// result.
     pub async fn get_metadata(&mut self) -> Result<ProcessMetadata> {
         // while retrieving the process metadata, preemptively timeslice
-        self.store.epoch_deadline_async_yield_and_update(1);
+        self.store
+            .epoch_deadline_async_yield_and_update(self.yield_epochs);
 
         // attempt to locate the `_hearth_metadata` export
         if let Ok(cb) = self
@@ -860,17 +1932,35 @@ impl WasmProcess {
         }
 
         // retrieve the written metadata from the store's process data
-        let ProcessData::Metadata { metadata } = self.store.data() else {
+        let ProcessData::Metadata { metadata, .. } = self.store.data() else {
             bail!("process metadata unavailable");
         };
 
+        // refuse guests built against an incompatible ABI
+        if let Some(version) = metadata.meta.abi_version {
+            if version != ABI_VERSION {
+                bail!(
+                    "guest was built against ABI version {}, but host provides version {}",
+                    version,
+                    ABI_VERSION
+                );
+            }
+        }
+
         Ok(metadata.meta.to_owned())
     }
 
     /// Executes a Wasm process.
-    async fn run(mut self, runtime: Arc<Runtime>, ctx: Process, entrypoint: Option<u32>) {
-        // grab the PID for logging
+    async fn run(
+        mut self,
+        runtime: Arc<Runtime>,
+        ctx: Process,
+        entrypoint: Option<u32>,
+        controls: Arc<ProcessControls>,
+    ) {
+        // grab the PID for logging and the priority for timeslicing
         let pid = ctx.borrow_info().pid;
+        let priority = ctx.borrow_info().meta.priority.unwrap_or(0);
 
         // log a warning if this process did not export its metadata
         if !self.exports_metadata {
@@ -880,10 +1970,23 @@ impl WasmProcess {
             );
         }
 
-        // switch the process ABIs to running
-        *self.store.data_mut() = ProcessData::new_running(runtime.as_ref(), ctx, self.this_lump);
-
-        // while executing the main function, preemptively timeslice until killed
+        // switch the process ABIs to running, keeping the process's limits
+        let limits = std::mem::take(self.store.data_mut().limits_mut());
+        *self.store.data_mut() = ProcessData::new_running(
+            runtime.as_ref(),
+            ctx,
+            self.this_lump,
+            limits,
+            self.control.clone(),
+            controls,
+        );
+
+        // while executing the main function, preemptively timeslice until
+        // killed, and take a snapshot when a suspension is requested
+        let control = self.control.clone();
+        let memory = self.instance.get_memory(&mut self.store, "memory");
+        let this_lump = self.this_lump;
+        let yield_epochs = self.yield_epochs * (priority.min(MAX_PRIORITY) as u64 + 1);
         self.store.epoch_deadline_callback(move |store| {
             let ProcessData::Running { table, .. } = store.data() else {
                 bail!("process is not running");
@@ -893,20 +1996,75 @@ impl WasmProcess {
                 bail!("process killed");
             }
 
-            Ok(UpdateDeadline::Yield(1))
+            if control.suspend.load(Ordering::SeqCst) && !control.is_suspended() {
+                let snapshot = take_snapshot(store, memory, this_lump, entrypoint);
+                *control.snapshot.lock().unwrap() = Some(snapshot);
+            }
+
+            Ok(UpdateDeadline::Yield(yield_epochs))
         });
 
-        // call inner execution behavior and handle its errors
-        match self
-            .run_inner(entrypoint)
-            .await
-            .with_context(|| format!("PID {}", pid))
-        {
+        // nothing is ever sent to this mailbox, so receiving on it only
+        // finishes once the process is killed
+        let ProcessData::Running { table, .. } = self.store.data() else {
+            unreachable!("process data was just switched to running");
+        };
+
+        let process = table.process.clone();
+        let watch = process
+            .borrow_group()
+            .create_mailbox()
+            .context("process has been killed");
+
+        let killed = async {
+            if let Ok(watch) = &watch {
+                watch.recv(|_| ()).await;
+            }
+        };
+
+        let result = {
+            // don't poll the process's execution while it is suspended
+            let control = self.control.clone();
+            let mut inner = std::pin::pin!(self.run_inner(entrypoint));
+            let running = poll_fn(|cx| {
+                if control.is_suspended() {
+                    control.waker.register(cx.waker());
+
+                    // check again in case we were resumed before registering
+                    if control.is_suspended() {
+                        return Poll::Pending;
+                    }
+                }
+
+                inner.as_mut().poll(cx)
+            });
+
+            // the epoch callback can't stop suspended processes, so stop
+            // waiting on them when they're killed
+            tokio::select! {
+                result = running => result,
+                _ = killed => Err(anyhow!("process killed")),
+            }
+        };
+
+        // name the guest function that trapped, if any
+        let result = result.map_err(|err| match describe_trap(&err) {
+            Some(trap) => err.context(trap),
+            None => err,
+        });
+
+        // handle inner execution errors
+        match result.with_context(|| format!("PID {}", pid)) {
             Ok(()) => {}
             Err(err) => {
                 error!("{:?}", err);
             }
         }
+
+        // take down every process linked to this one
+        if let ProcessData::Running { mailbox, .. } = self.store.data() {
+            mailbox.kill_links();
+        }
     }
 
     /// Performs the actual process execution using easy error handling.
@@ -947,40 +2105,205 @@ impl WasmProcess {
                 // call it with the specified entrypoint index
                 cb.call_async(&mut self.store, entrypoint)
                     .await
-                    .context("calling Wasm entrypoint")
+                    .with_context(|| format!("calling Wasm entrypoint {}", entrypoint))
             }
         }
     }
 }
 
+/// Describes the guest trap that caused an error, if any, with the name of
+/// the guest function that it occurred in when it's known.
+fn describe_trap(err: &hearth_runtime::anyhow::Error) -> Option<String> {
+    let trap = err.downcast_ref::<Trap>()?;
+
+    let func = err
+        .downcast_ref::<WasmBacktrace>()
+        .and_then(|backtrace| backtrace.frames().first())
+        .map(|frame| match frame.func_name() {
+            Some(name) => name.to_string(),
+            None => format!("<wasm function {}>", frame.func_index()),
+        });
+
+    Some(match func {
+        Some(func) => format!("guest trapped in {}: {}", func, trap),
+        None => format!("guest trapped: {}", trap),
+    })
+}
+
+/// Captures the state of a running process from within its epoch callback.
+fn take_snapshot(
+    mut store: StoreContextMut<ProcessData>,
+    memory: Option<Memory>,
+    lump: LumpId,
+    entrypoint: Option<u32>,
+) -> WasmProcessSnapshot {
+    let memory = memory
+        .map(|memory| memory.data(&store).to_vec())
+        .unwrap_or_default();
+
+    let (signals, queued) = match store.data_mut() {
+        ProcessData::Running { mailbox, .. } => {
+            (mailbox.snapshot_signals(), mailbox.snapshot_queued())
+        }
+        ProcessData::Metadata { .. } => (vec![], vec![]),
+    };
+
+    WasmProcessSnapshot {
+        lump,
+        entrypoint,
+        memory,
+        signals,
+        queued,
+    }
+}
+
+/// Options for [WasmProcessSpawner::spawn_inner].
+#[derive(Default)]
+struct SpawnOptions {
+    /// The entrypoint to run, like [WasmSpawnInfo::entrypoint].
+    entrypoint: Option<u32>,
+
+    /// If set, the child is also passed a capability to signal readiness on,
+    /// and is killed if it doesn't within this long.
+    ready_timeout: Option<Duration>,
+
+    /// If set, the child is killed once it consumes this much fuel.
+    fuel_limit: Option<u64>,
+
+    /// The child's scheduling priority, capped to [MAX_PRIORITY].
+    priority: u32,
+}
+
 /// The native WebAssembly process spawner. Accepts WasmSpawnInfo.
-#[derive(GetProcessMetadata)]
+#[derive(Clone, GetProcessMetadata)]
 pub struct WasmProcessSpawner {
     engine: Arc<Engine>,
     linker: Arc<Linker<ProcessData>>,
+    controls: Arc<ProcessControls>,
+    config: ProcessConfig,
 }
 
+// This cannot be a [RequestResponseProcess] because spawns that wait for the
+// child to signal readiness reply asynchronously.
 #[async_trait]
-impl RequestResponseProcess for WasmProcessSpawner {
-    type Request = WasmSpawnInfo;
-    type Response = ();
+impl SinkProcess for WasmProcessSpawner {
+    type Message = WasmSpawnInfo;
 
-    async fn on_request<'a>(
-        &'a mut self,
-        request: &mut RequestInfo<'a, WasmSpawnInfo>,
-    ) -> ResponseInfo<'a, Self::Response> {
-        ResponseInfo {
-            data: (),
-            caps: match self.spawn(request).await {
-                // spawned successfully; return cap
-                Ok(child) => vec![child],
-                // error occurred. log and no cap
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, WasmSpawnInfo>) {
+        let Some(reply) = message.caps.first() else {
+            debug!("Request to {:?} has no reply address", message.label);
+            return;
+        };
+
+        let mut request = RequestInfo {
+            label: message.label,
+            process: message.process,
+            reply: reply.clone(),
+            cap_args: &message.caps[1..],
+            runtime: message.runtime,
+            data: message.data,
+        };
+
+        let (data, caps) = match self.spawn(&mut request).await {
+            // spawned successfully; return cap
+            Ok((child, None)) => (Ok(()), vec![child]),
+            // wait for readiness without blocking other spawn requests
+            Ok((_child, Some(pending))) => {
+                pending.reply_when_ready(reply.to_owned());
+                return;
+            }
+            // error occurred. log and reply with the error
+            Err(err) => {
+                error!("Wasm spawning error: {:?}", err);
+                (Err(err), vec![])
+            }
+        };
+
+        send_spawn_response(reply, data, &caps).await;
+    }
+}
+
+/// Sends a [WasmSpawnResponse] to a spawn request's reply capability.
+async fn send_spawn_response(
+    reply: &CapabilityRef<'_>,
+    data: WasmSpawnResponse,
+    caps: &[CapabilityRef<'_>],
+) {
+    let data = serde_json::to_vec(&data).unwrap();
+    let caps: Vec<_> = caps.iter().collect();
+
+    if let Err(err) = reply.send(&data, &caps).await {
+        debug!("Wasm spawner reply error: {:?}", err);
+    }
+}
+
+/// A spawned process that has yet to signal that it's ready.
+///
+/// See [WasmSpawnInfo::ready_timeout].
+struct PendingReady {
+    /// A process whose parent mailbox receives the child's readiness signal.
+    waiter: Process,
+
+    /// A capability to the child.
+    child: OwnedCapability,
+
+    /// The child's execution task, which finishes when the child exits.
+    run: JoinHandle<()>,
+
+    /// How long to wait for the child to signal readiness.
+    timeout: Duration,
+}
+
+impl PendingReady {
+    /// Spawns a task that waits for the child to signal readiness and then
+    /// replies to its spawn request.
+    ///
+    /// The child is killed if it doesn't signal readiness in time.
+    fn reply_when_ready(self, reply: OwnedCapability) {
+        tokio::spawn(async move {
+            let Self {
+                waiter,
+                child,
+                mut run,
+                timeout,
+            } = self;
+
+            let table = waiter.borrow_table();
+            let reply = table
+                .wrap_handle(table.import_owned(reply).unwrap())
+                .unwrap();
+            let child = table
+                .wrap_handle(table.import_owned(child).unwrap())
+                .unwrap();
+
+            let ready = waiter
+                .borrow_parent()
+                .recv(|signal| matches!(signal, TableSignal::Message { .. }));
+
+            // prefer a readiness signal that arrived alongside the child's
+            // exit or the timeout
+            let result = tokio::select! {
+                biased;
+                ready = ready => match ready {
+                    Some(true) => Ok(()),
+                    _ => Err("spawner was killed while waiting for readiness".to_string()),
+                },
+                _ = &mut run => Err("process exited before signaling readiness".to_string()),
+                _ = tokio::time::sleep(timeout) => {
+                    let _ = child.kill();
+                    Err(format!("process did not signal readiness within {timeout:?}"))
+                }
+            };
+
+            match result {
+                Ok(()) => send_spawn_response(&reply, Ok(()), &[child]).await,
                 Err(err) => {
+                    let err = WasmSpawnError::SpawnFailed(err);
                     error!("Wasm spawning error: {:?}", err);
-                    vec![]
+                    send_spawn_response(&reply, Err(err), &[]).await;
                 }
-            },
-        }
+            }
+        });
     }
 }
 
@@ -989,28 +2312,120 @@ impl ServiceRunner for WasmProcessSpawner {
 }
 
 impl WasmProcessSpawner {
-    pub async fn spawn<'a>(
+    /// Lists every import of a module that this spawner's linker does not
+    /// provide, formatted as `module::name`.
+    ///
+    /// wasmtime's instantiation error only describes the first unsatisfied
+    /// import in its own terms, so this lists all of the missing imports at
+    /// once. Missing imports usually mean that the guest and host ABIs have
+    /// drifted apart.
+    pub fn missing_imports(&self, module: &Module) -> Vec<String> {
+        let data = ProcessData::new_metadata(StoreLimits::default());
+        let mut store = Store::new(&self.engine, data);
+
+        module
+            .imports()
+            .filter(|import| self.linker.get_by_import(&mut store, import).is_none())
+            .map(|import| format!("{}::{}", import.module(), import.name()))
+            .collect()
+    }
+
+    /// Spawns a Wasm process for a spawn request and returns a capability to
+    /// it.
+    ///
+    /// If the request has a [WasmSpawnInfo::ready_timeout], the process has
+    /// not necessarily signaled readiness yet, and a [PendingReady] is also
+    /// returned to wait for it.
+    async fn spawn<'a>(
         &'a mut self,
         request: &mut RequestInfo<'a, WasmSpawnInfo>,
-    ) -> Result<CapabilityRef<'a>> {
+    ) -> Result<(CapabilityRef<'a>, Option<PendingReady>), WasmSpawnError> {
+        let WasmSpawnInfo {
+            lump,
+            entrypoint,
+            ready_timeout,
+            fuel_limit,
+            priority,
+        } = request.data.clone();
+
+        let ready_timeout = match ready_timeout {
+            Some(timeout) if !(timeout.is_finite() && timeout >= 0.0) => {
+                let err = format!("invalid ready timeout {timeout}");
+                return Err(WasmSpawnError::SpawnFailed(err));
+            }
+            timeout => timeout.map(Duration::from_secs_f32),
+        };
+
+        let options = SpawnOptions {
+            entrypoint,
+            ready_timeout,
+            fuel_limit,
+            priority,
+        };
+
+        self.spawn_inner(request, lump, options).await
+    }
+
+    /// Spawns a Wasm process on behalf of a request, passing the request's
+    /// capability arguments to the child.
+    ///
+    /// Returns a [PendingReady] if the child has been asked to signal
+    /// readiness.
+    async fn spawn_inner<'a, T>(
+        &self,
+        request: &RequestInfo<'a, T>,
+        lump: LumpId,
+        options: SpawnOptions,
+    ) -> Result<(CapabilityRef<'a>, Option<PendingReady>), WasmSpawnError> {
+        use WasmSpawnError::*;
+
+        let SpawnOptions {
+            entrypoint,
+            ready_timeout,
+            fuel_limit,
+            priority,
+        } = options;
+
         // load the WebAssembly module from the asset store
         let module = request
             .runtime
             .asset_store
-            .load_asset::<WasmModuleLoader>(&request.data.lump)
+            .load_asset::<WasmModuleLoader>(&lump)
             .await
-            .context("loading Wasm module")?;
+            .map_err(|err| LoadFailed(format!("{:#}", err)))?;
 
         // instantiate a new WasmProcess
-        let mut process = WasmProcess::new(&self.engine, &self.linker, &module, request.data.lump)
-            .await
-            .context("initializing process")?;
+        let process = WasmProcess::new(
+            &self.engine,
+            &self.linker,
+            &module,
+            lump,
+            self.config,
+            fuel_limit,
+        )
+        .await;
+
+        // on failure, check if the module is incompatible with our linker
+        let mut process = match process {
+            Ok(process) => process,
+            Err(err) => {
+                let missing = self.missing_imports(&module);
+                if !missing.is_empty() {
+                    return Err(MissingImports(missing));
+                }
+
+                return Err(InstantiateFailed(format!("{:#}", err)));
+            }
+        };
 
         // retrieve the process's metadata
-        let meta = process
+        let mut meta = process
             .get_metadata()
             .await
-            .context("retrieving process metadata")?;
+            .map_err(|err| MetadataFailed(format!("{:#}", err)))?;
+
+        // the spawner decides the priority, not the process itself
+        meta.priority = Some(priority.min(MAX_PRIORITY));
 
         // spawn a new local process
         let child = request.runtime.process_factory.spawn(meta);
@@ -1021,24 +2436,103 @@ impl WasmProcessSpawner {
             .export_to(Permissions::all(), request.process.borrow_table())
             .unwrap();
 
+        // create a process for the child to signal readiness to, if
+        // requested, so that the wait can outlive this request
+        let waiter = ready_timeout.map(|_| {
+            let mut meta = cargo_process_metadata!();
+            meta.name = Some("Wasm readiness waiter".to_string());
+            request.runtime.process_factory.spawn(meta)
+        });
+
+        let ready_cap = waiter.as_ref().map(|waiter| {
+            waiter
+                .borrow_parent()
+                .export_to(Permissions::SEND, request.process.borrow_table())
+                .unwrap()
+        });
+
         // send the child the initial capabilities from the request
-        child_cap
-            .send(&[], request.cap_args.iter().collect::<Vec<_>>().as_slice())
-            .await
-            .unwrap();
+        let mut init_caps: Vec<_> = request.cap_args.iter().collect();
+        init_caps.extend(ready_cap.as_ref());
+
+        child_cap.send(&[], &init_caps).await.unwrap();
 
         // flush the child's mailbox to import the initial capabilities
         child.borrow_parent().recv(|_| ()).await.unwrap();
 
+        // make the process controllable while it runs
+        let controls = self.controls.clone();
+        let key = controls.insert(child_cap.clone(), process.control.clone());
+
         // run the process
         let runtime = request.runtime.clone();
-        tokio::spawn(process.run(runtime, child, request.data.entrypoint));
+        let run = tokio::spawn(async move {
+            process
+                .run(runtime, child, entrypoint, controls.clone())
+                .await;
+            controls.remove(key);
+        });
+
+        let pending = waiter
+            .zip(ready_timeout)
+            .map(|(waiter, timeout)| PendingReady {
+                waiter,
+                child: child_cap.to_owned(),
+                run,
+                timeout,
+            });
 
         // return the child's cap
-        Ok(child_cap)
+        Ok((child_cap, pending))
+    }
+}
+
+/// The native Wasm process controller. Accepts [WasmControlRequest].
+///
+/// Suspends, resumes, and snapshots Wasm processes given capabilities to them
+/// with the kill permission.
+#[derive(GetProcessMetadata)]
+pub struct WasmProcessController {
+    spawner: WasmProcessSpawner,
+}
+
+#[async_trait]
+impl RequestResponseProcess for WasmProcessController {
+    type Request = WasmControlRequest;
+    type Response = WasmControlResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, Self::Request>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        let control = request
+            .cap_args
+            .first()
+            .and_then(|cap| self.spawner.controls.get(cap.clone()));
+
+        let Some(control) = control else {
+            return WasmControlError::NoSuchProcess.into();
+        };
+
+        match &request.data {
+            WasmControlRequest::Suspend => control.suspend(),
+            WasmControlRequest::Resume => control.resume(),
+            WasmControlRequest::Snapshot => {
+                return match control.get_snapshot() {
+                    Some(snapshot) => Ok(WasmControlSuccess::Snapshot(snapshot)).into(),
+                    None => WasmControlError::NotSuspended.into(),
+                }
+            }
+        }
+
+        Ok(WasmControlSuccess::Ok).into()
     }
 }
 
+impl ServiceRunner for WasmProcessController {
+    const NAME: &'static str = "hearth.wasm.WasmProcessController";
+}
+
 pub struct WasmModuleLoader {
     engine: Arc<Engine>,
 }
@@ -1054,21 +2548,79 @@ impl AssetLoader for WasmModuleLoader {
 
 pub struct WasmPlugin {
     engine: Arc<Engine>,
+    timeslice: Duration,
+    config: ProcessConfig,
 }
 
 impl Default for WasmPlugin {
     fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl WasmPlugin {
+    fn new(consume_fuel: bool) -> Self {
         let mut config = Config::new();
         config.async_support(true);
         config.epoch_interruption(true);
         config.memory_init_cow(true);
+        config.consume_fuel(consume_fuel);
 
         let engine = Engine::new(&config).unwrap();
 
         Self {
             engine: Arc::new(engine),
+            timeslice: Duration::from_micros(100),
+            config: ProcessConfig::default(),
         }
     }
+
+    /// Creates a Wasm plugin that meters the execution of its processes
+    /// with fuel.
+    ///
+    /// Processes yield every `fuel_slice` units of fuel in addition to every
+    /// epoch. Unlike epochs, fuel counts the instructions that a process
+    /// actually executes, so processes spawned with a
+    /// [WasmSpawnInfo::fuel_limit] are killed deterministically once they
+    /// exceed it. Fuel metering makes all processes run slower.
+    pub fn fuel_metered(fuel_slice: u64) -> Self {
+        let mut plugin = Self::new(true);
+        plugin.config.fuel_slice = Some(fuel_slice.max(1));
+        plugin
+    }
+
+    /// Limits the memory and table sizes of every process spawned by this
+    /// plugin.
+    ///
+    /// By default, processes are unlimited and may allocate until the host
+    /// runs out of memory.
+    pub fn with_limits(mut self, max_memory_bytes: usize, max_table_elements: u32) -> Self {
+        self.config.limits = Some(ProcessLimits {
+            max_memory_bytes,
+            max_table_elements,
+        });
+
+        self
+    }
+
+    /// Sets the duration of a single epoch. Defaults to 100 microseconds.
+    ///
+    /// Longer timeslices improve the throughput of compute-heavy processes at
+    /// the cost of scheduling latency.
+    pub fn with_timeslice(mut self, timeslice: Duration) -> Self {
+        self.timeslice = timeslice;
+        self
+    }
+
+    /// Sets the number of epochs that a process may run for before yielding
+    /// to other tasks. Defaults to 1.
+    ///
+    /// Processes are still killed and suspended from their epoch callbacks,
+    /// so larger values also delay how quickly those take effect.
+    pub fn with_yield_epochs(mut self, epochs: u64) -> Self {
+        self.config.yield_epochs = epochs.max(1);
+        self
+    }
 }
 
 impl Plugin for WasmPlugin {
@@ -1076,11 +2628,19 @@ impl Plugin for WasmPlugin {
         let mut linker = Linker::new(&self.engine);
         ProcessData::add_to_linker(&mut linker);
 
-        builder.add_plugin(WasmProcessSpawner {
+        let spawner = WasmProcessSpawner {
             engine: self.engine.to_owned(),
             linker: Arc::new(linker),
+            controls: Arc::new(ProcessControls::new(builder.get_post())),
+            config: self.config,
+        };
+
+        builder.add_plugin(WasmProcessController {
+            spawner: spawner.clone(),
         });
 
+        builder.add_plugin(spawner);
+
         builder.add_asset_loader(WasmModuleLoader {
             engine: self.engine.to_owned(),
         });
@@ -1088,10 +2648,8 @@ impl Plugin for WasmPlugin {
 
     fn finalize(self, _builder: &mut RuntimeBuilder) {
         tokio::spawn(async move {
-            // TODO make this time slice duration configurable
-            let duration = std::time::Duration::from_micros(100);
             loop {
-                tokio::time::sleep(duration).await;
+                tokio::time::sleep(self.timeslice).await;
                 self.engine.increment_epoch();
             }
         });
@@ -1102,6 +2660,8 @@ impl Plugin for WasmPlugin {
 mod tests {
     use super::*;
 
+    use hearth_runtime::process::ProcessFactory;
+
     #[test]
     fn link() {
         let mut config = Config::new();
@@ -1110,4 +2670,86 @@ mod tests {
         let mut linker = Linker::new(&engine);
         ProcessData::add_to_linker(&mut linker);
     }
+
+    #[test]
+    fn get_slice_overflow() {
+        let mut bytes = vec![0u8; 16];
+        let memory = GuestMemory { bytes: &mut bytes };
+        assert!(memory.get_slice(u32::MAX, u32::MAX).is_err());
+        assert!(memory.get_slice(8, u32::MAX).is_err());
+        assert!(memory.get_slice(8, 8).is_ok());
+    }
+
+    #[test]
+    fn process_controls_by_capability() {
+        let post = PostOffice::new();
+        let factory = ProcessFactory::new(post.clone());
+        let child = factory.spawn(ProcessMetadata::default());
+        let other = factory.spawn(ProcessMetadata::default());
+        let controls = ProcessControls::new(post);
+        let control = Arc::new(ProcessControl::default());
+
+        let cap = child.borrow_parent().export(Permissions::all()).unwrap();
+        let key = controls.insert(cap, control.clone());
+
+        let kill = child
+            .borrow_parent()
+            .export_to(Permissions::KILL, other.borrow_table())
+            .unwrap();
+        let found = controls.get(kill).unwrap();
+        assert!(Arc::ptr_eq(&found, &control));
+
+        let send = child
+            .borrow_parent()
+            .export_to(Permissions::SEND, other.borrow_table())
+            .unwrap();
+        assert!(controls.get(send).is_none());
+
+        let unrelated = other.borrow_parent().export(Permissions::KILL).unwrap();
+        assert!(controls.get(unrelated).is_none());
+
+        controls.remove(key);
+        let kill = child.borrow_parent().export(Permissions::KILL).unwrap();
+        assert!(controls.get(kill).is_none());
+    }
+
+    #[tokio::test]
+    async fn shutdown_requests_are_taken_once() {
+        let control = ProcessControl::default();
+        assert!(!control.take_shutdown());
+
+        control.request_shutdown();
+        control.wait_shutdown().await;
+        assert!(!control.take_shutdown());
+
+        control.request_shutdown();
+        assert!(control.take_shutdown());
+        assert!(!control.take_shutdown());
+    }
+
+    #[tokio::test]
+    async fn unload_lump_in_use() {
+        let store = Arc::new(LumpStoreImpl::new());
+        let this_lump = store.add_lump(vec![0u8; 4].into()).await;
+        let data: Bytes = vec![1u8; 4].into();
+        let id = store.add_lump(data.clone()).await;
+        let mut abi = LumpAbi::with_store(store.clone(), this_lump);
+
+        async fn unload(abi: &LumpAbi, id: LumpId) -> u32 {
+            let mut bytes = id.0.to_vec();
+            let memory = GuestMemory { bytes: &mut bytes };
+            abi.unload_store(memory, 0).await.unwrap()
+        }
+
+        assert_eq!(unload(&abi, this_lump).await, 0);
+
+        let handle = abi.insert_lump(id, data);
+        assert_eq!(unload(&abi, id).await, 0);
+        abi.free(handle).unwrap();
+        assert_eq!(unload(&abi, id).await, 1);
+        assert!(!store.has_lump(&id).await);
+
+        drop(abi);
+        assert!(store.remove_lump(&this_lump).await);
+    }
 }